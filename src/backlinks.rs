@@ -0,0 +1,124 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use pulldown_cmark::escape::{escape_href, escape_html};
+use pulldown_cmark::{Event, Options, Parser, Tag};
+
+use crate::frontmatter;
+use crate::site::Page;
+use crate::urls::{normalize, percent_decode};
+use crate::wikilink::{self, NoteIndex};
+
+/// A page linking to the one being rendered.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Backlink {
+    /// The linking page's frontmatter title, or its file name without one.
+    pub title: String,
+    /// Where the linking page is rendered, relative to the site root.
+    pub output: PathBuf,
+}
+
+/// The link graph of a site turned inside out: for every page, the pages
+/// that link to it through a wikilink or a relative link to its markdown.
+#[derive(Debug, Default)]
+pub struct Backlinks {
+    linked_from: HashMap<PathBuf, BTreeSet<Backlink>>,
+}
+
+impl Backlinks {
+    /// Collects the links in every page, where `sources[i]` is the markdown
+    /// of `pages[i]`.
+    pub fn new(pages: &[Page], sources: &[String], notes: &NoteIndex) -> Backlinks {
+        let outputs: BTreeSet<PathBuf> = pages.iter().map(Page::output_path).collect();
+        let mut linked_from: HashMap<PathBuf, BTreeSet<Backlink>> = HashMap::new();
+
+        for (page, markdown) in pages.iter().zip(sources) {
+            let (title, content) = match frontmatter::split_frontmatter(markdown) {
+                Ok((frontmatter, content)) => (frontmatter.title, content),
+                // The page's own render reports the error.
+                Err(_) => (None, markdown.as_str()),
+            };
+            let title = title.unwrap_or_else(|| page_name(page));
+            let source = Backlink { title, output: page.output_path() };
+
+            for target in outgoing_links(page, content, notes) {
+                if target != source.output && outputs.contains(&target) {
+                    linked_from.entry(target).or_default().insert(source.clone());
+                }
+            }
+        }
+
+        return Backlinks { linked_from };
+    }
+
+    /// The pages linking to `page`, ordered by title.
+    pub fn to(&self, page: &Page) -> Vec<&Backlink> {
+        match self.linked_from.get(&page.output_path()) {
+            Some(links) => links.iter().collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Renders the "Linked from" section appended to a page, or nothing if no
+/// page links to it. `root` is the path from the page back to the site root.
+pub fn backlinks_html(links: &[&Backlink], root: &str) -> String {
+    if links.is_empty() {
+        return String::new();
+    }
+
+    let mut html = String::from("<section class=\"backlinks\">\n<h2>Linked from</h2>\n<ul>\n");
+    for link in links {
+        html.push_str("<li><a href=\"");
+        let _ = escape_href(&mut html, root);
+        let _ = escape_href(&mut html, &link.output.to_string_lossy());
+        html.push_str("\">");
+        let _ = escape_html(&mut html, &link.title);
+        html.push_str("</a></li>\n");
+    }
+    html.push_str("</ul>\n</section>\n");
+
+    return html;
+}
+
+fn page_name(page: &Page) -> String {
+    page.relative.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// The output paths, relative to the site root, of every page `content`
+/// links to. Links inside code are ignored.
+pub(crate) fn outgoing_links(page: &Page, content: &str, notes: &NoteIndex) -> Vec<PathBuf> {
+    let directory = page.relative.parent().unwrap_or_else(|| Path::new(""));
+    let mut links = Vec::new();
+    let mut text = String::new();
+    let mut in_code = false;
+
+    for event in Parser::new_ext(content, Options::ENABLE_FOOTNOTES) {
+        match event {
+            Event::Start(Tag::Link(_, destination, _)) => {
+                let path = percent_decode(destination.split('#').next().unwrap_or(""));
+                if path.ends_with(".md") && !path.contains("://") {
+                    // Links climbing out of the site lead to no page.
+                    if let Some(target) = normalize(&directory.join(&path)) {
+                        links.push(target.with_extension("html"));
+                    }
+                }
+            }
+            Event::Start(Tag::CodeBlock(_)) => in_code = true,
+            Event::End(Tag::CodeBlock(_)) => in_code = false,
+            Event::Text(t) if !in_code => text.push_str(&t),
+            Event::End(_) => text.push('\n'),
+            _ => {}
+        }
+    }
+
+    for target in wikilink::targets(&text) {
+        if let Some(output) = notes.resolve(target) {
+            links.push(output.to_path_buf());
+        }
+    }
+
+    return links;
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use pulldown_cmark::escape::{escape_href, escape_html};
+use pulldown_cmark::{Event, Parser, Tag};
+use regex::{Captures, Regex};
+
+use crate::slug::slugify;
+use crate::urls::{normalize, percent_decode, unescape};
+use crate::wikilink::{self, NoteIndex};
+
+lazy_static::lazy_static! {
+    static ref SCHEME: Regex = Regex::new(r"^[A-Za-z][A-Za-z0-9+.-]*:").unwrap();
+    static ref ANCHOR: Regex = Regex::new(r#"(\s)(id|for|href)="([^"]*)""#).unwrap();
+    static ref FOOTNOTE_NUMBER: Regex = Regex::new(r#"(<sup class="fn"><a [^>]*>)\[(\d+)\]"#).unwrap();
+}
+
+/// How the list of footnotes at the end of a chapter starts.
+const FOOTNOTES_START: &str = "<hr />\n<ol>\n";
+
+/// A chapter of the book and how deep in the summary's lists it sits.
+pub struct Chapter {
+    /// The note's path relative to the source directory.
+    pub relative: PathBuf,
+    pub depth: usize,
+}
+
+/// The chapters the summary at `relative`, within the source directory,
+/// links to in reading order, each listed once. Links are relative to the
+/// summary; wikilinks are resolved against `notes`.
+pub fn parse_summary(markdown: &str, relative: &Path, notes: &NoteIndex) -> Vec<Chapter> {
+    let directory = relative.parent().unwrap_or(Path::new(""));
+    let mut chapters: Vec<Chapter> = Vec::new();
+    let mut depth: usize = 0;
+    // Wikilinks can be split across text events, so text is gathered up
+    // until something else comes along.
+    let mut text = String::new();
+    for event in Parser::new(markdown) {
+        if let Event::Text(part) = &event {
+            text.push_str(part);
+            continue;
+        }
+
+        let mut targets: Vec<PathBuf> = wikilink::targets(&text)
+            .filter_map(|target| notes.resolve(target))
+            .filter_map(|output| notes.source(output))
+            .map(Path::to_path_buf)
+            .collect();
+        text.clear();
+        match event {
+            Event::Start(Tag::List(_)) => depth += 1,
+            Event::End(Tag::List(_)) => depth = depth.saturating_sub(1),
+            Event::Start(Tag::Link(_, destination, _)) => {
+                let destination = percent_decode(&destination);
+                let path = destination.split('#').next().unwrap_or("");
+                if !SCHEME.is_match(path) && !path.is_empty() && !path.starts_with('/') {
+                    if let Some(path) = normalize(&directory.join(path)) {
+                        // Links may name a note by its output as well.
+                        let path = notes.source(&path).map_or(path.clone(), Path::to_path_buf);
+                        targets.push(path);
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        for relative in targets {
+            if !chapters.iter().any(|chapter| chapter.relative == relative) {
+                // Chapters sit in a list, so the outermost one is depth 0.
+                chapters.push(Chapter { relative, depth: depth.saturating_sub(1) });
+            }
+        }
+    }
+
+    return chapters;
+}
+
+/// The id of the section a chapter sits in on the book page.
+pub fn chapter_id(relative: &Path) -> String {
+    slugify(&relative.with_extension("").to_string_lossy())
+}
+
+/// Rewrites `html`, a chapter rendered for the book page, so its ids are
+/// prefixed with the chapter's `id` and can't clash with other chapters'.
+/// Links within the chapter follow them, and links to other chapters, by
+/// output path in `chapters`, go to their sections.
+pub fn anchor_chapter(html: &str, id: &str, chapters: &HashMap<PathBuf, String>) -> String {
+    let anchored = ANCHOR.replace_all(html, |captures: &Captures| {
+        let value = unescape(&captures[3]);
+        let mut attribute = format!("{}{}=\"", &captures[1], &captures[2]);
+        if &captures[2] != "href" {
+            let _ = escape_html(&mut attribute, &format!("{}-{}", id, value));
+            attribute.push('"');
+            return attribute;
+        }
+
+        let (path, fragment) = value.split_once('#').unwrap_or((&value, ""));
+        let chapter = match path {
+            "" => id,
+            path => match chapters.get(Path::new(&percent_decode(path))) {
+                Some(chapter) => chapter,
+                None => return captures[0].to_string(),
+            },
+        };
+        let anchor = match fragment {
+            "" => format!("#{}", chapter),
+            fragment => format!("#{}-{}", chapter, percent_decode(fragment)),
+        };
+        let _ = escape_href(&mut attribute, &anchor);
+        attribute.push('"');
+        attribute
+    });
+
+    return anchored.into_owned();
+}
+
+/// Numbers the footnotes in `html` on from the `offset` in the chapters
+/// before it, returning the chapter and how many footnotes it has.
+pub fn renumber_footnotes(html: &str, offset: usize) -> (String, usize) {
+    let mut count = 0;
+    let renumbered = FOOTNOTE_NUMBER.replace_all(html, |captures: &Captures| {
+        let number: usize = captures[2].parse().unwrap_or(0);
+        count = count.max(number);
+        format!("{}[{}]", &captures[1], number + offset)
+    });
+    let mut renumbered = renumbered.into_owned();
+    if offset > 0 {
+        if let Some(idx) = renumbered.rfind(FOOTNOTES_START) {
+            let start = format!("<hr />\n<ol start=\"{}\">\n", offset + 1);
+            renumbered.replace_range(idx..(idx + FOOTNOTES_START.len()), &start);
+        }
+    }
+
+    return (renumbered, count);
+}
+
+/// The book's table of contents, linking each chapter's `(title, id,
+/// depth)` in lists nested by depth.
+pub fn toc_html(chapters: &[(String, String, usize)]) -> String {
+    let mut html = String::from("<nav class=\"book-toc\">\n<ol>\n");
+    let mut depth = 0;
+    for (idx, (title, id, chapter_depth)) in chapters.iter().enumerate() {
+        // A chapter can only go one list deeper than the one before it.
+        let chapter_depth = if idx == 0 { 0 } else { (*chapter_depth).min(depth + 1) };
+        if idx > 0 && chapter_depth > depth {
+            html.push_str("\n<ol>\n");
+        } else if idx > 0 {
+            html.push_str("</li>\n");
+            for _ in chapter_depth..depth {
+                html.push_str("</ol>\n</li>\n");
+            }
+        }
+        depth = chapter_depth;
+
+        html.push_str("<li><a href=\"");
+        let _ = escape_href(&mut html, &format!("#{}", id));
+        html.push_str("\">");
+        let _ = escape_html(&mut html, title);
+        html.push_str("</a>");
+    }
+    if !chapters.is_empty() {
+        html.push_str("</li>\n");
+    }
+    for _ in 0..depth {
+        html.push_str("</ol>\n</li>\n");
+    }
+    html.push_str("</ol>\n</nav>\n");
+
+    return html;
+}
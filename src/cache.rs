@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::{OrbitError, Result};
+
+pub const CACHE_FILENAME: &str = ".orbit-cache.json";
+
+/// The build manifest kept in the destination directory, recording what each
+/// source file looked like the last time it was written out.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct BuildCache {
+    /// Hash of everything that affects every page: the template, the
+    /// stylesheet, and the configuration. A change here invalidates all pages.
+    #[serde(default)]
+    site_hash: String,
+    #[serde(default)]
+    entries: BTreeMap<PathBuf, CacheEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CacheEntry {
+    pub hash: String,
+    pub output: PathBuf,
+    /// Other files written for the source, such as its resized images and
+    /// Orbit attachments, relative to the destination directory.
+    #[serde(default)]
+    pub extra: Vec<PathBuf>,
+}
+
+impl BuildCache {
+    pub fn new(site_hash: String) -> BuildCache {
+        BuildCache { site_hash, entries: BTreeMap::new() }
+    }
+
+    /// Loads the manifest from `destination`. A missing or unreadable manifest
+    /// is treated as empty, which simply means everything gets rebuilt.
+    pub fn load(destination: &Path) -> BuildCache {
+        std::fs::read_to_string(destination.join(CACHE_FILENAME))
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, destination: &Path) -> Result<()> {
+        let contents = serde_json::to_string_pretty(self)?;
+        let path = destination.join(CACHE_FILENAME);
+        std::fs::write(&path, contents).map_err(OrbitError::io(&path))?;
+
+        return Ok(());
+    }
+
+    pub fn site_hash(&self) -> &str {
+        &self.site_hash
+    }
+
+    /// Whether the output for `source` was built from content hashing to
+    /// `hash` and is still present under `destination`.
+    pub fn is_fresh(&self, source: &Path, hash: &str, destination: &Path) -> bool {
+        match self.entries.get(source) {
+            Some(entry) => entry.hash == hash && destination.join(&entry.output).exists(),
+            None => false,
+        }
+    }
+
+    pub fn get(&self, source: &Path) -> Option<&CacheEntry> {
+        self.entries.get(source)
+    }
+
+    pub fn insert(&mut self, source: PathBuf, entry: CacheEntry) {
+        self.entries.insert(source, entry);
+    }
+
+    /// Every file written for the sources in the manifest, relative to the
+    /// destination directory.
+    pub fn outputs(&self) -> impl Iterator<Item = &Path> {
+        self.entries.values()
+            .flat_map(|entry| std::iter::once(&entry.output).chain(&entry.extra))
+            .map(PathBuf::as_path)
+    }
+}
+
+pub fn hash_bytes(bytes: &[u8]) -> String {
+    let digest = Sha256::digest(bytes);
+
+    return digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+}
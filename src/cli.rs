@@ -0,0 +1,311 @@
+use std::path::PathBuf;
+
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use tracing::level_filters::LevelFilter;
+
+use orbit_rs::config::{self, Config, Profile};
+
+#[derive(Parser, Debug)]
+#[command(name = "orbit", version, about = "Render a directory of markdown notes to a Tufte-styled site")]
+pub struct Cli {
+    /// Path to the site configuration file.
+    #[arg(short, long, global = true, default_value = config::CONFIG_FILENAME)]
+    pub config: PathBuf,
+
+    /// Print only warnings and errors.
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Also print timings and why pages are skipped; twice to print every
+    /// file copied too.
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Render the source directory into the destination directory.
+    Build(BuildArgs),
+    /// Scaffold a new site with the bundled template and stylesheet.
+    Init(InitArgs),
+    /// Remove the generated output.
+    Clean(CleanArgs),
+    /// Build the site, serve it locally, and reload pages as sources change.
+    Serve(ServeArgs),
+    /// Render a single markdown file, or stdin, to stdout.
+    Render(RenderArgs),
+    /// Write every Orbit card in the source directory to a deck file.
+    ExportDeck(ExportDeckArgs),
+    /// Gather the notes in the source directory into a book.
+    Export(ExportArgs),
+    /// Check every Orbit card in the source directory for common mistakes.
+    Lint(LintArgs),
+    /// Build the site and publish it to the target in `[deploy]`.
+    Deploy(DeployArgs),
+}
+
+#[derive(Args, Debug, Default)]
+pub struct SiteArgs {
+    /// Directory of markdown notes. Overrides `source` in the config.
+    pub source: Option<PathBuf>,
+    /// Directory to write HTML into. Overrides `destination` in the config.
+    pub destination: Option<PathBuf>,
+    /// Handlebars page template to use instead of the bundled one. Overrides
+    /// `template` in the config.
+    #[arg(long)]
+    pub template: Option<PathBuf>,
+    /// Build pages marked as drafts too.
+    #[arg(long)]
+    pub drafts: bool,
+    /// Strip comments and insignificant whitespace from rendered pages.
+    #[arg(long)]
+    pub minify: bool,
+    /// What to build the site for. Overrides `profile` in the config.
+    #[arg(long, value_enum)]
+    pub profile: Option<BuildProfile>,
+    /// Also print every page built to a PDF with a headless Chrome or
+    /// Chromium. Implies `--profile print`.
+    #[arg(long)]
+    pub pdf: bool,
+}
+
+#[derive(Args, Debug, Default)]
+pub struct BuildArgs {
+    #[command(flatten)]
+    pub site: SiteArgs,
+    /// Keep running and rebuild pages whenever their sources change.
+    #[arg(short, long)]
+    pub watch: bool,
+    /// Rebuild every page, ignoring the build manifest.
+    #[arg(short, long)]
+    pub force: bool,
+    /// Render everything but only list the files that would be written,
+    /// leaving the destination alone.
+    #[arg(long, conflicts_with = "watch")]
+    pub dry_run: bool,
+    /// Render only the markdown file given as the source, or stdin if it's
+    /// `-`, writing its page to this file, or to stdout if `-`, rather than
+    /// building the site.
+    #[arg(short, long, conflicts_with_all = ["destination", "watch", "dry_run", "clean_dest", "drafts", "profile", "pdf"])]
+    pub output: Option<PathBuf>,
+    /// With `--output`, write only the rendered body, without the page
+    /// template around it.
+    #[arg(long, requires = "output")]
+    pub fragment: bool,
+    /// Delete files in the destination that no longer come from any source,
+    /// such as the pages of renamed or removed notes.
+    #[arg(long)]
+    pub clean_dest: bool,
+    /// Fail the build on any warning, such as a bad footnote, invalid
+    /// frontmatter, or missing attachment, or any broken internal link.
+    #[arg(long)]
+    pub strict: bool,
+    /// How to print warnings and errors: for people to read, or as one JSON
+    /// object per line for editors and CI.
+    #[arg(long, value_enum, default_value_t = MessageFormat::Human)]
+    pub message_format: MessageFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct InitArgs {
+    /// Directory to create the site in.
+    #[arg(default_value = ".")]
+    pub directory: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    #[command(flatten)]
+    pub site: SiteArgs,
+    /// Port to listen on.
+    #[arg(short, long, default_value_t = 8000)]
+    pub port: u16,
+}
+
+#[derive(Args, Debug)]
+pub struct RenderArgs {
+    /// Markdown file to render, or `-` to read it from stdin.
+    pub input: PathBuf,
+    /// File to write the page to, or `-` for stdout.
+    #[arg(short, long, default_value = "-")]
+    pub output: PathBuf,
+    /// Write only the rendered body, without the page template around it.
+    #[arg(long)]
+    pub fragment: bool,
+    /// Handlebars page template to use instead of the bundled one. Overrides
+    /// `template` in the config.
+    #[arg(long)]
+    pub template: Option<PathBuf>,
+    /// Strip comments and insignificant whitespace from the page.
+    #[arg(long)]
+    pub minify: bool,
+    /// Fail on any warning, such as a bad footnote or invalid frontmatter.
+    #[arg(long)]
+    pub strict: bool,
+    /// How to print warnings and errors.
+    #[arg(long, value_enum, default_value_t = MessageFormat::Human)]
+    pub message_format: MessageFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct CleanArgs {
+    /// Directory to remove. Overrides `destination` in the config.
+    pub destination: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct ExportDeckArgs {
+    /// Directory of markdown notes. Overrides `source` in the config.
+    pub source: Option<PathBuf>,
+    /// Kind of deck file to write.
+    #[arg(long, value_enum, default_value_t = DeckFormat::AnkiCsv)]
+    pub format: DeckFormat,
+    /// File to write the deck to, `deck.csv` or `deck.apkg` by default.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    /// Directory of markdown notes, the whole site or one directory of it.
+    /// Overrides `source` in the config.
+    pub source: Option<PathBuf>,
+    /// Kind of book to write.
+    #[arg(long, value_enum, default_value_t = BookFormat::Epub)]
+    pub format: BookFormat,
+    /// File to write the book to.
+    #[arg(short, long, default_value = "notes.epub")]
+    pub output: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct DeployArgs {
+    #[command(flatten)]
+    pub site: SiteArgs,
+    /// Publish the destination as it is, without building first.
+    #[arg(long)]
+    pub no_build: bool,
+    /// Build as usual, but only list what would be published.
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Publish nothing if the build has any warning or broken internal link.
+    #[arg(long)]
+    pub strict: bool,
+    /// How to print warnings and errors.
+    #[arg(long, value_enum, default_value_t = MessageFormat::Human)]
+    pub message_format: MessageFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct LintArgs {
+    /// Directory of markdown notes. Overrides `source` in the config.
+    pub source: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MessageFormat {
+    /// Lines of text on stderr.
+    #[default]
+    Human,
+    /// A JSON object per diagnostic on stdout, or on stderr when a rendered
+    /// page goes to stdout, with its level, kind, file, line, column, and
+    /// message.
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum BuildProfile {
+    /// Pages for browsing, with sidenotes and interactive review areas.
+    Web,
+    /// Pages for paper, with footnotes, review areas as plain prompts and
+    /// answers, and a print stylesheet.
+    Print,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum DeckFormat {
+    /// A CSV file for Anki's text importer, tagged with each card's note.
+    AnkiCsv,
+    /// An Anki package, imported whole into a deck called Orbit, tagged the
+    /// same way.
+    Apkg,
+}
+
+impl DeckFormat {
+    /// The file a deck in this format goes to unless `-o` says otherwise.
+    pub fn default_output(&self) -> &'static str {
+        match self {
+            DeckFormat::AnkiCsv => "deck.csv",
+            DeckFormat::Apkg => "deck.apkg",
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum BookFormat {
+    /// An EPUB with a chapter per note, styled for ereaders, in the order
+    /// `[epub] notes` gives.
+    Epub,
+}
+
+impl Cli {
+    /// The most detailed log messages to print, going by `-q` and `-v`.
+    pub fn log_level(&self) -> LevelFilter {
+        match (self.quiet, self.verbose) {
+            (true, _) => LevelFilter::WARN,
+            (false, 0) => LevelFilter::INFO,
+            (false, 1) => LevelFilter::DEBUG,
+            (false, _) => LevelFilter::TRACE,
+        }
+    }
+}
+
+impl BuildArgs {
+    /// The single-file render `--output` asks for, if it's given.
+    pub fn render_args(&self) -> Option<RenderArgs> {
+        let output = self.output.clone()?;
+
+        return Some(RenderArgs {
+            input: self.site.source.clone().unwrap_or_else(|| PathBuf::from("-")),
+            output,
+            fragment: self.fragment,
+            template: self.site.template.clone(),
+            minify: self.site.minify,
+            strict: self.strict,
+            message_format: self.message_format,
+        });
+    }
+}
+
+impl SiteArgs {
+    /// Applies the command-line paths on top of `config`.
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(source) = &self.source {
+            config.source = Some(source.clone());
+        }
+        if let Some(destination) = &self.destination {
+            config.destination = Some(destination.clone());
+        }
+        if let Some(template) = &self.template {
+            config.template = Some(template.clone());
+        }
+        if self.drafts {
+            config.drafts = true;
+        }
+        if self.minify {
+            config.minify = true;
+        }
+        match self.profile {
+            Some(BuildProfile::Web) => config.profile = Profile::Web,
+            Some(BuildProfile::Print) => config.profile = Profile::Print,
+            None => {}
+        }
+        if self.pdf {
+            config.profile = Profile::Print;
+            config.print.pdf = true;
+        }
+    }
+}
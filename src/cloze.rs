@@ -0,0 +1,141 @@
+use crate::orbit::OrbitCard;
+
+/// Marks a highlighted span in prose, as in `the ==mitochondria== is`.
+const HIGHLIGHT: &str = "==";
+
+/// Turns every paragraph holding `==highlighted==` spans into a cloze card
+/// deleting those spans, and the highlights themselves into `<mark>` so the
+/// page still shows them. Fenced code blocks, code spans, headings, tables and
+/// HTML blocks are left alone.
+pub fn extract_clozes(markdown: &str) -> (String, Vec<OrbitCard>) {
+    let mut output = String::with_capacity(markdown.len());
+    let mut cards = Vec::new();
+    let mut fence: Option<&str> = None;
+    let mut paragraph: Vec<&str> = Vec::new();
+
+    for line in markdown.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if let Some(marker) = fence {
+            output.push_str(line);
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            flush_paragraph(&mut paragraph, &mut output, &mut cards);
+            output.push_str(line);
+            fence = Some(&trimmed[..3]);
+            continue;
+        }
+
+        if trimmed.trim().is_empty() || trimmed.starts_with(['#', '|', '<']) {
+            flush_paragraph(&mut paragraph, &mut output, &mut cards);
+            output.push_str(line);
+            continue;
+        }
+        // Each list item is a card of its own.
+        if strip_block_markers(trimmed).len() < trimmed.trim().trim_start_matches('>').trim_start().len() {
+            flush_paragraph(&mut paragraph, &mut output, &mut cards);
+        }
+        paragraph.push(line);
+    }
+    flush_paragraph(&mut paragraph, &mut output, &mut cards);
+
+    return (output, cards);
+}
+
+fn flush_paragraph(paragraph: &mut Vec<&str>, output: &mut String, cards: &mut Vec<OrbitCard>) {
+    let mut cloze = Vec::with_capacity(paragraph.len());
+    let mut highlighted = false;
+    for line in paragraph.iter() {
+        let (marked, deleted) = replace_highlights(line);
+        highlighted |= marked != *line;
+        output.push_str(&marked);
+        cloze.push(strip_block_markers(&deleted).to_string());
+    }
+
+    if highlighted {
+        cards.push(OrbitCard::from_cloze(cloze.join(" ")));
+    }
+    paragraph.clear();
+}
+
+/// Replaces each `==span==` in `line` outside code spans with
+/// `<mark>span</mark>`, returning the result alongside the line with each span
+/// as a `{deletion}` instead.
+fn replace_highlights(line: &str) -> (String, String) {
+    let mut marked = String::with_capacity(line.len());
+    let mut deleted = String::with_capacity(line.len());
+    let mut rest = line;
+
+    loop {
+        let code = rest.find('`');
+        let start = match rest.find(HIGHLIGHT) {
+            Some(start) if code.is_none_or(|code| start < code) => start,
+            _ => match code {
+                // Skip over the whole code span, matching backtick run lengths.
+                Some(code) => {
+                    let run = rest[code..].chars().take_while(|&c| c == '`').count();
+                    let fence = &rest[code..(code + run)];
+                    let end = match rest[(code + run)..].find(fence) {
+                        Some(close) => code + run + close + run,
+                        None => code + run,
+                    };
+                    marked.push_str(&rest[..end]);
+                    deleted.push_str(&rest[..end]);
+                    rest = &rest[end..];
+                    continue;
+                }
+                None => break,
+            },
+        };
+
+        let after = &rest[(start + HIGHLIGHT.len())..];
+        let end = match after.find(HIGHLIGHT) {
+            Some(end) if end > 0 && !after[..end].contains('`') => end,
+            _ => {
+                marked.push_str(&rest[..(start + HIGHLIGHT.len())]);
+                deleted.push_str(&rest[..(start + HIGHLIGHT.len())]);
+                rest = after;
+                continue;
+            }
+        };
+
+        let span = &after[..end];
+        marked.push_str(&rest[..start]);
+        marked.push_str("<mark>");
+        marked.push_str(span);
+        marked.push_str("</mark>");
+        deleted.push_str(&rest[..start]);
+        deleted.push('{');
+        deleted.push_str(span);
+        deleted.push('}');
+        rest = &after[(end + HIGHLIGHT.len())..];
+    }
+    marked.push_str(rest);
+    deleted.push_str(rest);
+
+    return (marked, deleted);
+}
+
+/// The text of a paragraph line without list bullets or blockquote markers,
+/// which mean nothing outside the page.
+fn strip_block_markers(line: &str) -> &str {
+    let mut line = line.trim();
+    loop {
+        let stripped = line.strip_prefix('>')
+            .or_else(|| line.strip_prefix("- "))
+            .or_else(|| line.strip_prefix("* "))
+            .or_else(|| line.strip_prefix("+ "))
+            .or_else(|| {
+                let digits = line.chars().take_while(char::is_ascii_digit).count();
+                line[digits..].strip_prefix(". ").filter(|_| digits > 0)
+            });
+        match stripped {
+            Some(stripped) => line = stripped.trim_start(),
+            None => return line,
+        }
+    }
+}
@@ -0,0 +1,460 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+
+use crate::error::{OrbitError, Result};
+
+pub const CONFIG_FILENAME: &str = "orbit.toml";
+
+/// Site-wide settings read from `orbit.toml`.
+///
+/// Every field is optional; anything left unset falls back to either a
+/// command-line argument or the bundled defaults.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct Config {
+    pub source: Option<PathBuf>,
+    pub destination: Option<PathBuf>,
+    /// Glob patterns of files and directories under the source to leave out
+    /// of the site, such as `private/**`, on top of those listed in the
+    /// source's `.orbitignore`.
+    pub exclude: Vec<String>,
+    /// Absolute URL the site is served from, e.g. `https://example.com/notes/`.
+    /// Required for the Atom feed.
+    pub base_url: Option<String>,
+    pub title: Option<String>,
+    /// Short description of the site, used as the feed subtitle.
+    pub description: Option<String>,
+    /// Default author for the feed and for pages that don't name one.
+    pub author: Option<String>,
+    pub template: Option<PathBuf>,
+    /// Directory of additional templates, selectable per page with the
+    /// `layout` frontmatter key. Each `<name>.html` becomes layout `<name>`.
+    pub layouts: Option<PathBuf>,
+    /// Directory of Handlebars partials. Each `<name>.html` can be included
+    /// from any template with `{{> name}}`.
+    pub partials: Option<PathBuf>,
+    /// Directory of shortcode templates. Each `<name>.html` is rendered
+    /// wherever a page has `{{< name key="value" >}}`, before the markdown.
+    pub shortcodes: Option<PathBuf>,
+    pub css: Option<PathBuf>,
+    /// Further stylesheets copied into the destination and linked after the
+    /// main one, to layer site-specific overrides on top of it.
+    pub stylesheets: Vec<PathBuf>,
+    /// Bundled syntect theme used to colour fenced code blocks, written out
+    /// as `syntax.css`. Defaults to `InspiredGitHub`.
+    pub highlight_theme: Option<String>,
+    /// Write each page as `name/index.html` rather than `name.html`, so its
+    /// URL doesn't end in `.html`.
+    pub clean_urls: bool,
+    /// Slugify the paths pages are written to, so file names with spaces,
+    /// punctuation, or capitals don't end up in URLs.
+    pub slugify_paths: bool,
+    /// Strip comments and insignificant whitespace from rendered pages.
+    pub minify: bool,
+    /// Build pages marked `draft: true` in frontmatter, which are otherwise
+    /// left out.
+    pub drafts: bool,
+    /// Write a search index of every page and a `search.html` page that
+    /// searches it in the browser.
+    pub search: bool,
+    /// Write `site.json`, describing every page's title, tags, links,
+    /// headings, and cards for other tools to read.
+    pub site_json: bool,
+    /// Also write the redirects from pages' `aliases` to a `_redirects` file
+    /// for Netlify, alongside the pages redirecting from them.
+    pub netlify_redirects: bool,
+    /// Write a web app manifest and a service worker that caches every page
+    /// and file of the site, so it can be installed and read offline.
+    pub offline: bool,
+    /// Date pages by their first and last git commits, as `created` and
+    /// `updated`, rather than by file modification times.
+    pub git_dates: bool,
+    /// Frontmatter keys the templates use, which pages may set without
+    /// being warned the key is unknown.
+    pub frontmatter_keys: Vec<String>,
+    /// Colour theme pages start in: `light`, `dark`, or `auto` to follow the
+    /// reader's system preference. Readers can switch with the theme toggle.
+    pub theme: Theme,
+    /// What the site is built for: `web`, the default, or `print`, which
+    /// turns sidenotes into footnotes, shows review areas' prompts and
+    /// answers as plain text, and adds a print stylesheet.
+    pub profile: Profile,
+    /// The `[markdown]` table, toggling optional syntax.
+    pub markdown: RenderOptions,
+    /// The `[feed]` table.
+    pub feed: FeedOptions,
+    /// The `[related]` table, for the "See also" list under each page.
+    pub related: RelatedOptions,
+    /// The `[nav]` table, the menu atop every page.
+    pub nav: NavOptions,
+    /// The `[review]` table.
+    pub review: ReviewOptions,
+    /// The `[images]` table.
+    pub images: ImageOptions,
+    /// The `[epub]` table.
+    pub epub: EpubOptions,
+    /// The `[print]` table, for the print profile.
+    pub print: PrintOptions,
+    /// The `[book]` table.
+    pub book: BookOptions,
+    /// The `[manifest]` table, describing the site as an installable app
+    /// when `offline` is set.
+    pub manifest: ManifestOptions,
+    /// The `[hooks]` table, shell commands run around a build.
+    pub hooks: HookOptions,
+    /// The `[deploy]` table, where `orbit deploy` publishes the site.
+    pub deploy: Option<DeployTarget>,
+}
+
+/// Switches for the optional markdown extensions.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RenderOptions {
+    /// GitHub-flavoured pipe tables. On by default.
+    pub tables: bool,
+    /// Follow every heading with a `¶` link to its own anchor.
+    pub heading_permalinks: bool,
+    /// Render footnotes as Tufte sidenotes rather than a list at the end of
+    /// the page. Pages can override this with `sidenotes` in frontmatter.
+    pub sidenotes: bool,
+    /// Colour of Orbit review areas whose deck doesn't set one, using
+    /// Orbit's colour names such as `blue` or `orange`.
+    pub orbit_color: Option<String>,
+    /// Turn each paragraph with `==highlighted==` spans into a cloze prompt
+    /// deleting them, in a review area at the end of the page. Pages can
+    /// override this with `inline_cloze` in frontmatter.
+    pub inline_cloze: bool,
+    /// Open links to other sites in a new tab, with `rel="noopener
+    /// noreferrer"`.
+    pub external_links_new_tab: bool,
+    /// Class added to links to other sites, to style them apart.
+    pub external_link_class: Option<String>,
+    /// Lay out ```` ```dot ```` blocks with the system Graphviz and inline
+    /// the diagrams as SVG.
+    pub graphviz: bool,
+    /// Leave out of the body the level-1 heading a page's title is taken
+    /// from when its frontmatter has none, as the template shows the title.
+    pub strip_title_heading: bool,
+    /// Render for paper, with sidenotes and margin notes as numbered
+    /// footnotes whatever the page asks. Set by the print profile.
+    #[serde(skip)]
+    pub print: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> RenderOptions {
+        RenderOptions {
+            tables: true,
+            heading_permalinks: false,
+            sidenotes: false,
+            orbit_color: None,
+            inline_cloze: false,
+            external_links_new_tab: false,
+            external_link_class: None,
+            graphviz: false,
+            strip_title_heading: false,
+            print: false,
+        }
+    }
+}
+
+/// Settings for `feed.xml`, written whenever `base_url` is set.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct FeedOptions {
+    /// Include each page's rendered body, not just its title and link.
+    pub full_content: bool,
+    /// How many of the newest dated pages to include.
+    pub limit: usize,
+}
+
+impl Default for FeedOptions {
+    fn default() -> FeedOptions {
+        FeedOptions { full_content: false, limit: 20 }
+    }
+}
+
+/// Settings for the pages listed as related to each page, by the tags they
+/// share and the links between them.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RelatedOptions {
+    /// How many related pages to list at most. Zero lists none.
+    pub limit: usize,
+}
+
+impl Default for RelatedOptions {
+    fn default() -> RelatedOptions {
+        RelatedOptions { limit: 5 }
+    }
+}
+
+/// The links in the menu atop every page, given to templates as `nav` and
+/// shown by the `site-nav` partial.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct NavOptions {
+    /// Links to list first, in order, as `[[nav.items]]` tables.
+    pub items: Vec<NavItem>,
+    /// Also link each top-level directory of pages, after `items`.
+    pub auto: bool,
+}
+
+/// A link in the menu.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NavItem {
+    pub label: String,
+    /// A page's markdown, such as `about.md`, a directory, such as
+    /// `essays/`, for the `index.md` in it, or an absolute URL.
+    pub path: String,
+}
+
+/// Settings for the responsive variants of local images, made with
+/// ImageMagick.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ImageOptions {
+    /// Widths in pixels to resize local JPEG, PNG, and WebP images to, each
+    /// offered to the browser in a `srcset`. Widths no smaller than the
+    /// original are skipped. Empty, the default, leaves images alone.
+    pub widths: Vec<u32>,
+    /// The `sizes` attribute given alongside the `srcset`, telling the browser
+    /// how wide images are displayed.
+    pub sizes: String,
+}
+
+impl Default for ImageOptions {
+    fn default() -> ImageOptions {
+        ImageOptions { widths: Vec::new(), sizes: String::from("(min-width: 760px) 55vw, 100vw") }
+    }
+}
+
+/// Settings for the book `orbit export --format epub` makes.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct EpubOptions {
+    /// Title of the book. Defaults to the site title.
+    pub title: Option<String>,
+    /// Language the book is written in, as a tag such as `en` or `de-CH`.
+    pub language: String,
+    /// Notes to include, in reading order, relative to the exported
+    /// directory. Empty, the default, includes every note, ordered by path.
+    pub notes: Vec<PathBuf>,
+}
+
+impl Default for EpubOptions {
+    fn default() -> EpubOptions {
+        EpubOptions { title: None, language: String::from("en"), notes: Vec::new() }
+    }
+}
+
+/// Settings for `book.html`, every chapter of a book on one page, written
+/// when a summary or a list of notes is given.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct BookOptions {
+    /// Markdown file, relative to the source directory, whose links and
+    /// wikilinks list the chapters in reading order, nested in lists for
+    /// sections, as in mdBook's `SUMMARY.md`. It isn't built as a page.
+    pub summary: Option<PathBuf>,
+    /// The chapters in reading order, relative to the source directory,
+    /// when there's no summary.
+    pub notes: Vec<PathBuf>,
+    /// Title of the book. Defaults to the site title.
+    pub title: Option<String>,
+}
+
+/// The web app manifest written when `offline` is set.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct ManifestOptions {
+    /// Defaults to the site title.
+    pub name: Option<String>,
+    /// Name shown under the app's icon. Defaults to `name`.
+    pub short_name: Option<String>,
+    pub description: Option<String>,
+    /// Colour of the browser's interface around the site, as CSS.
+    pub theme_color: Option<String>,
+    /// Colour shown while the site loads, as CSS.
+    pub background_color: Option<String>,
+    /// Icons, relative to the source directory. PNGs at 192 and 512 pixels
+    /// square are what browsers ask for.
+    pub icons: Vec<PathBuf>,
+}
+
+/// Shell commands run around `orbit build`. Each sees the source and
+/// destination directories as `ORBIT_SOURCE` and `ORBIT_DESTINATION`, and a
+/// failing one stops the build. None run on a dry run.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct HookOptions {
+    /// Run before anything is built.
+    pub before_build: Option<String>,
+    /// Run once everything is built, with the pages rendered, one per line,
+    /// as `ORBIT_PAGES`.
+    pub after_build: Option<String>,
+    /// Run after each page is rendered, with its markdown as `ORBIT_PAGE`
+    /// and its HTML as `ORBIT_OUTPUT`. Unchanged pages that are skipped
+    /// don't run it, and a failure is a warning about the page.
+    pub after_page: Option<String>,
+}
+
+/// Where `orbit deploy` publishes the built site, picked by `target`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "target", rename_all = "kebab-case")]
+pub enum DeployTarget {
+    /// Copies the site over SSH with `rsync`.
+    Rsync {
+        /// Where to copy to, as `rsync` takes it: `user@host:/var/www/notes`.
+        remote: String,
+        /// Delete remote files the site no longer has.
+        #[serde(default)]
+        delete: bool,
+    },
+    /// Syncs the site to an S3 or S3-compatible bucket with the AWS CLI,
+    /// which finds its credentials as usual.
+    S3 {
+        /// Bucket to sync to, with an optional prefix: `my-bucket/notes`.
+        bucket: String,
+        /// URL of an S3-compatible service, such as Cloudflare R2 or MinIO.
+        endpoint: Option<String>,
+        region: Option<String>,
+        /// Delete objects the site no longer has.
+        #[serde(default)]
+        delete: bool,
+    },
+    /// Commits the site to a branch for GitHub Pages with `git`, replacing
+    /// whatever the branch held.
+    GithubPages {
+        /// Repository to push to. Defaults to the current repository's
+        /// `origin`.
+        repository: Option<String>,
+        /// Defaults to `gh-pages`.
+        #[serde(default = "default_pages_branch")]
+        branch: String,
+        /// Custom domain to write to `CNAME`.
+        cname: Option<String>,
+    },
+}
+
+fn default_pages_branch() -> String {
+    String::from("gh-pages")
+}
+
+/// Settings for the print profile.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct PrintOptions {
+    /// Also print every page built to a PDF beside it, with a headless
+    /// Chrome or Chromium.
+    pub pdf: bool,
+    /// The browser to print with, if it isn't on the path as `chromium` or
+    /// `google-chrome`.
+    pub browser: Option<PathBuf>,
+}
+
+/// What a site is built for.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    #[default]
+    Web,
+    Print,
+}
+
+/// The colour theme a page starts in.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Auto,
+    #[default]
+    Light,
+    Dark,
+}
+
+/// Which pages contribute cards to `review.html`. Empty lists don't filter.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct ReviewOptions {
+    /// Only include pages with at least one of these tags.
+    pub tags: Vec<String>,
+    /// Only include pages under one of these directories, relative to the
+    /// source directory.
+    pub directories: Vec<PathBuf>,
+}
+
+impl Config {
+    /// Loads the configuration at `path`, or the default configuration if no
+    /// such file exists.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+
+        let contents = std::fs::read_to_string(path).map_err(OrbitError::io(path))?;
+        let config: Config = toml::from_str(&contents).map_err(|err| OrbitError::Config {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        })?;
+
+        return Ok(config);
+    }
+
+    /// Reads the configured template from disk, or `bundled` if none is set.
+    pub fn load_template(&self, bundled: &str) -> Result<String> {
+        match &self.template {
+            Some(path) => std::fs::read_to_string(path).map_err(OrbitError::io(path)),
+            None => Ok(bundled.to_string()),
+        }
+    }
+
+    /// Reads every `.html` file in the configured layouts directory, returning
+    /// `(name, template)` pairs named after the file stems.
+    pub fn load_layouts(&self) -> Result<Vec<(String, String)>> {
+        read_templates(self.layouts.as_deref())
+    }
+
+    /// Reads every `.html` file in the configured partials directory, in the
+    /// same form as [`Config::load_layouts`].
+    pub fn load_partials(&self) -> Result<Vec<(String, String)>> {
+        read_templates(self.partials.as_deref())
+    }
+
+    /// Reads every `.html` file in the configured shortcodes directory, in the
+    /// same form as [`Config::load_layouts`].
+    pub fn load_shortcodes(&self) -> Result<Vec<(String, String)>> {
+        read_templates(self.shortcodes.as_deref())
+    }
+
+    /// Reads the configured stylesheet from disk, or `bundled` if none is set.
+    pub fn load_css(&self, bundled: &str) -> Result<String> {
+        match &self.css {
+            Some(path) => std::fs::read_to_string(path).map_err(OrbitError::io(path)),
+            None => Ok(bundled.to_string()),
+        }
+    }
+}
+
+fn read_templates(directory: Option<&Path>) -> Result<Vec<(String, String)>> {
+    let directory = match directory {
+        Some(directory) => directory,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut templates = Vec::new();
+    for entry in std::fs::read_dir(directory).map_err(OrbitError::io(directory))? {
+        let path = entry.map_err(OrbitError::io(directory))?.path();
+        if path.extension().is_none_or(|extension| extension != "html") {
+            continue;
+        }
+
+        let name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        let template = std::fs::read_to_string(&path).map_err(OrbitError::io(&path))?;
+        templates.push((name, template));
+    }
+    templates.sort();
+
+    return Ok(templates);
+}
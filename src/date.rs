@@ -0,0 +1,23 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+/// Date-only formats accepted in frontmatter, besides RFC 3339 timestamps.
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%Y/%m/%d", "%d %B %Y", "%d %b %Y", "%B %d, %Y", "%b %d, %Y"];
+/// Date and time formats accepted in frontmatter.
+const DATETIME_FORMATS: &[&str] = &["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"];
+
+/// Parses a frontmatter date such as `2024-03-05`, `2024/03/05`,
+/// `5 March 2024`, `March 5, 2024`, `2024-03-05 14:30`, or an RFC 3339
+/// timestamp, which is converted to UTC.
+pub fn parse_date(date: &str) -> Option<NaiveDateTime> {
+    let date = date.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+        return Some(date.naive_utc());
+    }
+    if let Some(date) = DATETIME_FORMATS.iter().find_map(|format| NaiveDateTime::parse_from_str(date, format).ok()) {
+        return Some(date);
+    }
+
+    return DATE_FORMATS.iter()
+        .find_map(|format| NaiveDate::parse_from_str(date, format).ok())
+        .and_then(|date| date.and_hms_opt(0, 0, 0));
+}
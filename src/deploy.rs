@@ -0,0 +1,142 @@
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process::Command;
+
+use crate::cache::CACHE_FILENAME;
+use crate::config::DeployTarget;
+use crate::error::{OrbitError, Result};
+
+impl DeployTarget {
+    /// The target's name, as `target` gives it in `[deploy]`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DeployTarget::Rsync { .. } => "rsync",
+            DeployTarget::S3 { .. } => "s3",
+            DeployTarget::GithubPages { .. } => "github-pages",
+        }
+    }
+}
+
+/// Publishes the site built in `destination` to `target`, leaving out the
+/// build manifest. A dry run asks the tool doing the copying to only say
+/// what it would do.
+pub fn deploy(target: &DeployTarget, destination: &Path, dry_run: bool) -> Result<()> {
+    let name = target.name();
+    match target {
+        DeployTarget::Rsync { remote, delete } => {
+            let mut rsync = Command::new("rsync");
+            // The trailing slash copies the directory's contents rather
+            // than the directory itself.
+            rsync.args(["-az", "--exclude", CACHE_FILENAME]).arg(format!("{}/", destination.display())).arg(remote);
+            if *delete {
+                rsync.arg("--delete");
+            }
+            if dry_run {
+                rsync.args(["--dry-run", "--itemize-changes"]);
+            }
+            log_output(&run(name, &mut rsync)?);
+        }
+        DeployTarget::S3 { bucket, endpoint, region, delete } => {
+            let mut aws = Command::new("aws");
+            let bucket = format!("s3://{}", bucket.trim_start_matches("s3://"));
+            aws.args(["s3", "sync"]).arg(destination).arg(bucket).args(["--exclude", CACHE_FILENAME]);
+            if *delete {
+                aws.arg("--delete");
+            }
+            if let Some(endpoint) = endpoint {
+                aws.args(["--endpoint-url", endpoint]);
+            }
+            if let Some(region) = region {
+                aws.args(["--region", region]);
+            }
+            if dry_run {
+                aws.arg("--dryrun");
+            }
+            log_output(&run(name, &mut aws)?);
+        }
+        DeployTarget::GithubPages { repository, branch, cname } => {
+            let repository = match repository {
+                Some(repository) => repository.clone(),
+                None => run(name, Command::new("git").args(["remote", "get-url", "origin"]))?.trim().to_string(),
+            };
+            push_pages(destination, &repository, branch, cname.as_deref(), dry_run)?;
+        }
+    }
+
+    return Ok(());
+}
+
+/// Commits everything in `destination` to a fresh repository kept outside
+/// it and force-pushes that to `branch` of `repository`. The files Pages
+/// wants besides the site go straight into the commit, leaving
+/// `destination` as the build left it.
+fn push_pages(destination: &Path, repository: &str, branch: &str, cname: Option<&str>, dry_run: bool) -> Result<()> {
+    // Pages would otherwise run the site through Jekyll, which drops files
+    // starting with an underscore.
+    let mut extra_files = vec![(".nojekyll", String::new())];
+    if let Some(cname) = cname {
+        extra_files.push(("CNAME", format!("{}\n", cname)));
+    }
+
+    let git_dir = std::env::temp_dir().join(format!("orbit-deploy-{}", std::process::id()));
+    let git = || {
+        let mut git = Command::new("git");
+        git.arg("--git-dir").arg(&git_dir).arg("--work-tree").arg(destination);
+        git
+    };
+    let mut push = git();
+    push.args(["push", "--force", "--quiet", repository, &format!("HEAD:refs/heads/{}", branch)]);
+    if dry_run {
+        push.arg("--dry-run");
+    }
+
+    let name = "github-pages";
+    let pushed = run(name, git().args(["init", "--quiet"]))
+        .and_then(|_| {
+            let exclude = git_dir.join("info").join("exclude");
+            std::fs::create_dir_all(git_dir.join("info")).map_err(OrbitError::io(&exclude))?;
+            std::fs::write(&exclude, format!("/{}\n", CACHE_FILENAME)).map_err(OrbitError::io(&exclude))
+        })
+        .and_then(|_| run(name, git().args(["add", "--all"])))
+        .and_then(|_| {
+            for (file, contents) in &extra_files {
+                let path = git_dir.join(file);
+                std::fs::write(&path, contents).map_err(OrbitError::io(&path))?;
+                let blob = run(name, git().arg("hash-object").arg("-w").arg(&path))?;
+                let cache_info = format!("100644,{},{}", blob.trim(), file);
+                run(name, git().args(["update-index", "--add", "--cacheinfo", &cache_info]))?;
+            }
+            return Ok(());
+        })
+        .and_then(|_| run(name, git().args(["commit", "--quiet", "--message", "Publish site"])))
+        .and_then(|_| run(name, &mut push));
+    let _ = std::fs::remove_dir_all(&git_dir);
+
+    return pushed.map(|_| ());
+}
+
+/// Runs `command` for deploying to `target`, returning what it printed.
+fn run(target: &'static str, command: &mut Command) -> Result<String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    tracing::debug!("running {:?}", command);
+    let output = match command.output() {
+        Ok(output) => output,
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            return Err(OrbitError::Deploy { target, message: format!("`{}` isn't installed", program) });
+        }
+        Err(err) => return Err(OrbitError::io(&program)(err)),
+    };
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(OrbitError::Deploy { target, message });
+    }
+
+    return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+}
+
+/// Passes on what a tool printed about the files it copied.
+fn log_output(output: &str) {
+    for line in output.lines().filter(|line| !line.trim().is_empty()) {
+        tracing::info!("{}", line);
+    }
+}
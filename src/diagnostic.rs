@@ -0,0 +1,98 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::error::OrbitError;
+use crate::linkcheck::BrokenLink;
+
+/// How serious a [`Diagnostic`] is.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Warning,
+    Error,
+}
+
+/// A problem found while building a site, in a form editors and CI can
+/// read: one JSON object per line with `--message-format json`.
+#[derive(Serialize, Debug)]
+pub struct Diagnostic {
+    pub level: Level,
+    /// What sort of problem it is, such as `footnote` or `broken-link`.
+    pub kind: &'static str,
+    /// The file at fault: the note for problems with a note, and the
+    /// generated page for broken links.
+    pub file: Option<PathBuf>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Describes `err`, placing it in the innermost note it's attributed to.
+    pub fn from_error(level: Level, err: &OrbitError) -> Diagnostic {
+        let (file, inner) = err.unwrap_file();
+        let (line, column) = match err.position() {
+            Some((line, column)) => (Some(line), column),
+            None => (None, None),
+        };
+
+        return Diagnostic {
+            level,
+            kind: error_kind(inner),
+            file: file.map(Path::to_path_buf),
+            line,
+            column,
+            message: inner.to_string(),
+        };
+    }
+
+    /// Describes a broken link in the page under `destination` holding it.
+    pub fn broken_link(level: Level, link: &BrokenLink, destination: &Path) -> Diagnostic {
+        let message = match link.missing_anchor {
+            true => format!("no anchor for `{}`", link.href),
+            false => format!("broken link to `{}`", link.href),
+        };
+
+        return Diagnostic {
+            level,
+            kind: "broken-link",
+            file: Some(destination.join(&link.page)),
+            line: None,
+            column: None,
+            message,
+        };
+    }
+
+    /// The diagnostic as a single line of JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("diagnostics serialize to JSON")
+    }
+}
+
+fn error_kind(err: &OrbitError) -> &'static str {
+    match err {
+        OrbitError::Io { .. } | OrbitError::Walk(_) => "io",
+        OrbitError::Frontmatter { .. } => "frontmatter",
+        OrbitError::FrontmatterKey { .. } => "frontmatter-key",
+        OrbitError::OrbitDeck { .. } | OrbitError::OrbitBlock { .. } => "orbit-deck",
+        OrbitError::Footnote { .. } => "footnote",
+        OrbitError::Graphviz { .. } => "graphviz",
+        OrbitError::Include { .. } => "include",
+        OrbitError::Shortcode { .. } | OrbitError::UnknownShortcode(_) => "shortcode",
+        OrbitError::Template(_) | OrbitError::Render(_) | OrbitError::UnknownLayout(_) => "template",
+        OrbitError::Embed { .. } => "embed",
+        OrbitError::ImageResize { .. } => "image",
+        OrbitError::Pdf { .. } => "pdf",
+        OrbitError::Hook { .. } => "hook",
+        OrbitError::Deploy { .. } => "deploy",
+        OrbitError::Anki(_) | OrbitError::ZipTooLarge { .. } => "export",
+        OrbitError::Config { .. }
+            | OrbitError::MissingSetting(_)
+            | OrbitError::UnknownTheme(_)
+            | OrbitError::IgnorePattern { .. }
+            | OrbitError::UnknownNote { .. } => "config",
+        OrbitError::OutputCollision { .. } => "output-collision",
+        OrbitError::Json(_) | OrbitError::File { .. } => "other",
+    }
+}
@@ -0,0 +1,302 @@
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use pulldown_cmark::escape::{escape_href, escape_html};
+use regex::{Captures, Regex};
+
+use crate::config::Config;
+use crate::error::{OrbitError, Result};
+use crate::ignore::IgnoreRules;
+use crate::site::{self, Page};
+use crate::urls::{normalize, percent_decode, unescape};
+use crate::wikilink::NoteIndex;
+use crate::zip::Archive;
+use crate::{cache, include, orbit};
+
+lazy_static::lazy_static! {
+    static ref IMG_SRC: Regex = Regex::new(r#"<img src="([^"]*)""#).unwrap();
+    static ref SCHEME: Regex = Regex::new(r"^[A-Za-z][A-Za-z0-9+.-]*:").unwrap();
+    /// HTML's empty elements, which XHTML needs closed.
+    static ref VOID_ELEMENT: Regex = Regex::new(r"<(br|hr|img|input|col|wbr)\b([^>]*?)\s*/?>").unwrap();
+    static ref ATTRIBUTE: Regex = Regex::new(r#"\s+([A-Za-z_:][-A-Za-z0-9_:.]*)(?:\s*=\s*("[^"]*"|'[^']*'))?"#).unwrap();
+}
+
+/// Tufte's styling, adapted for ereaders.
+const EPUB_CSS: &str = include_str!("epub.css");
+
+/// Directory in the archive the book's content lives in.
+const CONTENT_DIR: &str = "OEBPS";
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+/// Image formats EPUB readers must support, by extension.
+const IMAGE_TYPES: &[(&str, &str)] = &[
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("svg", "image/svg+xml"),
+    ("webp", "image/webp"),
+];
+
+/// A book made from a directory of notes.
+pub struct Epub {
+    /// The EPUB file.
+    pub bytes: Vec<u8>,
+    /// How many notes became chapters.
+    pub chapters: usize,
+    pub warnings: Vec<OrbitError>,
+}
+
+/// A note in the book.
+struct Chapter {
+    /// Path of the chapter within the content directory.
+    href: PathBuf,
+    title: String,
+    /// Whether the chapter has inline SVG, such as a Graphviz diagram.
+    has_svg: bool,
+}
+
+/// Renders the notes under `source` that `ignore` doesn't match into an
+/// EPUB, one chapter per note, in the order `[epub] notes` lists them or
+/// else by path. Drafts are left out unless `drafts` is set.
+///
+/// Review areas are replaced by their static fallbacks, and the local images
+/// notes show are packed alongside them. Links between chapters work; those
+/// to notes left out of the book don't.
+pub fn build_epub(config: &Config, source: &Path, ignore: &IgnoreRules) -> Result<Epub> {
+    let mut pages = site::find_pages(source, ignore)?;
+    pages.sort_by(|a, b| a.relative.cmp(&b.relative));
+    let notes = NoteIndex::new(&pages);
+    let pages = order_pages(pages, &config.epub.notes)?;
+
+    let language = &config.epub.language;
+    let mut archive = Archive::default();
+    // Readers tell an EPUB by this first, uncompressed entry.
+    archive.add("mimetype", b"application/epub+zip")?;
+    archive.add("META-INF/container.xml", CONTAINER_XML.as_bytes())?;
+
+    let mut chapters = Vec::new();
+    let mut images = Vec::new();
+    let mut warnings = Vec::new();
+    for page in &pages {
+        let markdown = crate::read_note(&page.path)?;
+        if !config.drafts && site::is_draft(&markdown) {
+            continue;
+        }
+        let markdown = include::expand_includes(&markdown, &page.path).map_err(|err| err.in_file(&page.path))?;
+        let rendered = crate::render_page_with(&markdown, &config.markdown, &notes, source, &page.relative)
+            .map_err(|err| err.in_file(&page.path))?;
+        warnings.extend(rendered.warnings.into_iter().map(|warning| warning.in_file(&page.path)));
+
+        let body = xhtml(&orbit::static_fallback(&rendered.body));
+        for image in local_images(&body, &page.relative) {
+            if !images.contains(&image) && source.join(&image).is_file() {
+                images.push(image);
+            }
+        }
+
+        let frontmatter = &rendered.frontmatter;
+        let title = frontmatter.title.clone()
+            .unwrap_or_else(|| page.relative.file_stem().unwrap_or_default().to_string_lossy().into_owned());
+        // An inferred title is already the body's first heading.
+        let heading = !frontmatter.inferred_title || config.markdown.strip_title_heading;
+        let href = page.output_path();
+        let contents = chapter_xhtml(&title, heading, &body, &page.root_prefix(), language);
+        archive.add(&content_path(&href), contents.as_bytes())?;
+        chapters.push(Chapter { href, title, has_svg: body.contains("<svg") });
+    }
+
+    for image in &images {
+        let path = source.join(image);
+        let contents = std::fs::read(&path).map_err(OrbitError::io(&path))?;
+        archive.add(&content_path(image), &contents)?;
+    }
+
+    let title = config.epub.title.as_deref().or(config.title.as_deref()).unwrap_or("Notes");
+    archive.add(&content_path(Path::new("epub.css")), EPUB_CSS.as_bytes())?;
+    archive.add(&content_path(Path::new("nav.xhtml")), nav_xhtml(title, &chapters, language).as_bytes())?;
+    archive.add(&content_path(Path::new("content.opf")), package_opf(config, title, &chapters, &images).as_bytes())?;
+
+    return Ok(Epub { bytes: archive.finish()?, chapters: chapters.len(), warnings });
+}
+
+/// `pages` in the order of `order`, paths relative to the exported directory
+/// with or without their `.md`, or all of them if `order` is empty.
+fn order_pages(pages: Vec<Page>, order: &[PathBuf]) -> Result<Vec<Page>> {
+    if order.is_empty() {
+        return Ok(pages);
+    }
+
+    let mut ordered = Vec::with_capacity(order.len());
+    for note in order {
+        let page = pages.iter()
+            .find(|page| page.relative == *note || page.relative.with_extension("") == *note)
+            .ok_or_else(|| OrbitError::UnknownNote { note: note.clone(), setting: "[epub] notes" })?;
+        ordered.push(page.clone());
+    }
+
+    return Ok(ordered);
+}
+
+/// The path in the archive of `path` within the content directory.
+fn content_path(path: &Path) -> String {
+    format!("{}/{}", CONTENT_DIR, path.to_string_lossy().replace('\\', "/"))
+}
+
+/// The local images `html`, rendered for the note at `relative`, shows,
+/// relative to the source directory.
+fn local_images(html: &str, relative: &Path) -> Vec<PathBuf> {
+    let mut images = Vec::new();
+    for captures in IMG_SRC.captures_iter(html) {
+        let src = unescape(&captures[1]);
+        if src.is_empty() || src.starts_with(['/', '#']) || SCHEME.is_match(&src) {
+            continue;
+        }
+        let url_path = &src[..src.find(['?', '#']).unwrap_or(src.len())];
+        let image = normalize(&relative.parent().unwrap_or_else(|| Path::new("")).join(percent_decode(url_path)));
+        if let Some(image) = image.filter(|image| media_type(image).is_some()) {
+            images.push(image);
+        }
+    }
+
+    return images;
+}
+
+/// The media type of the image at `path`, if readers must support it.
+fn media_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_string_lossy().to_lowercase();
+
+    return IMAGE_TYPES.iter().find(|(known, _)| *known == extension).map(|(_, media_type)| *media_type);
+}
+
+/// `html` with its empty elements closed and their bare attributes given
+/// values, as XHTML needs.
+fn xhtml(html: &str) -> String {
+    let closed = VOID_ELEMENT.replace_all(html, |captures: &Captures| {
+        let mut element = format!("<{}", &captures[1]);
+        for attribute in ATTRIBUTE.captures_iter(&captures[2]) {
+            match attribute.get(2) {
+                Some(value) => element.push_str(&format!(" {}={}", &attribute[1], value.as_str())),
+                None => element.push_str(&format!(" {0}=\"{0}\"", &attribute[1])),
+            }
+        }
+        element.push_str("/>");
+        element
+    });
+
+    return closed.into_owned();
+}
+
+/// Opens an XHTML document titled `title`, linking the stylesheet by way of
+/// `root`, the path back to the content directory.
+fn xhtml_head(title: &str, root: &str, language: &str) -> String {
+    let mut head = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<!DOCTYPE html>\n");
+    head.push_str("<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" xml:lang=\"");
+    let _ = escape_html(&mut head, language);
+    head.push_str("\" lang=\"");
+    let _ = escape_html(&mut head, language);
+    head.push_str("\">\n<head>\n<meta charset=\"utf-8\"/>\n<title>");
+    let _ = escape_html(&mut head, title);
+    head.push_str("</title>\n<link rel=\"stylesheet\" type=\"text/css\" href=\"");
+    let _ = escape_href(&mut head, root);
+    head.push_str("epub.css\"/>\n</head>\n");
+
+    return head;
+}
+
+/// A chapter of the book, opening with its title if `heading` is set.
+fn chapter_xhtml(title: &str, heading: bool, body: &str, root: &str, language: &str) -> String {
+    let mut xhtml = xhtml_head(title, root, language);
+    xhtml.push_str("<body>\n<article>\n");
+    if heading {
+        xhtml.push_str("<h1>");
+        let _ = escape_html(&mut xhtml, title);
+        xhtml.push_str("</h1>\n");
+    }
+    xhtml.push_str(body);
+    xhtml.push_str("</article>\n</body>\n</html>\n");
+
+    return xhtml;
+}
+
+/// The navigation document, listing every chapter as the table of contents.
+fn nav_xhtml(title: &str, chapters: &[Chapter], language: &str) -> String {
+    let mut xhtml = xhtml_head(title, "", language);
+    xhtml.push_str("<body>\n<nav epub:type=\"toc\" id=\"toc\">\n<h1>");
+    let _ = escape_html(&mut xhtml, title);
+    xhtml.push_str("</h1>\n<ol>\n");
+    for chapter in chapters {
+        xhtml.push_str("<li><a href=\"");
+        let _ = escape_href(&mut xhtml, &chapter.href.to_string_lossy());
+        xhtml.push_str("\">");
+        let _ = escape_html(&mut xhtml, &chapter.title);
+        xhtml.push_str("</a></li>\n");
+    }
+    xhtml.push_str("</ol>\n</nav>\n</body>\n</html>\n");
+
+    return xhtml;
+}
+
+/// The package document, describing the book and listing its files in
+/// reading order.
+fn package_opf(config: &Config, title: &str, chapters: &[Chapter], images: &[PathBuf]) -> String {
+    let language = &config.epub.language;
+    // The same notes under the same title make the same book.
+    let mut key = title.to_string();
+    for chapter in chapters {
+        key.push_str(&chapter.href.to_string_lossy());
+    }
+    let hash = cache::hash_bytes(key.as_bytes());
+    let identifier = format!("urn:uuid:{}-{}-{}-{}-{}", &hash[..8], &hash[8..12], &hash[12..16], &hash[16..20], &hash[20..32]);
+
+    let mut opf = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    opf.push_str("<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\" xml:lang=\"");
+    let _ = escape_html(&mut opf, language);
+    opf.push_str("\">\n  <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n");
+    opf.push_str(&format!("    <dc:identifier id=\"book-id\">{}</dc:identifier>\n", identifier));
+    push_element(&mut opf, "dc:title", title);
+    push_element(&mut opf, "dc:language", language);
+    if let Some(author) = &config.author {
+        push_element(&mut opf, "dc:creator", author);
+    }
+    if let Some(description) = &config.description {
+        push_element(&mut opf, "dc:description", description);
+    }
+    opf.push_str(&format!("    <meta property=\"dcterms:modified\">{}</meta>\n", Utc::now().format("%Y-%m-%dT%H:%M:%SZ")));
+    opf.push_str("  </metadata>\n  <manifest>\n");
+    opf.push_str("    <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n");
+    opf.push_str("    <item id=\"css\" href=\"epub.css\" media-type=\"text/css\"/>\n");
+    for (idx, chapter) in chapters.iter().enumerate() {
+        let properties = if chapter.has_svg { " properties=\"svg\"" } else { "" };
+        push_item(&mut opf, &format!("chapter-{}", idx + 1), &chapter.href, "application/xhtml+xml", properties);
+    }
+    for (idx, image) in images.iter().enumerate() {
+        push_item(&mut opf, &format!("image-{}", idx + 1), image, media_type(image).unwrap_or_default(), "");
+    }
+    opf.push_str("  </manifest>\n  <spine>\n");
+    for idx in 0..chapters.len() {
+        opf.push_str(&format!("    <itemref idref=\"chapter-{}\"/>\n", idx + 1));
+    }
+    opf.push_str("  </spine>\n</package>\n");
+
+    return opf;
+}
+
+fn push_element(xml: &mut String, name: &str, text: &str) {
+    xml.push_str(&format!("    <{}>", name));
+    let _ = escape_html(&mut *xml, text);
+    xml.push_str(&format!("</{}>\n", name));
+}
+
+fn push_item(xml: &mut String, id: &str, href: &Path, media_type: &str, properties: &str) {
+    xml.push_str(&format!("    <item id=\"{}\" href=\"", id));
+    let _ = escape_href(&mut *xml, &href.to_string_lossy());
+    xml.push_str(&format!("\" media-type=\"{}\"{}/>\n", media_type, properties));
+}
@@ -0,0 +1,286 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::result;
+
+use thiserror::Error;
+
+pub type Result<T> = result::Result<T, OrbitError>;
+
+/// Everything that can go wrong while rendering a site.
+///
+/// Errors raised while rendering a particular note are wrapped in
+/// [`OrbitError::File`] so the message names the file at fault. Line and
+/// column numbers are 1-based and count from the top of that file.
+#[derive(Error, Debug)]
+pub enum OrbitError {
+    #[error("{}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("invalid frontmatter at line {line}, column {column}: {message}")]
+    Frontmatter {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+
+    #[error("frontmatter key `{key}`: {message}")]
+    FrontmatterKey {
+        key: String,
+        message: String,
+    },
+
+    #[error("invalid orbit deck at line {line}, column {column}: {message}")]
+    OrbitDeck {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+
+    #[error("footnote at line {line}: {message}")]
+    Footnote {
+        line: usize,
+        message: String,
+    },
+
+    #[error("graph at line {line}: {message}")]
+    Graphviz {
+        line: usize,
+        message: String,
+    },
+
+    #[error("include at line {line}: {message}")]
+    Include {
+        line: usize,
+        message: String,
+    },
+
+    #[error("shortcode at line {line}: {message}")]
+    Shortcode {
+        line: usize,
+        message: String,
+    },
+
+    /// An error in an `orbit` block, with the block's line range and the
+    /// offending line of the deck.
+    #[error("orbit block at lines {start}-{end}: {source}\n{snippet}")]
+    OrbitBlock {
+        start: usize,
+        end: usize,
+        snippet: String,
+        #[source]
+        source: Box<OrbitError>,
+    },
+
+    // Handlebars errors are boxed; unboxed they would make every `Result` in
+    // the crate several times larger.
+    #[error("template error: {0}")]
+    Template(Box<handlebars::TemplateError>),
+
+    #[error("template render error: {0}")]
+    Render(Box<handlebars::RenderError>),
+
+    #[error("unknown layout `{0}`")]
+    UnknownLayout(String),
+
+    #[error("unknown shortcode `{0}`")]
+    UnknownShortcode(String),
+
+    #[error("unknown highlighting theme `{0}`")]
+    UnknownTheme(String),
+
+    #[error("{}: invalid configuration: {message}", path.display())]
+    Config {
+        path: PathBuf,
+        message: String,
+    },
+
+    #[error("{} and {} would both be written to {}", first.display(), second.display(), output.display())]
+    OutputCollision {
+        output: PathBuf,
+        first: PathBuf,
+        second: PathBuf,
+    },
+
+    #[error("{}: couldn't resize image: {message}", path.display())]
+    ImageResize {
+        path: PathBuf,
+        message: String,
+    },
+
+    #[error("{}: couldn't print to PDF: {message}", path.display())]
+    Pdf {
+        path: PathBuf,
+        message: String,
+    },
+
+    #[error("`{hook}` hook failed: {message}")]
+    Hook {
+        hook: &'static str,
+        message: String,
+    },
+
+    #[error("couldn't deploy to {target}: {message}")]
+    Deploy {
+        target: &'static str,
+        message: String,
+    },
+
+    #[error("couldn't embed `{target}`: {message}")]
+    Embed {
+        target: String,
+        message: String,
+    },
+
+    #[error("{name} is too large for a zip archive")]
+    ZipTooLarge {
+        name: String,
+    },
+
+    #[error("couldn't write the Anki collection: {0}")]
+    Anki(#[from] rusqlite::Error),
+
+    #[error("invalid ignore pattern `{pattern}`: {message}")]
+    IgnorePattern {
+        pattern: String,
+        message: String,
+    },
+
+    #[error("no note {} to put in the book; check `{setting}`", note.display())]
+    UnknownNote {
+        note: PathBuf,
+        setting: &'static str,
+    },
+
+    #[error("{0}")]
+    MissingSetting(&'static str),
+
+    #[error(transparent)]
+    Walk(#[from] walkdir::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error("{}: {source}", path.display())]
+    File {
+        path: PathBuf,
+        #[source]
+        source: Box<OrbitError>,
+    },
+}
+
+impl OrbitError {
+    /// Returns a closure wrapping an I/O error with the path it concerns, for
+    /// use with `map_err`.
+    pub fn io<P: AsRef<Path>>(path: P) -> impl FnOnce(io::Error) -> OrbitError {
+        let path = path.as_ref().to_path_buf();
+        move |source| OrbitError::Io { path, source }
+    }
+
+    /// Builds an [`OrbitError::Frontmatter`], dropping the parser's own
+    /// location suffix since it is relative to the YAML rather than the file.
+    pub fn frontmatter(line: usize, column: usize, message: &str) -> OrbitError {
+        OrbitError::Frontmatter { line, column, message: strip_location(message) }
+    }
+
+    /// Builds an [`OrbitError::OrbitDeck`]; see [`OrbitError::frontmatter`].
+    pub fn orbit_deck(line: usize, column: usize, message: &str) -> OrbitError {
+        OrbitError::OrbitDeck { line, column, message: strip_location(message) }
+    }
+
+    /// Places an [`OrbitError::OrbitDeck`] in the `orbit` block spanning
+    /// lines `start` to `end` of the note `block` was taken from, quoting the
+    /// offending line with a caret under the column at fault.
+    pub fn in_orbit_block(self, block: &str, start: usize, end: usize) -> OrbitError {
+        let (line, column) = match &self {
+            OrbitError::OrbitDeck { line, column, .. } => (*line, *column),
+            _ => (start, 1),
+        };
+        let text = block.lines().nth(line.saturating_sub(start)).unwrap_or("");
+        let gutter = line.to_string().len();
+        let snippet = format!(
+            "{:>gutter$} | {}\n{:>gutter$} | {:>column$}",
+            line, text, "", "^", gutter = gutter, column = column.max(1),
+        );
+
+        OrbitError::OrbitBlock { start, end, snippet, source: Box::new(self) }
+    }
+
+    /// Attributes this error to the note at `path`.
+    pub fn in_file<P: AsRef<Path>>(self, path: P) -> OrbitError {
+        OrbitError::File { path: path.as_ref().to_path_buf(), source: Box::new(self) }
+    }
+
+    /// The error inside every [`OrbitError::File`] wrapping this one, and
+    /// the note the innermost of them names.
+    pub fn unwrap_file(&self) -> (Option<&Path>, &OrbitError) {
+        let mut path = None;
+        let mut err = self;
+        while let OrbitError::File { path: file, source } = err {
+            path = Some(file.as_path());
+            err = source;
+        }
+
+        return (path, err);
+    }
+
+    /// The line, and column where known, in its note that this error points
+    /// at, if it points anywhere.
+    pub fn position(&self) -> Option<(usize, Option<usize>)> {
+        match self.unwrap_file().1 {
+            OrbitError::Frontmatter { line, column, .. } | OrbitError::OrbitDeck { line, column, .. } => {
+                Some((*line, Some(*column)))
+            }
+            OrbitError::Footnote { line, .. }
+                | OrbitError::Graphviz { line, .. }
+                | OrbitError::Include { line, .. }
+                | OrbitError::Shortcode { line, .. } => Some((*line, None)),
+            OrbitError::OrbitBlock { start, .. } => Some((*start, None)),
+            _ => None,
+        }
+    }
+}
+
+/// The 1-based line and column of byte `offset` in `text`, counting the
+/// column in characters so carets line up under non-ASCII text.
+pub(crate) fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+
+    return (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1);
+}
+
+/// The byte offset in `text` of a 1-based line and column counted in bytes,
+/// as serde_json reports them.
+pub(crate) fn byte_offset(text: &str, line: usize, column: usize) -> usize {
+    let line_start: usize = text.split_inclusive('\n').take(line.saturating_sub(1)).map(str::len).sum();
+
+    return line_start + column.saturating_sub(1);
+}
+
+/// Removes a trailing ` at line N column M` from a serde error message.
+fn strip_location(message: &str) -> String {
+    match message.rfind(" at line ") {
+        Some(idx) => message[..idx].to_string(),
+        None => message.to_string(),
+    }
+}
+
+impl From<handlebars::TemplateError> for OrbitError {
+    fn from(err: handlebars::TemplateError) -> OrbitError {
+        OrbitError::Template(Box::new(err))
+    }
+}
+
+impl From<handlebars::RenderError> for OrbitError {
+    fn from(err: handlebars::RenderError) -> OrbitError {
+        OrbitError::Render(Box::new(err))
+    }
+}
@@ -0,0 +1,294 @@
+use std::path::Path;
+
+use chrono::Utc;
+use regex::Regex;
+use rusqlite::{params, Connection};
+use sha1::{Digest, Sha1};
+
+use crate::error::{OrbitError, Result};
+use crate::ignore::IgnoreRules;
+use crate::orbit::{self, OrbitCard};
+use crate::site;
+use crate::slug::slugify;
+use crate::zip::Archive;
+use crate::{cache, math};
+
+lazy_static::lazy_static! {
+    static ref HTML_TAG: Regex = Regex::new(r"<[^>]*>").unwrap();
+}
+
+/// Ids of the note types and deck in an `.apkg`, fixed so importing a newer
+/// export updates the notes from the last one rather than duplicating them.
+const BASIC_MODEL_ID: i64 = 1_690_000_000_001;
+const CLOZE_MODEL_ID: i64 = 1_690_000_000_002;
+const DECK_ID: i64 = 1_690_000_000_003;
+/// The deck an `.apkg` fills.
+const DECK_NAME: &str = "Orbit";
+/// The collection schema Anki 2.1 reads from an `.apkg`.
+const COLLECTION_SCHEMA: &str = include_str!("anki-schema.sql");
+/// Styles every card, as Anki's own note types do.
+const CARD_CSS: &str = ".card { font-family: arial; font-size: 20px; text-align: center; color: black; background-color: white; }\n.cloze { font-weight: bold; color: blue; }";
+
+/// A card taken from a note, for exporting to other review tools.
+#[derive(Debug)]
+pub struct ExportedCard {
+    /// Anki tag naming the note the card came from, e.g. `essays::on-memory`
+    /// for `essays/On Memory.md`.
+    pub tag: String,
+    pub card: OrbitCard,
+}
+
+/// Every card in every `orbit` block under `source`, ordered by note,
+/// leaving out notes `ignore` matches.
+pub fn collect_cards(source: &Path, ignore: &IgnoreRules) -> Result<Vec<ExportedCard>> {
+    let mut pages = site::find_pages(source, ignore)?;
+    pages.sort_by(|a, b| a.relative.cmp(&b.relative));
+
+    let mut cards = Vec::new();
+    for page in pages {
+        let markdown = crate::read_note(&page.path)?;
+        let decks = crate::extract_decks(&markdown).map_err(|err| err.in_file(&page.path))?;
+        let tag = page.relative.with_extension("")
+            .components()
+            .map(|component| slugify(&component.as_os_str().to_string_lossy()))
+            .collect::<Vec<_>>()
+            .join("::");
+
+        for (_, deck) in decks {
+            for card in deck.prompts() {
+                cards.push(ExportedCard { tag: tag.clone(), card });
+            }
+        }
+    }
+
+    return Ok(cards);
+}
+
+/// Writes `cards` as a comma-separated file for Anki's text importer, using
+/// the built-in `Basic` and `Cloze` note types. Fields are HTML, with math
+/// in the `\(...\)` form Anki's MathJax expects.
+pub fn anki_csv(cards: &[ExportedCard]) -> String {
+    let mut csv = String::from("#separator:Comma\n#html:true\n#notetype column:1\n#tags column:4\n");
+    for exported in cards {
+        let note = AnkiNote::new(&exported.card);
+        let notetype = match note.cloze {
+            true => "Cloze",
+            false => "Basic",
+        };
+
+        let row = [notetype, &note.front, &note.back, &exported.tag]
+            .iter()
+            .map(|field| csv_quote(field))
+            .collect::<Vec<_>>()
+            .join(",");
+        csv.push_str(&row);
+        csv.push('\n');
+    }
+
+    return csv;
+}
+
+/// Writes `cards` as an Anki package: a collection of `Basic` and `Cloze`
+/// notes in a deck of their own, zipped with an empty media list. Fields
+/// are as in [`anki_csv`].
+pub fn anki_apkg(cards: &[ExportedCard]) -> Result<Vec<u8>> {
+    let path = std::env::temp_dir().join(format!("orbit-deck-{}.anki2", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let written = write_collection(&path, cards);
+    let collection = written.and_then(|_| std::fs::read(&path).map_err(OrbitError::io(&path)));
+    let _ = std::fs::remove_file(&path);
+
+    let mut archive = Archive::default();
+    archive.add("collection.anki2", &collection?)?;
+    archive.add("media", b"{}")?;
+
+    return archive.finish();
+}
+
+/// A card as an Anki note: its front and back fields, and whether it's a
+/// cloze, whose front holds `cards` numbered deletions.
+struct AnkiNote {
+    cloze: bool,
+    front: String,
+    back: String,
+    cards: usize,
+}
+
+impl AnkiNote {
+    fn new(card: &OrbitCard) -> AnkiNote {
+        if let Some(cloze) = card.cloze() {
+            let mut cards = 0;
+            let cloze = orbit::replace_deletions(cloze, |deletion| {
+                cards += 1;
+                format!("{{{{c{}::{}}}}}", cards, deletion)
+            });
+            return AnkiNote { cloze: true, front: anki_field(&cloze), back: String::new(), cards };
+        }
+
+        return AnkiNote {
+            cloze: false,
+            front: anki_field(card.question().unwrap_or_default()),
+            back: anki_field(card.answer().unwrap_or_default()),
+            cards: 1,
+        };
+    }
+}
+
+/// Renders a card field for Anki, leaving math for its MathJax.
+fn anki_field(field: &str) -> String {
+    let (markdown, math_spans) = math::extract_math(field);
+
+    return math::restore_math(&crate::render_inline(&markdown), &math_spans);
+}
+
+/// Creates the Anki collection at `path` and fills it with `cards`.
+fn write_collection(path: &Path, cards: &[ExportedCard]) -> Result<()> {
+    let now = Utc::now();
+    let (seconds, millis) = (now.timestamp(), now.timestamp_millis());
+    let mut db = Connection::open(path)?;
+    db.execute_batch(COLLECTION_SCHEMA)?;
+    db.execute(
+        "INSERT INTO col VALUES (1, ?1, ?2, ?2, 11, 0, 0, 0, ?3, ?4, ?5, ?6, '{}')",
+        params![
+            seconds - seconds % 86400,
+            millis,
+            collection_conf().to_string(),
+            models(seconds).to_string(),
+            decks(seconds).to_string(),
+            deck_conf().to_string(),
+        ],
+    )?;
+
+    let transaction = db.transaction()?;
+    let mut card_id = millis;
+    for (idx, exported) in cards.iter().enumerate() {
+        let note = AnkiNote::new(&exported.card);
+        let note_id = millis + idx as i64;
+        let model = if note.cloze { CLOZE_MODEL_ID } else { BASIC_MODEL_ID };
+        let fields = format!("{}\x1f{}", note.front, note.back);
+        let sort_field = HTML_TAG.replace_all(&note.front, "").into_owned();
+        let checksum = Sha1::digest(sort_field.as_bytes());
+        let checksum = u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+        // The same note in the same place keeps its guid from export to
+        // export, so Anki updates it in place.
+        let guid = &cache::hash_bytes(format!("{}\x1f{}", exported.tag, fields).as_bytes())[..10];
+        transaction.execute(
+            "INSERT INTO notes VALUES (?1, ?2, ?3, ?4, -1, ?5, ?6, ?7, ?8, 0, '')",
+            params![note_id, guid, model, seconds, format!(" {} ", exported.tag), fields, sort_field, checksum],
+        )?;
+        for ord in 0..note.cards {
+            transaction.execute(
+                "INSERT INTO cards VALUES (?1, ?2, ?3, ?4, ?5, -1, 0, 0, ?6, 0, 0, 0, 0, 0, 0, 0, 0, '')",
+                params![card_id, note_id, DECK_ID, ord as i64, seconds, idx as i64 + 1],
+            )?;
+            card_id += 1;
+        }
+    }
+    transaction.commit()?;
+
+    return Ok(());
+}
+
+fn collection_conf() -> serde_json::Value {
+    serde_json::json!({
+        "activeDecks": [DECK_ID],
+        "curDeck": DECK_ID,
+        "newSpread": 0,
+        "collapseTime": 1200,
+        "timeLim": 0,
+        "estTimes": true,
+        "dueCounts": true,
+        "curModel": BASIC_MODEL_ID,
+        "nextPos": 1,
+        "sortType": "noteFld",
+        "sortBackwards": false,
+        "addToCur": true,
+    })
+}
+
+/// The `Basic` and `Cloze` note types, as Anki ships them.
+fn models(modified: i64) -> serde_json::Value {
+    let field = |name: &str, ord: usize| serde_json::json!({
+        "name": name, "ord": ord, "sticky": false, "rtl": false, "font": "Arial", "size": 20, "media": [],
+    });
+    let model = |id: i64, name: &str, kind: usize, fields: [&str; 2], template: serde_json::Value, req: serde_json::Value| serde_json::json!({
+        "id": id,
+        "name": name,
+        "type": kind,
+        "mod": modified,
+        "usn": -1,
+        "sortf": 0,
+        "did": DECK_ID,
+        "tmpls": [template],
+        "flds": [field(fields[0], 0), field(fields[1], 1)],
+        "css": CARD_CSS,
+        "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\\setlength{\\parindent}{0in}\n\\begin{document}\n",
+        "latexPost": "\\end{document}",
+        "tags": [],
+        "vers": [],
+        "req": req,
+    });
+    let template = |name: &str, question: &str, answer: &str| serde_json::json!({
+        "name": name, "ord": 0, "qfmt": question, "afmt": answer, "did": null, "bqfmt": "", "bafmt": "",
+    });
+
+    return serde_json::json!({
+        BASIC_MODEL_ID.to_string(): model(
+            BASIC_MODEL_ID, "Basic", 0, ["Front", "Back"],
+            template("Card 1", "{{Front}}", "{{FrontSide}}\n\n<hr id=answer>\n\n{{Back}}"),
+            serde_json::json!([[0, "any", [0]]]),
+        ),
+        CLOZE_MODEL_ID.to_string(): model(
+            CLOZE_MODEL_ID, "Cloze", 1, ["Text", "Back Extra"],
+            template("Cloze", "{{cloze:Text}}", "{{cloze:Text}}<br>\n{{Back Extra}}"),
+            serde_json::json!([]),
+        ),
+    });
+}
+
+/// Anki's default deck, which every collection has, and the one the cards
+/// go in.
+fn decks(modified: i64) -> serde_json::Value {
+    let deck = |id: i64, name: &str| serde_json::json!({
+        "id": id,
+        "name": name,
+        "desc": "",
+        "mod": modified,
+        "usn": -1,
+        "collapsed": false,
+        "newToday": [0, 0],
+        "revToday": [0, 0],
+        "lrnToday": [0, 0],
+        "timeToday": [0, 0],
+        "dyn": 0,
+        "conf": 1,
+        "extendNew": 10,
+        "extendRev": 50,
+    });
+
+    return serde_json::json!({ "1": deck(1, "Default"), DECK_ID.to_string(): deck(DECK_ID, DECK_NAME) });
+}
+
+/// Anki's default deck options.
+fn deck_conf() -> serde_json::Value {
+    serde_json::json!({
+        "1": {
+            "id": 1,
+            "name": "Default",
+            "mod": 0,
+            "usn": 0,
+            "maxTaken": 60,
+            "autoplay": true,
+            "timer": 0,
+            "replayq": true,
+            "dyn": false,
+            "new": { "delays": [1, 10], "ints": [1, 4, 7], "initialFactor": 2500, "order": 1, "perDay": 20, "bury": true, "separate": true },
+            "rev": { "perDay": 100, "ease4": 1.3, "fuzz": 0.05, "minSpace": 1, "ivlFct": 1, "maxIvl": 36500, "bury": true, "hardFactor": 1.2 },
+            "lapse": { "delays": [10], "mult": 0, "minInt": 1, "leechFails": 8, "leechAction": 0 },
+        },
+    })
+}
+
+fn csv_quote(field: &str) -> String {
+    format!("\"{}\"", field.replace('"', "\"\""))
+}
@@ -0,0 +1,125 @@
+use std::path::{Path, PathBuf};
+
+use chrono::DateTime;
+use pulldown_cmark::escape::escape_html;
+
+use crate::config::Config;
+use crate::date::parse_date;
+use crate::listing::PageSummary;
+use crate::slug::slugify;
+use crate::urls;
+
+pub const FEED_FILENAME: &str = "feed.xml";
+
+/// Directory under the destination holding a feed per tag.
+pub const TAG_FEEDS_DIR: &str = "feeds";
+
+/// Where the feed of everything tagged `tag` is written.
+pub fn tag_feed_output(tag: &str) -> PathBuf {
+    Path::new(TAG_FEEDS_DIR).join(format!("{}.xml", slugify(tag)))
+}
+
+/// A page to include in the feed, with its rendered body if the feed carries
+/// full content.
+#[derive(Clone)]
+pub struct FeedEntry<'a> {
+    pub summary: &'a PageSummary,
+    /// The page's URL relative to the site root.
+    pub link: String,
+    pub content: Option<String>,
+    /// When the page was last committed, if dated from git.
+    pub updated: Option<String>,
+}
+
+/// Converts a frontmatter date in any format [`parse_date`] understands to
+/// the RFC 3339 timestamp Atom requires. Other dates can't go in a feed.
+pub fn feed_date(date: &str) -> Option<String> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+        return Some(date.to_rfc3339());
+    }
+
+    return parse_date(date).map(|date| date.format("%Y-%m-%dT%H:%M:%SZ").to_string());
+}
+
+/// When `entry` last changed: its last commit if known, or else its date.
+fn entry_updated(entry: &FeedEntry) -> Option<String> {
+    entry.updated.as_deref()
+        .or(entry.summary.date.as_deref())
+        .and_then(feed_date)
+}
+
+/// Builds an Atom feed titled `title` of `entries`, which must all have a
+/// date [`feed_date`] understands, with links made absolute against
+/// `base_url`. The feed is written to `output` and stands for the page at
+/// `page`, both relative to the site root.
+pub fn atom_feed(config: &Config, base_url: &str, title: &str, output: &Path, page: &str, entries: &[FeedEntry]) -> String {
+    let home = urls::absolute_url(base_url, page);
+    let updated = entries.iter()
+        .filter_map(entry_updated)
+        .max_by_key(|date| DateTime::parse_from_rfc3339(date).ok())
+        .unwrap_or_else(|| "1970-01-01T00:00:00Z".to_string());
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    push_element(&mut xml, "  ", "title", title);
+    if let Some(description) = &config.description {
+        push_element(&mut xml, "  ", "subtitle", description);
+    }
+    push_element(&mut xml, "  ", "id", &home);
+    push_element(&mut xml, "  ", "updated", &updated);
+    push_link(&mut xml, "  ", &home, None);
+    push_link(&mut xml, "  ", &urls::absolute_url(base_url, &output.to_string_lossy().replace('\\', "/")), Some("self"));
+    if let Some(author) = &config.author {
+        xml.push_str("  <author>\n");
+        push_element(&mut xml, "    ", "name", author);
+        xml.push_str("  </author>\n");
+    }
+
+    for entry in entries {
+        let url = urls::absolute_url(base_url, &entry.link);
+        let published = entry.summary.date.as_deref().and_then(feed_date);
+        let date = entry_updated(entry).unwrap_or_else(|| updated.clone());
+
+        xml.push_str("  <entry>\n");
+        push_element(&mut xml, "    ", "title", &entry.summary.title);
+        push_element(&mut xml, "    ", "id", &url);
+        push_link(&mut xml, "    ", &url, None);
+        push_element(&mut xml, "    ", "updated", &date);
+        if let Some(published) = published.filter(|published| *published != date) {
+            push_element(&mut xml, "    ", "published", &published);
+        }
+        for tag in &entry.summary.tags {
+            xml.push_str("    <category term=\"");
+            let _ = escape_html(&mut xml, tag);
+            xml.push_str("\"/>\n");
+        }
+        if let Some(content) = &entry.content {
+            // Relative links in the body resolve against the page itself.
+            xml.push_str("    <content type=\"html\" xml:base=\"");
+            let _ = escape_html(&mut xml, &url);
+            xml.push_str("\">");
+            let _ = escape_html(&mut xml, content);
+            xml.push_str("</content>\n");
+        }
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+
+    return xml;
+}
+
+fn push_element(xml: &mut String, indent: &str, name: &str, text: &str) {
+    xml.push_str(&format!("{}<{}>", indent, name));
+    let _ = escape_html(&mut *xml, text);
+    xml.push_str(&format!("</{}>\n", name));
+}
+
+fn push_link(xml: &mut String, indent: &str, href: &str, rel: Option<&str>) {
+    xml.push_str(indent);
+    xml.push_str("<link href=\"");
+    let _ = escape_html(&mut *xml, href);
+    xml.push('"');
+    if let Some(rel) = rel {
+        xml.push_str(&format!(" rel=\"{}\"", rel));
+    }
+    xml.push_str("/>\n");
+}
@@ -0,0 +1,336 @@
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag};
+use serde::{Serialize, Deserialize};
+
+use crate::date;
+use crate::error::{self, OrbitError, Result};
+use crate::wikilink;
+
+const YAML_DELIMITER: &str = "---";
+const TOML_DELIMITER: &str = "+++";
+const JSON_START: &str = "{";
+
+/// Every key read from frontmatter, besides those only templates use, and
+/// the type its value must have. `created` and `updated` override the dates
+/// from git.
+const KNOWN_KEYS: &[(&str, Kind)] = &[
+    ("title", Kind::Text),
+    ("date", Kind::Date),
+    ("created", Kind::Date),
+    ("updated", Kind::Date),
+    ("author", Kind::Text),
+    ("tags", Kind::List),
+    ("aliases", Kind::List),
+    ("series", Kind::Text),
+    ("series_part", Kind::Count),
+    ("description", Kind::Text),
+    ("image", Kind::Text),
+    ("layout", Kind::Text),
+    ("draft", Kind::Bool),
+    ("toc", Kind::Bool),
+    ("sidenotes", Kind::Bool),
+    ("inline_cloze", Kind::Bool),
+];
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Frontmatter {
+    pub title: Option<String>,
+    pub date: Option<String>,
+    pub author: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Paths the page used to be at, relative to the site root, such as
+    /// `old-name.html`, which get pages redirecting to it.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// Name of the series of pages, such as a multi-part essay, this page
+    /// belongs to.
+    pub series: Option<String>,
+    /// Where the page comes in its series. Pages without one follow those
+    /// with one, by date and then path.
+    pub series_part: Option<u64>,
+    /// One-line summary for link previews. The start of the page's first
+    /// paragraph is used when it's missing.
+    pub description: Option<String>,
+    /// Image for link previews, relative to the page or an absolute URL.
+    pub image: Option<String>,
+    /// Name of the layout in the configured layouts directory to render this
+    /// page with, instead of the default page template.
+    pub layout: Option<String>,
+    /// Leave the page out of builds unless drafts are asked for.
+    #[serde(default)]
+    pub draft: bool,
+    /// Put a table of contents at the top of the page.
+    #[serde(default)]
+    pub toc: bool,
+    /// Render footnotes as Tufte sidenotes in the margin, overriding the
+    /// site-wide `sidenotes` setting for this page.
+    pub sidenotes: Option<bool>,
+    /// Turn highlighted spans into cloze prompts, overriding the site-wide
+    /// `inline_cloze` setting for this page.
+    pub inline_cloze: Option<bool>,
+    /// Whether `title` was taken from the page's first level-1 heading, as
+    /// its frontmatter has none.
+    #[serde(skip)]
+    pub inferred_title: bool,
+    /// Any keys not listed above, passed through to the template as-is.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Frontmatter {
+    /// The keys set in frontmatter that orbit doesn't read, which only a
+    /// template could use.
+    pub fn unknown_keys(&self) -> impl Iterator<Item = &str> {
+        self.extra.keys()
+            .map(String::as_str)
+            .filter(|key| KNOWN_KEYS.iter().all(|(known, _)| known != key))
+    }
+}
+
+/// The known key most like `key`, if it's close enough to be a typo.
+pub fn suggest_key(key: &str) -> Option<&'static str> {
+    KNOWN_KEYS.iter()
+        .map(|(known, _)| (edit_distance(key, known), *known))
+        .filter(|(distance, known)| *distance <= 2 && *distance < known.len())
+        .min()
+        .map(|(_, known)| known)
+}
+
+/// Splits a markdown document into its frontmatter and the remaining content.
+///
+/// Frontmatter is YAML between a `---` on the very first line and the next
+/// line consisting solely of `---`, TOML fenced the same way with `+++`, or
+/// a JSON object opened by a `{` alone on the first line, as in Hugo.
+/// Documents without frontmatter are returned untouched alongside a default
+/// `Frontmatter`. Known keys of the wrong type are left out, and a missing
+/// title is taken from the first level-1 heading.
+pub fn split_frontmatter(markdown: &str) -> Result<(Frontmatter, &str)> {
+    let (frontmatter, content, _) = split_frontmatter_with_warnings(markdown)?;
+
+    return Ok((frontmatter, content));
+}
+
+/// Splits a markdown document like [`split_frontmatter`], also returning a
+/// warning for each known key left out for having the wrong type.
+pub fn split_frontmatter_with_warnings(markdown: &str) -> Result<(Frontmatter, &str, Vec<OrbitError>)> {
+    let first_line_end = markdown.find('\n').unwrap_or(markdown.len());
+    let fenced = match markdown[..first_line_end].trim_end() {
+        YAML_DELIMITER => split_fenced(markdown, YAML_DELIMITER).map(|(yaml, content)| (parse_yaml(yaml), content)),
+        TOML_DELIMITER => split_fenced(markdown, TOML_DELIMITER).map(|(toml, content)| (parse_toml(toml), content)),
+        JSON_START => Some(split_json(markdown)),
+        _ => None,
+    };
+
+    let (value, content) = match fenced {
+        Some((value, content)) => (value?, content),
+        // An opening delimiter without a closing one isn't frontmatter at all.
+        None => (serde_json::Value::Null, markdown),
+    };
+    let (mut frontmatter, warnings) = from_value(value)?;
+    if frontmatter.title.is_none() {
+        frontmatter.title = first_heading(content);
+        frontmatter.inferred_title = frontmatter.title.is_some();
+    }
+
+    return Ok((frontmatter, content, warnings));
+}
+
+/// The text of the first level-1 heading in `content`, with wikilinks
+/// replaced by their labels.
+fn first_heading(content: &str) -> Option<String> {
+    let options = Options::ENABLE_HEADING_ATTRIBUTES | Options::ENABLE_SMART_PUNCTUATION;
+    let mut events = Parser::new_ext(content, options)
+        .skip_while(|event| !matches!(event, Event::Start(Tag::Heading(HeadingLevel::H1, ..))));
+    events.next()?;
+
+    let mut title = String::new();
+    for event in events {
+        match event {
+            Event::Text(text) | Event::Code(text) => title.push_str(&text),
+            Event::End(Tag::Heading(..)) => break,
+            _ => {}
+        }
+    }
+    let title = wikilink::strip_wikilinks(title.trim());
+
+    return (!title.is_empty()).then_some(title);
+}
+
+/// Splits `markdown`, whose first line is `delimiter`, into the text
+/// between that and the next line consisting solely of `delimiter`, and the
+/// content after, if there is such a line.
+fn split_fenced<'a>(markdown: &'a str, delimiter: &str) -> Option<(&'a str, &'a str)> {
+    let start = (markdown.find('\n')? + 1).min(markdown.len());
+    let mut offset = start;
+    for line in markdown[start..].split_inclusive('\n') {
+        if line.trim_end() == delimiter {
+            return Some((&markdown[start..offset], &markdown[(offset + line.len())..]));
+        }
+
+        offset += line.len();
+    }
+
+    return None;
+}
+
+/// Splits `markdown`, whose first line is `{`, after the JSON object it
+/// opens, dropping the rest of the line the object ends on.
+fn split_json(markdown: &str) -> (Result<serde_json::Value>, &str) {
+    let mut objects = serde_json::Deserializer::from_str(markdown).into_iter::<serde_json::Value>();
+    let value = match objects.next() {
+        Some(Ok(value)) => Ok(value),
+        Some(Err(err)) => {
+            let (line, column) = error::line_column(markdown, error::byte_offset(markdown, err.line(), err.column()));
+            Err(OrbitError::frontmatter(line, column, &err.to_string()))
+        }
+        None => Ok(serde_json::Value::Null),
+    };
+    let end = objects.byte_offset();
+    let content_start = markdown[end..].find('\n').map_or(markdown.len(), |newline| end + newline + 1);
+
+    return (value, &markdown[content_start..]);
+}
+
+fn parse_yaml(yaml: &str) -> Result<serde_json::Value> {
+    if yaml.trim().is_empty() {
+        return Ok(serde_json::Value::Null);
+    }
+
+    return serde_yaml::from_str(yaml).map_err(|err| {
+        // The YAML starts on the line after the opening delimiter.
+        let (line, column) = err.location()
+            .map(|location| (location.line() + 1, location.column()))
+            .unwrap_or((1, 1));
+
+        OrbitError::frontmatter(line, column, &err.to_string())
+    });
+}
+
+fn parse_toml(toml: &str) -> Result<serde_json::Value> {
+    let table: toml::Table = toml.parse().map_err(|err: toml::de::Error| {
+        // The TOML starts on the line after the opening delimiter.
+        let offset = err.span().map_or(0, |span| span.start);
+        let (line, column) = error::line_column(toml, offset);
+        OrbitError::frontmatter(line + 1, column, err.message())
+    })?;
+
+    // TOML dates and times are their own type, which the template sees as
+    // the strings written.
+    return Ok(toml_to_json(toml::Value::Table(table)));
+}
+
+/// Reads the frontmatter from parsed YAML, TOML, or JSON, leaving out the
+/// known keys of the wrong type with a warning for each. Keys set to
+/// nothing are as good as missing.
+fn from_value(value: serde_json::Value) -> Result<(Frontmatter, Vec<OrbitError>)> {
+    let mut map = match value {
+        serde_json::Value::Object(map) => map,
+        serde_json::Value::Null => return Ok((Frontmatter::default(), Vec::new())),
+        _ => return Err(OrbitError::frontmatter(1, 1, "expected keys and values")),
+    };
+
+    let mut warnings = Vec::new();
+    for (key, kind) in KNOWN_KEYS {
+        let value = match map.get_mut(*key) {
+            Some(serde_json::Value::Null) => {
+                map.remove(*key);
+                continue;
+            }
+            Some(value) => value,
+            None => continue,
+        };
+        if let Err(message) = kind.check(value) {
+            warnings.push(OrbitError::FrontmatterKey { key: key.to_string(), message });
+            if !value.is_string() || *kind != Kind::Date {
+                map.remove(*key);
+            }
+        }
+    }
+
+    let frontmatter = serde_json::from_value(map.into())
+        .map_err(|err| OrbitError::frontmatter(1, 1, &err.to_string()))?;
+
+    return Ok((frontmatter, warnings));
+}
+
+/// Converts a TOML value to JSON, writing dates and times as strings.
+fn toml_to_json(value: toml::Value) -> serde_json::Value {
+    match value {
+        toml::Value::String(string) => string.into(),
+        toml::Value::Integer(integer) => integer.into(),
+        toml::Value::Float(float) => float.into(),
+        toml::Value::Boolean(boolean) => boolean.into(),
+        toml::Value::Datetime(datetime) => datetime.to_string().into(),
+        toml::Value::Array(array) => array.into_iter().map(toml_to_json).collect(),
+        toml::Value::Table(table) => table.into_iter().map(|(key, value)| (key, toml_to_json(value))).collect(),
+    }
+}
+
+/// The type a known frontmatter key's value must have.
+#[derive(Debug, PartialEq, Eq)]
+enum Kind {
+    Text,
+    /// Text that [`date::parse_date`] understands.
+    Date,
+    Bool,
+    /// A list of text.
+    List,
+    /// A whole number, zero or more.
+    Count,
+}
+
+impl Kind {
+    /// Checks `value` is of this kind, turning numbers into the text they
+    /// were written as where text is wanted.
+    fn check(&self, value: &mut serde_json::Value) -> std::result::Result<(), String> {
+        if let (Kind::Text, serde_json::Value::Number(number)) = (self, &value) {
+            *value = number.to_string().into();
+        }
+        let found = match value {
+            serde_json::Value::String(_) if *self == Kind::Text => return Ok(()),
+            serde_json::Value::String(text) if *self == Kind::Date => match date::parse_date(text) {
+                Some(_) => return Ok(()),
+                None => return Err(format!("`{}` isn't a date orbit understands, such as 2024-03-05", text)),
+            },
+            serde_json::Value::Bool(_) if *self == Kind::Bool => return Ok(()),
+            serde_json::Value::Number(number) if *self == Kind::Count && number.is_u64() => return Ok(()),
+            serde_json::Value::Array(items) if *self == Kind::List => match items.iter().all(|item| item.is_string()) {
+                true => return Ok(()),
+                false => "a list with more than text in it",
+            },
+            serde_json::Value::String(_) => "text",
+            serde_json::Value::Number(_) if *self == Kind::Count => "a negative or fractional number",
+            serde_json::Value::Number(_) => "a number",
+            serde_json::Value::Bool(_) => "true or false",
+            serde_json::Value::Array(_) => "a list",
+            serde_json::Value::Object(_) => "keys and values",
+            serde_json::Value::Null => "nothing",
+        };
+        let expected = match self {
+            Kind::Text => "text",
+            Kind::Date => "a date",
+            Kind::Bool => "true or false",
+            Kind::List => "a list of text, such as [one, two]",
+            Kind::Count => "a whole number, such as 2",
+        };
+
+        return Err(format!("expected {}, found {}", expected, found));
+    }
+}
+
+/// How many single-character insertions, deletions, or substitutions turn
+/// `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    return previous[b.len()];
+}
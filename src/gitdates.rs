@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Marks the lines of `git log` output carrying a commit's date, as opposed
+/// to the files it touched.
+const DATE_MARKER: char = '\0';
+
+/// When a file was first and last committed, as RFC 3339 timestamps.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FileDates {
+    pub created: String,
+    pub updated: String,
+}
+
+/// The commit dates of every file under a directory, from git history rather
+/// than modification times, which a fresh clone resets.
+#[derive(Debug, Default)]
+pub struct GitDates {
+    /// Keyed by path relative to the directory the history was read for.
+    dates: HashMap<PathBuf, FileDates>,
+}
+
+impl GitDates {
+    /// Reads the history of every file under `directory` with `git log`.
+    /// Outside a repository, or without git installed, no file has dates.
+    pub fn load(directory: &Path) -> GitDates {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(directory)
+            .args(["-c", "core.quotePath=false", "log", "--format=%x00%aI", "--name-only", "--relative", "--no-renames", "--", "."])
+            .output();
+        let log = match output {
+            Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout).into_owned(),
+            _ => return GitDates::default(),
+        };
+
+        return GitDates::parse(&log);
+    }
+
+    /// The dates of the file at `relative`, if it has been committed.
+    pub fn get(&self, relative: &Path) -> Option<&FileDates> {
+        self.dates.get(relative)
+    }
+
+    /// Parses `git log` output, newest commit first, in which each commit is
+    /// a [`DATE_MARKER`] line with its date followed by the files it touched.
+    fn parse(log: &str) -> GitDates {
+        let mut dates: HashMap<PathBuf, FileDates> = HashMap::new();
+        let mut date: Option<&str> = None;
+        for line in log.lines() {
+            if let Some(commit_date) = line.strip_prefix(DATE_MARKER) {
+                date = Some(commit_date.trim());
+                continue;
+            }
+            let date = match date {
+                Some(date) if !line.is_empty() => date,
+                _ => continue,
+            };
+
+            dates.entry(PathBuf::from(line))
+                .and_modify(|dates| dates.created = date.to_string())
+                .or_insert_with(|| FileDates { created: date.to_string(), updated: date.to_string() });
+        }
+
+        return GitDates { dates };
+    }
+}
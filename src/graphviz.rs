@@ -0,0 +1,41 @@
+use std::io::{ErrorKind, Write};
+use std::process::{Command, Stdio};
+
+/// Languages of the fenced blocks laid out with Graphviz.
+pub const LANGUAGES: &[&str] = &["dot", "graphviz"];
+
+/// Lays out the DOT graph `source` with the system `dot`, returning the SVG
+/// wrapped in a figure to inline in the page. The graph's `layout`
+/// attribute picks another engine, such as `neato`.
+pub fn render_svg(source: &str) -> Result<String, String> {
+    let mut child = Command::new("dot")
+        .arg("-Tsvg")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| match err.kind() {
+            ErrorKind::NotFound => String::from("Graphviz isn't installed"),
+            _ => err.to_string(),
+        })?;
+    // The graph is written from another thread so a large one can't fill
+    // the pipe while `dot` is blocked writing warnings nobody's reading.
+    if let Some(mut stdin) = child.stdin.take() {
+        let source = source.to_string();
+        std::thread::spawn(move || stdin.write_all(source.as_bytes()));
+    }
+    let output = child.wait_with_output().map_err(|err| err.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+
+    let svg = String::from_utf8_lossy(&output.stdout);
+    // The XML declaration, doctype, and comments before the `<svg>` are
+    // only wanted in a standalone file.
+    let svg = match svg.find("<svg") {
+        Some(start) => &svg[start..],
+        None => return Err(String::from("dot wrote no SVG")),
+    };
+
+    return Ok(format!("<figure class=\"diagram\">\n{}</figure>\n", svg));
+}
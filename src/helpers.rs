@@ -0,0 +1,95 @@
+use chrono::{DateTime, NaiveDate};
+use handlebars::{
+    handlebars_helper, Context, Handlebars, Helper, HelperResult, Output, RenderContext, RenderError,
+};
+use pulldown_cmark::escape::escape_html;
+use pulldown_cmark::{Options, Parser};
+use regex::Regex;
+
+use crate::{slug, urls};
+
+lazy_static::lazy_static! {
+    static ref SCHEME: Regex = Regex::new(r"^[A-Za-z][A-Za-z0-9+.-]*:").unwrap();
+}
+
+const DEFAULT_DATE_FORMAT: &str = "%B %-d, %Y";
+
+handlebars_helper!(date_format: |date: str, { format: str = "" }| format_date(date, format));
+handlebars_helper!(slugify: |text: str| slug::slugify(text));
+handlebars_helper!(truncate: |text: str, length: u64| truncate_chars(text, length as usize));
+handlebars_helper!(markdown: |text: str| render_markdown(text));
+
+/// Registers the built-in helpers available to every template:
+///
+/// - `{{date_format date format="%Y"}}` reformats a `YYYY-MM-DD` or RFC 3339
+///   date with a strftime-style format, defaulting to `July 4, 2023`.
+/// - `{{slugify text}}` turns text into a URL-safe slug.
+/// - `{{truncate text 80}}` shortens text to at most that many characters.
+/// - `{{{markdown text}}}` renders a markdown string to HTML, in triple
+///   braces so the HTML isn't escaped.
+/// - `{{absolute_url path}}` turns a path relative to the site root into an
+///   absolute URL under `base_url`, or a relative one if there isn't one.
+pub fn register(registry: &mut Handlebars) {
+    registry.register_helper("date_format", Box::new(date_format));
+    registry.register_helper("slugify", Box::new(slugify));
+    registry.register_helper("truncate", Box::new(truncate));
+    registry.register_helper("markdown", Box::new(markdown));
+    registry.register_helper("absolute_url", Box::new(absolute_url));
+}
+
+fn absolute_url(
+    helper: &Helper,
+    _: &Handlebars,
+    context: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let path = helper.param(0)
+        .and_then(|param| param.value().as_str())
+        .ok_or_else(|| RenderError::new("absolute_url expects a path"))?;
+    let data = context.data();
+
+    let url = match data["site"]["base_url"].as_str() {
+        _ if SCHEME.is_match(path) => path.to_string(),
+        Some(base_url) => urls::absolute_url(base_url, path),
+        None => format!("{}{}", data["root"].as_str().unwrap_or(""), path.trim_start_matches('/')),
+    };
+    let mut escaped = String::new();
+    let _ = escape_html(&mut escaped, &url);
+    out.write(&escaped)?;
+
+    return Ok(());
+}
+
+/// Formats `date` with `format`, returning it untouched if it isn't a
+/// recognisable date.
+fn format_date(date: &str, format: &str) -> String {
+    let format = if format.is_empty() { DEFAULT_DATE_FORMAT } else { format };
+
+    if let Ok(date) = NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        return date.format(format).to_string();
+    }
+    if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+        return date.format(format).to_string();
+    }
+
+    return date.to_string();
+}
+
+fn truncate_chars(text: &str, length: usize) -> String {
+    match text.char_indices().nth(length) {
+        Some((idx, _)) => format!("{}…", text[..idx].trim_end()),
+        None => text.to_string(),
+    }
+}
+
+fn render_markdown(text: &str) -> String {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_SMART_PUNCTUATION);
+
+    let mut html_output = String::new();
+    pulldown_cmark::html::push_html(&mut html_output, Parser::new_ext(text, options));
+
+    return html_output;
+}
@@ -0,0 +1,55 @@
+use pulldown_cmark::escape::escape_html;
+use syntect::highlighting::ThemeSet;
+use syntect::html::{ClassedHTMLGenerator, ClassStyle};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+use crate::error::{OrbitError, Result};
+
+pub const DEFAULT_THEME: &str = "InspiredGitHub";
+pub const CSS_FILENAME: &str = "syntax.css";
+
+/// Highlighted spans get classes like `hl-source hl-rust`; the prefix keeps
+/// them from colliding with the stylesheet's own classes.
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+lazy_static::lazy_static! {
+    static ref SYNTAXES: SyntaxSet = SyntaxSet::load_defaults_newlines();
+    static ref THEMES: ThemeSet = ThemeSet::load_defaults();
+}
+
+/// Renders a fenced code block as `<pre><code>`, with highlighted spans if
+/// `language` is one syntect recognises and plain escaped text otherwise.
+pub fn highlight_code(language: &str, code: &str) -> String {
+    let mut html = String::from("<pre class=\"code\"><code class=\"language-");
+    let _ = escape_html(&mut html, language);
+    html.push_str("\">");
+
+    match SYNTAXES.find_syntax_by_token(language) {
+        Some(syntax) => {
+            let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, &SYNTAXES, CLASS_STYLE);
+            for line in LinesWithEndings::from(code) {
+                // Only fails on a malformed syntax definition, and the bundled
+                // ones are well-formed.
+                let _ = generator.parse_html_for_line_which_includes_newline(line);
+            }
+            html.push_str(&generator.finalize());
+        }
+        None => {
+            let _ = escape_html(&mut html, code);
+        }
+    }
+
+    html.push_str("</code></pre>\n");
+
+    return html;
+}
+
+/// The stylesheet colouring highlighted code with the named bundled theme.
+pub fn theme_css(theme: &str) -> Result<String> {
+    let theme = THEMES.themes.get(theme)
+        .ok_or_else(|| OrbitError::UnknownTheme(theme.to_string()))?;
+
+    return syntect::html::css_for_theme_with_class_style(theme, CLASS_STYLE)
+        .map_err(|err| OrbitError::UnknownTheme(err.to_string()));
+}
@@ -0,0 +1,38 @@
+use std::ffi::OsStr;
+use std::io;
+use std::process::{Command, Stdio};
+
+use crate::error::{OrbitError, Result};
+
+/// Runs the `[hooks]` command `command` in the shell, with `env` set on top
+/// of Orbit's own environment. Its output goes to stderr, keeping stdout
+/// for what `orbit` prints itself.
+pub fn run_hook(hook: &'static str, command: &str, env: &[(&str, &OsStr)]) -> Result<()> {
+    let mut shell = match cfg!(windows) {
+        true => {
+            let mut shell = Command::new("cmd");
+            shell.arg("/C");
+            shell
+        }
+        false => {
+            let mut shell = Command::new("sh");
+            shell.arg("-c");
+            shell
+        }
+    };
+    shell.arg(command).stdout(Stdio::from(io::stderr()));
+    for (name, value) in env {
+        shell.env(name, value);
+    }
+
+    let status = shell.status().map_err(|err| OrbitError::Hook { hook, message: err.to_string() })?;
+    if !status.success() {
+        let message = match status.code() {
+            Some(code) => format!("`{}` exited with status {}", command, code),
+            None => format!("`{}` was killed", command),
+        };
+        return Err(OrbitError::Hook { hook, message });
+    }
+
+    return Ok(());
+}
@@ -0,0 +1,138 @@
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::error::{OrbitError, Result};
+
+/// File in the source directory listing patterns to leave out of the site.
+pub const IGNORE_FILENAME: &str = ".orbitignore";
+
+/// Files and directories under the source to leave out of the site, matched
+/// by gitignore-style glob patterns: `*` and `?` match within a path
+/// component, `**` across them, and `[abc]` any listed character. A pattern
+/// without a `/` matches a name at any depth, one ending in `/` only
+/// directories, and one starting with `!` takes a path back in.
+#[derive(Debug, Default)]
+pub struct IgnoreRules {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    regex: Regex,
+    negated: bool,
+    directory_only: bool,
+}
+
+impl IgnoreRules {
+    /// Reads the patterns in `source`'s `.orbitignore`, if there is one,
+    /// followed by `patterns`, so the latter win.
+    pub fn load(source: &Path, patterns: &[String]) -> Result<IgnoreRules> {
+        let path = source.join(IGNORE_FILENAME);
+        let file = match path.exists() {
+            true => std::fs::read_to_string(&path).map_err(OrbitError::io(&path))?,
+            false => String::new(),
+        };
+
+        let mut rules = Vec::new();
+        let lines = file.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#'));
+        for pattern in lines.chain(patterns.iter().map(String::as_str)) {
+            rules.push(Rule::new(pattern)?);
+        }
+
+        return Ok(IgnoreRules { rules });
+    }
+
+    /// Whether the file or directory at `relative`, within the source, is
+    /// left out, by its own path or any of its parent directories'.
+    pub fn is_ignored(&self, relative: &Path, is_dir: bool) -> bool {
+        if self.rules.is_empty() {
+            return false;
+        }
+
+        return relative.ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .enumerate()
+            .any(|(idx, ancestor)| self.matches(ancestor, is_dir || idx > 0));
+    }
+
+    /// Whether the last rule matching `relative` itself ignores it.
+    fn matches(&self, relative: &Path, is_dir: bool) -> bool {
+        let path = relative.to_string_lossy().replace('\\', "/");
+        let rule = self.rules.iter()
+            .rev()
+            .find(|rule| (is_dir || !rule.directory_only) && rule.regex.is_match(&path));
+
+        return rule.is_some_and(|rule| !rule.negated);
+    }
+}
+
+impl Rule {
+    fn new(pattern: &str) -> Result<Rule> {
+        let (negated, glob) = match pattern.strip_prefix('!') {
+            Some(glob) => (true, glob),
+            None => (false, pattern),
+        };
+        let (directory_only, glob) = match glob.strip_suffix('/') {
+            Some(glob) => (true, glob),
+            None => (false, glob),
+        };
+        // A pattern with a slash before its end is relative to the source
+        // directory; one without matches a name at any depth.
+        let anchored = glob.contains('/');
+        let glob = glob.strip_prefix('/').unwrap_or(glob);
+
+        let mut regex = String::from("^");
+        if !anchored {
+            regex.push_str("(?:.*/)?");
+        }
+        regex.push_str(&glob_regex(glob));
+        regex.push('$');
+        let regex = Regex::new(&regex).map_err(|err| OrbitError::IgnorePattern {
+            pattern: pattern.to_string(),
+            message: err.to_string(),
+        })?;
+
+        return Ok(Rule { regex, negated, directory_only });
+    }
+}
+
+/// Translates a glob into the regex syntax matching the same paths.
+fn glob_regex(glob: &str) -> String {
+    let mut regex = String::new();
+    let mut chars = glob.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '*' if glob[idx..].starts_with("**/") => {
+                regex.push_str("(?:.*/)?");
+                chars.next();
+                chars.next();
+            }
+            '*' if glob[idx..].starts_with("**") => {
+                regex.push_str(".*");
+                chars.next();
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' if glob[idx..].contains(']') => {
+                let end = idx + glob[idx..].find(']').unwrap();
+                let class = &glob[(idx + 1)..end];
+                regex.push('[');
+                match class.strip_prefix('!') {
+                    Some(class) => {
+                        regex.push('^');
+                        regex.push_str(&class.replace('\\', "\\\\"));
+                    }
+                    None => regex.push_str(&class.replace('\\', "\\\\")),
+                }
+                regex.push(']');
+                while chars.peek().is_some_and(|(next, _)| *next <= end) {
+                    chars.next();
+                }
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    return regex;
+}
@@ -0,0 +1,221 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use pulldown_cmark::escape::{escape_href, escape_html};
+use regex::{Captures, Regex};
+
+use crate::config::ImageOptions;
+use crate::error::{OrbitError, Result};
+use crate::urls::{normalize, percent_decode, unescape};
+
+lazy_static::lazy_static! {
+    static ref IMG_SRC: Regex = Regex::new(r#"<img src="([^"]*)""#).unwrap();
+    static ref SCHEME: Regex = Regex::new(r"^[A-Za-z][A-Za-z0-9+.-]*:").unwrap();
+}
+
+/// Image formats resized into responsive variants. GIFs would lose their
+/// animation and SVGs don't need it.
+const RESIZABLE: &[&str] = &["jpg", "jpeg", "png", "webp"];
+
+/// ImageMagick's command, under its version 7 name and then its older one.
+const RESIZE_COMMANDS: &[&str] = &["magick", "convert"];
+
+/// Gives every local JPEG, PNG, and WebP `<img>` in `html` a `srcset` of
+/// variants resized to the configured widths, which `write_variant` is
+/// asked to write beside the original, given the image and variant paths
+/// and the width. `relative` is the page's path within the `source`
+/// directory, which image paths are resolved against. Images that can't be
+/// resized are left as they are, with a warning.
+pub fn add_srcsets<F>(
+    html: &str,
+    relative: &Path,
+    source: &Path,
+    options: &ImageOptions,
+    mut write_variant: F,
+    warnings: &mut Vec<OrbitError>,
+) -> String
+where
+    F: FnMut(&Path, &Path, u32) -> Result<()>,
+{
+    if options.widths.is_empty() {
+        return html.to_string();
+    }
+
+    let rewritten = IMG_SRC.replace_all(html, |captures: &Captures| {
+        let src = unescape(&captures[1]);
+        if src.is_empty() || src.starts_with(['/', '#']) || SCHEME.is_match(&src) {
+            return captures[0].to_string();
+        }
+        let url_path = &src[..src.find(['?', '#']).unwrap_or(src.len())];
+        let image = match normalize(&relative.parent().unwrap_or_else(|| Path::new("")).join(percent_decode(url_path))) {
+            Some(image) if is_resizable(&image) => image,
+            _ => return captures[0].to_string(),
+        };
+        let width = match dimensions(&source.join(&image)) {
+            Some((width, _)) => width,
+            None => return captures[0].to_string(),
+        };
+
+        let mut widths: Vec<u32> = options.widths.iter().copied().filter(|&variant| variant < width).collect();
+        widths.sort_unstable();
+        widths.dedup();
+        // The srcset is built from the escaped attribute value, as its
+        // descriptors must stay apart from the URLs.
+        let escaped = &captures[1];
+        let escaped_path = &escaped[..escaped.find(['?', '#']).unwrap_or(escaped.len())];
+        let directory = &escaped_path[..escaped_path.rfind('/').map_or(0, |slash| slash + 1)];
+        let mut srcset = String::new();
+        for variant in widths {
+            let variant_image = variant_path(&image, variant);
+            if let Err(err) = write_variant(&image, &variant_image, variant) {
+                warnings.push(err);
+                break;
+            }
+            srcset.push_str(directory);
+            let _ = escape_href(&mut srcset, &variant_image.file_name().unwrap_or_default().to_string_lossy());
+            srcset.push_str(&format!(" {}w, ", variant));
+        }
+        if srcset.is_empty() {
+            return captures[0].to_string();
+        }
+        srcset.push_str(&format!("{} {}w", escaped_path, width));
+
+        let mut img = format!("{} srcset=\"{}\" sizes=\"", &captures[0], srcset);
+        let _ = escape_html(&mut img, &options.sizes);
+        img.push('"');
+        img
+    });
+
+    return rewritten.into_owned();
+}
+
+/// The width and height in pixels of the PNG, GIF, JPEG, or WebP image at
+/// `path`, read from its header. Anything else, or an unreadable file, has
+/// no dimensions.
+pub fn dimensions(path: &Path) -> Option<(u32, u32)> {
+    let bytes = std::fs::read(path).ok()?;
+
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some((be_u32(&bytes, 16)?, be_u32(&bytes, 20)?));
+    }
+    if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        return Some((le_u16(&bytes, 6)? as u32, le_u16(&bytes, 8)? as u32));
+    }
+    if bytes.starts_with(b"\xff\xd8") {
+        return jpeg_dimensions(&bytes);
+    }
+    if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        return webp_dimensions(&bytes);
+    }
+
+    return None;
+}
+
+/// Whether the image at `path` can be resized into responsive variants.
+pub fn is_resizable(path: &Path) -> bool {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .is_some_and(|extension| RESIZABLE.contains(&extension.as_str()))
+}
+
+/// Where the variant of the image at `path` resized to `width` goes, beside
+/// it: `photo.jpg` becomes `photo-480w.jpg`.
+pub fn variant_path(path: &Path, width: u32) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}-{}w.{}", stem, width, extension.to_string_lossy()),
+        None => format!("{}-{}w", stem, width),
+    };
+
+    return path.with_file_name(name);
+}
+
+/// Writes the image at `source` scaled down to `width` pixels wide to
+/// `destination` with ImageMagick, unless a variant at least as new as the
+/// source is already there.
+pub fn resize(source: &Path, destination: &Path, width: u32) -> Result<()> {
+    if let Some(parent) = destination.parent() {
+        std::fs::create_dir_all(parent).map_err(OrbitError::io(parent))?;
+    }
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    if let (Some(source_time), Some(destination_time)) = (modified(source), modified(destination)) {
+        if destination_time >= source_time {
+            return Ok(());
+        }
+    }
+
+    for command in RESIZE_COMMANDS {
+        let output = Command::new(command)
+            .arg(source)
+            .args(["-resize", &format!("{}x", width), "-strip"])
+            .arg(destination)
+            .output();
+        match output {
+            Ok(output) if output.status.success() => return Ok(()),
+            Ok(output) => {
+                return Err(OrbitError::ImageResize {
+                    path: source.to_path_buf(),
+                    message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+                });
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => return Err(OrbitError::io(command)(err)),
+        }
+    }
+
+    return Err(OrbitError::ImageResize {
+        path: source.to_path_buf(),
+        message: String::from("ImageMagick isn't installed"),
+    });
+}
+
+fn jpeg_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let mut idx = 2;
+    loop {
+        // Markers may be padded with any number of 0xFF bytes.
+        while *bytes.get(idx)? == 0xff {
+            idx += 1;
+        }
+        let marker = *bytes.get(idx)?;
+        let length = be_u16(bytes, idx + 1)? as usize;
+        // Every start-of-frame marker but DHT, JPG, and DAC.
+        if (0xc0..=0xcf).contains(&marker) && ![0xc4, 0xc8, 0xcc].contains(&marker) {
+            return Some((be_u16(bytes, idx + 6)? as u32, be_u16(bytes, idx + 4)? as u32));
+        }
+        idx += 1 + length;
+    }
+}
+
+fn webp_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    match bytes.get(12..16)? {
+        b"VP8 " => Some(((le_u16(bytes, 26)? & 0x3fff) as u32, (le_u16(bytes, 28)? & 0x3fff) as u32)),
+        b"VP8L" => {
+            let bits = le_u32(bytes, 21)?;
+            Some(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+        }
+        b"VP8X" => Some((le_u24(bytes, 24)? + 1, le_u24(bytes, 27)? + 1)),
+        _ => None,
+    }
+}
+
+fn be_u16(bytes: &[u8], idx: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(bytes.get(idx..(idx + 2))?.try_into().ok()?))
+}
+
+fn be_u32(bytes: &[u8], idx: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(idx..(idx + 4))?.try_into().ok()?))
+}
+
+fn le_u16(bytes: &[u8], idx: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(idx..(idx + 2))?.try_into().ok()?))
+}
+
+fn le_u24(bytes: &[u8], idx: usize) -> Option<u32> {
+    let bytes = bytes.get(idx..(idx + 3))?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]))
+}
+
+fn le_u32(bytes: &[u8], idx: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(idx..(idx + 4))?.try_into().ok()?))
+}
@@ -0,0 +1,68 @@
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::error::{OrbitError, Result};
+use crate::frontmatter;
+use crate::shortcode::{fenced_ranges, in_code_span};
+
+lazy_static::lazy_static! {
+    static ref INCLUDE: Regex = Regex::new(r#"\{\{\s*include\s+"([^"]+)"\s*\}\}"#).unwrap();
+}
+
+/// How deeply fragments may include other fragments.
+pub const MAX_DEPTH: usize = 8;
+
+/// Replaces every `{{include "path"}}` in `markdown` outside code with the
+/// markdown of the fragment at `path`, relative to the directory of `file`,
+/// which `markdown` was read from. A fragment's own frontmatter is dropped,
+/// so one can be kept out of the build with `draft: true`.
+///
+/// Fragments may include others up to [`MAX_DEPTH`] deep, but not
+/// themselves.
+pub fn expand_includes(markdown: &str, file: &Path) -> Result<String> {
+    return expand(markdown, file, &mut vec![file.to_path_buf()]);
+}
+
+/// Expands the includes in `markdown`, read from `file`, with `including`
+/// holding every file whose includes are being expanded, outermost first.
+fn expand(markdown: &str, file: &Path, including: &mut Vec<PathBuf>) -> Result<String> {
+    if !INCLUDE.is_match(markdown) {
+        return Ok(markdown.to_string());
+    }
+
+    let code = fenced_ranges(markdown);
+    let mut output = String::with_capacity(markdown.len());
+    let mut last = 0;
+    for captures in INCLUDE.captures_iter(markdown) {
+        let whole = captures.get(0).unwrap();
+        if code.iter().any(|range| range.contains(&whole.start())) || in_code_span(markdown, whole.start()) {
+            continue;
+        }
+
+        let line = markdown[..whole.start()].matches('\n').count() + 1;
+        let error = |message: String| OrbitError::Include { line, message };
+        let path = file.parent().unwrap_or_else(|| Path::new("")).join(&captures[1]);
+        if including.contains(&path) {
+            return Err(error(format!("{} is already being included", path.display())));
+        }
+        if including.len() > MAX_DEPTH {
+            return Err(error(format!("fragments are nested more than {} deep", MAX_DEPTH)));
+        }
+
+        let fragment = std::fs::read_to_string(&path).map_err(|err| error(format!("{}: {}", path.display(), err)))?;
+        let fragment = crate::normalize_source(&fragment);
+        let (_, content) = frontmatter::split_frontmatter(&fragment).map_err(|err| err.in_file(&path))?;
+        including.push(path.clone());
+        let expanded = expand(content, &path, including);
+        including.pop();
+        let expanded = expanded.map_err(|err| err.in_file(&path))?;
+
+        output.push_str(&markdown[last..whole.start()]);
+        output.push_str(expanded.trim_end_matches('\n'));
+        last = whole.end();
+    }
+    output.push_str(&markdown[last..]);
+
+    return Ok(output);
+}
@@ -0,0 +1,854 @@
+#![allow(clippy::needless_return)]
+
+//! Renders markdown notes, with embedded Orbit review decks, to Tufte-styled
+//! HTML.
+//!
+//! [`render_page`] turns a single document into an HTML fragment; [`Site`]
+//! renders a whole directory through the page template.
+
+pub mod backlinks;
+pub mod book;
+pub mod cache;
+pub mod cloze;
+pub mod config;
+pub mod date;
+pub mod deploy;
+pub mod diagnostic;
+pub mod epub;
+pub mod error;
+pub mod export;
+pub mod feed;
+pub mod frontmatter;
+pub mod graphviz;
+pub mod gitdates;
+pub mod helpers;
+pub mod highlight;
+pub mod hooks;
+pub mod ignore;
+pub mod images;
+pub mod include;
+pub mod linkcheck;
+pub mod lint;
+pub mod listing;
+pub mod math;
+pub mod minify;
+pub mod nav;
+pub mod offline;
+pub mod orbit;
+pub mod pdf;
+pub mod redirect;
+pub mod related;
+pub mod search;
+pub mod series;
+pub mod shortcode;
+pub mod site;
+pub mod sitejson;
+pub mod sitemap;
+pub mod slug;
+pub mod social;
+pub mod transform;
+pub mod urls;
+pub mod wikilink;
+pub mod zip;
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::path::Path;
+
+use pulldown_cmark::{Parser, Event, Tag, CodeBlockKind, Options};
+use pulldown_cmark::escape::{escape_href, escape_html};
+use regex::Regex;
+
+use frontmatter::Frontmatter;
+use orbit::{Attachment, DeckFormat, Orbit};
+use transform::EventTransformer;
+use wikilink::NoteIndex;
+
+pub use config::{Config, FeedOptions, RenderOptions};
+pub use error::{OrbitError, Result};
+pub use site::{BuildSummary, Page, Site};
+
+lazy_static::lazy_static! {
+    static ref NORMAL_FOOTNOTE: Regex = Regex::new("\\[\\^(.*)\\]:(.*)$").unwrap();
+}
+
+/// The page template bundled into the binary.
+pub const TEMPLATE: &str = include_str!("../template.html");
+/// The stylesheet bundled into the binary.
+pub const CSS: &str = include_str!("../tufte.css");
+
+/// Reading speed used to estimate a page's reading time.
+pub const WORDS_PER_MINUTE: usize = 200;
+
+/// A single markdown document rendered to HTML, before it is placed into the
+/// page template.
+#[derive(Debug)]
+pub struct RenderedPage {
+    pub frontmatter: Frontmatter,
+    /// The rendered document body, footnotes included.
+    pub body: String,
+    /// Whether the body contains any math needing KaTeX to display.
+    pub has_math: bool,
+    /// Whether the body contains an Orbit review area needing the web
+    /// component to display.
+    pub has_orbit: bool,
+    /// How many Orbit prompts the body contains, reversed twins included.
+    pub card_count: usize,
+    /// How many words of prose the body contains, leaving out code blocks
+    /// and Orbit decks.
+    pub word_count: usize,
+    /// Minutes it takes to read the body at [`WORDS_PER_MINUTE`], at least
+    /// one for any markdown page.
+    pub reading_time: usize,
+    /// Problems that didn't stop the page rendering, such as an unreadable
+    /// Orbit deck.
+    pub warnings: Vec<OrbitError>,
+    /// Local files the page's Orbit cards refer to, which need copying
+    /// alongside it.
+    pub attachments: Vec<Attachment>,
+}
+
+/// Parses the frontmatter of `markdown` and renders the rest to HTML with the
+/// default [`RenderOptions`].
+pub fn render_page(markdown: &str) -> Result<RenderedPage> {
+    return render_page_with(markdown, &RenderOptions::default(), &NoteIndex::default(), Path::new(""), Path::new(""));
+}
+
+/// Parses the frontmatter of `markdown` and renders the rest to HTML.
+/// `relative` is the page's path within the `source` directory, against which
+/// wikilinks to `notes`, local Orbit attachments, and images are resolved.
+pub fn render_page_with(
+    markdown: &str,
+    render_options: &RenderOptions,
+    notes: &NoteIndex,
+    source: &Path,
+    relative: &Path,
+) -> Result<RenderedPage> {
+    return render_page_with_pipeline(markdown, render_options, notes, source, relative, transform::default_pipeline());
+}
+
+/// Renders like [`render_page_with`], handing the parsed markdown down
+/// `pipeline` instead of the [default one](transform::default_pipeline).
+pub fn render_page_with_pipeline(
+    markdown: &str,
+    render_options: &RenderOptions,
+    notes: &NoteIndex,
+    source: &Path,
+    relative: &Path,
+    mut pipeline: Vec<Box<dyn EventTransformer>>,
+) -> Result<RenderedPage> {
+    let markdown = &normalize_source(markdown);
+    let (frontmatter, content, mut warnings) = frontmatter::split_frontmatter_with_warnings(markdown)?;
+
+    // Diagnostics count lines from the top of the file, frontmatter included.
+    let frontmatter_lines = markdown[..(markdown.len() - content.len())].matches('\n').count();
+    let (content, clozes) = match frontmatter.inline_cloze.unwrap_or(render_options.inline_cloze) {
+        true => cloze::extract_clozes(content),
+        false => (content.to_string(), Vec::new()),
+    };
+    let (mut content, math_spans) = math::extract_math(&content);
+    // The prompts go after the math is pulled out, since their TeX is typeset
+    // by Orbit, and as an HTML block so they land before any footnotes.
+    if !clozes.is_empty() {
+        content.push_str("\n\n<div class=\"orbit-cloze\">\n");
+        content.push_str(&Orbit::new(clozes).to_html(render_options.orbit_color.as_deref())?);
+        content.push_str("\n</div>\n");
+    }
+    let mut attachments = Vec::new();
+    let mut word_count = 0;
+    let body = markdown_to_html(
+        &content,
+        frontmatter_lines,
+        &frontmatter,
+        render_options,
+        notes,
+        source,
+        relative,
+        &mut warnings,
+        &mut attachments,
+        &mut word_count,
+        &mut pipeline,
+    )?;
+    let body = math::restore_math(&body, &math_spans);
+    let has_orbit = body.contains(orbit::REVIEW_START);
+    let card_count = body.matches(orbit::PROMPT_START).count();
+    let reading_time = word_count.div_ceil(WORDS_PER_MINUTE).max(1);
+
+    return Ok(RenderedPage {
+        frontmatter,
+        body,
+        has_math: !math_spans.is_empty(),
+        has_orbit,
+        card_count,
+        word_count,
+        reading_time,
+        warnings,
+        attachments,
+    });
+}
+
+/// Renders `markdown` to HTML. `line_offset` is the number of lines that
+/// preceded `markdown` in its file, used to report accurate line numbers.
+/// The words of prose rendered are added to `word_count`. Every event is
+/// handed down `pipeline`.
+#[allow(clippy::too_many_arguments)]
+fn markdown_to_html(
+    markdown: &str,
+    line_offset: usize,
+    frontmatter: &Frontmatter,
+    render_options: &RenderOptions,
+    notes: &NoteIndex,
+    source: &Path,
+    relative: &Path,
+    warnings: &mut Vec<OrbitError>,
+    attachments: &mut Vec<Attachment>,
+    word_count: &mut usize,
+    pipeline: &mut [Box<dyn EventTransformer>],
+) -> Result<String> {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_SMART_PUNCTUATION);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options.insert(Options::ENABLE_TASKLISTS);
+    options.insert(Options::ENABLE_HEADING_ATTRIBUTES);
+    if render_options.tables {
+        options.insert(Options::ENABLE_TABLES);
+    }
+
+    let (content, footnotes, lines) = split_content_and_footnotes(markdown, line_offset, warnings);
+    let mut context = transform::RenderContext {
+        content: &content,
+        frontmatter,
+        options: render_options,
+        notes,
+        source,
+        relative,
+        root: site::root_prefix(relative),
+        warnings: Vec::new(),
+        attachments: Vec::new(),
+        headings: Vec::new(),
+        word_count: 0,
+        footnotes,
+        lines,
+        line_offset,
+    };
+
+    let mut events = Vec::new();
+    for (event, range) in Parser::new_ext(&content, options).into_offset_iter() {
+        let mut event = Some(event);
+        for transformer in pipeline.iter_mut() {
+            event = match event {
+                Some(event) => transformer.transform(event, range.clone(), &mut events, &mut context)?,
+                None => break,
+            };
+        }
+        events.extend(event);
+    }
+    for transformer in pipeline.iter_mut() {
+        transformer.finish(&mut events, &mut context)?;
+    }
+
+    let mut html_output = String::new();
+    pulldown_cmark::html::push_html(&mut html_output, events.into_iter());
+    warnings.append(&mut context.warnings);
+    attachments.append(&mut context.attachments);
+    *word_count += context.word_count;
+
+    return Ok(html_output);
+}
+
+/// A footnote definition pulled out of the page: `[^name]:` followed by a
+/// markdown body that may run over several indented lines and paragraphs.
+#[derive(Debug)]
+struct Footnote {
+    name: String,
+    /// The line of the file the definition starts on.
+    line: usize,
+    /// The definition with its continuation lines dedented.
+    body: String,
+}
+
+/// Splits footnote definitions out of `markdown`. A definition continues over
+/// lines indented by four spaces or a tab, blank lines between them included,
+/// and over unindented lines that carry on its last paragraph. Lines that
+/// start like a definition but aren't one are kept as content, with a warning.
+///
+/// Lines are counted from `line_offset`. Alongside the content comes the line
+/// of the file each of its lines was taken from, for diagnostics.
+fn split_content_and_footnotes(markdown: &str, line_offset: usize, warnings: &mut Vec<OrbitError>) -> (String, Vec<Footnote>, Vec<usize>) {
+    let mut footnotes: Vec<Footnote> = Vec::new();
+    let mut content = Vec::new();
+    let mut lines = Vec::new();
+    // Blank lines after a definition, which belong to it only if an indented
+    // line follows.
+    let mut blanks = 0;
+    let mut in_footnote = false;
+    let mut fence: Option<&str> = None;
+
+    for (idx, line) in markdown.lines().enumerate() {
+        let trimmed = line.trim_start();
+        let line_no = line_offset + idx + 1;
+        if !in_footnote {
+            if let Some(marker) = fence {
+                content.push(line);
+                lines.push(line_no);
+                if trimmed.starts_with(marker) {
+                    fence = None;
+                }
+                continue;
+            }
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                fence = Some(&trimmed[..3]);
+                content.push(line);
+                lines.push(line_no);
+                continue;
+            }
+        }
+
+        if line.starts_with("[^") {
+            match NORMAL_FOOTNOTE.captures(line) {
+                Some(captures) => {
+                    footnotes.push(Footnote {
+                        name: captures[1].to_string(),
+                        line: line_no,
+                        body: captures[2].trim().to_string(),
+                    });
+                    in_footnote = true;
+                    blanks = 0;
+                    continue;
+                }
+                None => warnings.push(OrbitError::Footnote {
+                    line: line_no,
+                    message: "expected a definition like `[^name]: text`".to_string(),
+                }),
+            }
+        }
+
+        match footnotes.last_mut() {
+            Some(footnote) if in_footnote => {
+                let indented = line.strip_prefix("    ").or_else(|| line.strip_prefix('\t'));
+                if line.trim().is_empty() {
+                    blanks += 1;
+                    continue;
+                } else if let Some(indented) = indented {
+                    footnote.body.push_str(&"\n".repeat(blanks + 1));
+                    footnote.body.push_str(indented);
+                    blanks = 0;
+                    continue;
+                } else if blanks == 0 && !starts_block(trimmed) {
+                    footnote.body.push('\n');
+                    footnote.body.push_str(line);
+                    continue;
+                }
+
+                in_footnote = false;
+                content.extend(std::iter::repeat_n("", blanks));
+                lines.extend((line_no - blanks)..line_no);
+                blanks = 0;
+            }
+            _ => {}
+        }
+
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+        }
+        content.push(line);
+        lines.push(line_no);
+    }
+
+    return (content.join("\n"), footnotes, lines);
+}
+
+/// Whether `line` opens a block of its own rather than carrying on a
+/// paragraph.
+fn starts_block(line: &str) -> bool {
+    let block_markers = ["#", ">", "```", "~~~", "- ", "* ", "+ ", "|", "<", "[^"];
+    return block_markers.iter().any(|marker| line.starts_with(marker));
+}
+
+fn fmt_footnotes_to_html(footnotes: &[&Footnote]) -> Result<String> {
+    let mut html_output = String::from("<hr />\n<ol>\n");
+    for footnote in footnotes {
+        let mut back = String::from(" <a class=\"fn-back\" href=\"#");
+        let _ = escape_href(&mut back, &footnote.name);
+        back.push_str("-back\">↩</a>");
+
+        html_output.push_str("<li id=\"");
+        let _ = escape_html(&mut html_output, &footnote.name);
+        html_output.push_str("\">");
+        // A single paragraph stays bare, as in a tight list; longer bodies
+        // keep their blocks, with the back link ending the last paragraph.
+        let body = render_footnote(&footnote.body);
+        match body.strip_prefix("<p>").and_then(|body| body.strip_suffix("</p>\n")) {
+            Some(paragraph) if !paragraph.contains("<p>") => {
+                html_output.push_str(paragraph);
+                html_output.push_str(&back);
+            }
+            _ => match body.rfind("</p>") {
+                Some(end) if body[end..].trim() == "</p>" => {
+                    html_output.push_str(&body[..end]);
+                    html_output.push_str(&back);
+                    html_output.push_str(&body[end..]);
+                }
+                _ => {
+                    html_output.push_str(&body);
+                    html_output.push_str(&back);
+                }
+            },
+        }
+        html_output.push_str("</li>\n");
+    }
+    html_output.push_str("</ol>\n");
+
+    Ok(html_output)
+}
+
+/// Renders the body of a footnote, which may hold any blocks.
+fn render_footnote(markdown: &str) -> String {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_SMART_PUNCTUATION);
+
+    let events = Parser::new_ext(markdown, options).map(|event| match event {
+        Event::Start(Tag::Link(link_type, destination, title)) => {
+            let new_destination = rewrite_link(&destination);
+            Event::Start(Tag::Link(link_type, new_destination.into(), title))
+        }
+        _ => event,
+    });
+
+    let mut html_output = String::new();
+    pulldown_cmark::html::push_html(&mut html_output, events);
+
+    return html_output;
+}
+
+/// The body of the footnote definition named `name`, if there is one.
+fn footnote_definition<'a>(footnotes: &'a [Footnote], name: &str) -> Option<&'a str> {
+    footnotes.iter()
+        .find(|footnote| footnote.name == name)
+        .map(|footnote| footnote.body.as_str())
+}
+
+/// A footnote body rendered to fit inside a sidenote or margin note's
+/// `<span>`, with line breaks between its paragraphs.
+fn note_html(definition: &str) -> String {
+    return definition.split("\n\n")
+        .filter(|paragraph| !paragraph.trim().is_empty())
+        .map(render_inline)
+        .collect::<Vec<_>>()
+        .join("<br><br>");
+}
+
+/// Tufte's sidenote markup: a numbered label, the checkbox that toggles the
+/// note open on narrow screens, and the note itself.
+fn sidenote_html(name: &str, definition: &str) -> String {
+    let mut id = String::from("sn-");
+    let _ = escape_html(&mut id, name);
+
+    return format!(
+        "<label for=\"{id}\" class=\"margin-toggle sidenote-number\"></label><input type=\"checkbox\" id=\"{id}\" class=\"margin-toggle\"/><span class=\"sidenote\">{}</span>",
+        note_html(definition),
+        id = id,
+    );
+}
+
+/// Like [`sidenote_html`], but for an unnumbered margin note written as
+/// `[^+name]`. On narrow screens the note is toggled by a ⊕ symbol.
+fn marginnote_html(name: &str, definition: &str) -> String {
+    let mut id = String::from("mn-");
+    let _ = escape_html(&mut id, name);
+
+    return format!(
+        "<label for=\"{id}\" class=\"margin-toggle\">&#8853;</label><input type=\"checkbox\" id=\"{id}\" class=\"margin-toggle\"/><span class=\"marginnote\">{}</span>",
+        note_html(definition),
+        id = id,
+    );
+}
+
+/// Renders an `epigraph` fenced block as Tufte's epigraph markup. Lines
+/// starting with `--` or `—` attribute the quote, e.g.
+/// `-- Richard Feynman, What Do You Care What Other People Think?`, where
+/// anything after the first comma is the cited work.
+fn epigraph_html(block: &str) -> String {
+    let mut quote = String::new();
+    let mut footers = Vec::new();
+    for line in block.lines() {
+        let trimmed = line.trim();
+        match trimmed.strip_prefix("--").or_else(|| trimmed.strip_prefix('—')) {
+            Some(attribution) => footers.push(attribution.trim().to_string()),
+            None => {
+                quote.push_str(line);
+                quote.push('\n');
+            }
+        }
+    }
+
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_SMART_PUNCTUATION);
+
+    let mut html = String::from("<div class=\"epigraph\">\n<blockquote>\n");
+    pulldown_cmark::html::push_html(&mut html, Parser::new_ext(&quote, options));
+    for footer in footers {
+        html.push_str("<footer>");
+        match footer.split_once(", ") {
+            Some((author, cite)) => {
+                html.push_str(&render_inline(author));
+                html.push_str(", <cite>");
+                html.push_str(&render_inline(cite));
+                html.push_str("</cite>");
+            }
+            None => html.push_str(&render_inline(&footer)),
+        }
+        html.push_str("</footer>\n");
+    }
+    html.push_str("</blockquote>\n</div>\n");
+
+    return html;
+}
+
+/// Renders a snippet of markdown without wrapping it in a paragraph, for
+/// markup that has to stay inline such as sidenotes.
+pub(crate) fn render_inline(markdown: &str) -> String {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_SMART_PUNCTUATION);
+
+    let events = Parser::new_ext(markdown, options).filter_map(|event| match event {
+        Event::Start(Tag::Paragraph) | Event::End(Tag::Paragraph) => None,
+        Event::Start(Tag::Link(link_type, destination, title)) => {
+            let new_destination = rewrite_link(&destination);
+            Some(Event::Start(Tag::Link(link_type, new_destination.into(), title)))
+        }
+        _ => Some(event),
+    });
+
+    let mut html_output = String::new();
+    pulldown_cmark::html::push_html(&mut html_output, events);
+
+    return html_output.trim_end().to_string();
+}
+
+/// Points links at markdown notes to their rendered HTML, keeping any
+/// `?query` and `#fragment` so links to a heading in another note still land
+/// on it. Only a `.md` ending the path is rewritten, and links to other sites
+/// are left alone.
+fn rewrite_link(destination: &str) -> String {
+    let is_remote = destination.starts_with("//") || destination.split_once(':').is_some_and(|(scheme, _)| {
+        !scheme.is_empty() && !scheme.contains(['/', '?', '#'])
+    });
+    if is_remote {
+        return destination.to_string();
+    }
+
+    let suffix_start = destination.find(['?', '#']).unwrap_or(destination.len());
+    let (path, suffix) = destination.split_at(suffix_start);
+
+    return match path.strip_suffix(".md") {
+        Some(stem) if !stem.is_empty() && !stem.ends_with('/') => format!("{}.html{}", stem, suffix),
+        _ => destination.to_string(),
+    };
+}
+
+/// The opening tag of a link to another site, carrying the attributes
+/// `render_options` asks external links to have. `None` for links within the
+/// site, or when no decoration is configured.
+fn external_link_html(destination: &str, title: &str, render_options: &RenderOptions) -> Option<String> {
+    let external = destination.starts_with("http://") || destination.starts_with("https://") || destination.starts_with("//");
+    if !external || (!render_options.external_links_new_tab && render_options.external_link_class.is_none()) {
+        return None;
+    }
+
+    let mut html = String::from("<a href=\"");
+    let _ = escape_href(&mut html, destination);
+    html.push('"');
+    if !title.is_empty() {
+        html.push_str(" title=\"");
+        let _ = escape_html(&mut html, title);
+        html.push('"');
+    }
+    if let Some(class) = &render_options.external_link_class {
+        html.push_str(" class=\"");
+        let _ = escape_html(&mut html, class);
+        html.push('"');
+    }
+    if render_options.external_links_new_tab {
+        html.push_str(" target=\"_blank\" rel=\"noopener noreferrer\"");
+    }
+    html.push('>');
+
+    return Some(html);
+}
+
+/// The text content of a run of events, ignoring markup.
+fn plain_text(events: &[Event]) -> String {
+    let mut text = String::new();
+    for event in events {
+        if let Event::Text(t) | Event::Code(t) = event {
+            text.push_str(t);
+        }
+    }
+
+    return text;
+}
+
+/// Marks a title as asking for Tufte's full-width figure variant.
+const FULLWIDTH_MARKER: &str = "{fullwidth}";
+
+/// Turns every paragraph consisting of a single image into a `<figure>`,
+/// captioned by the image's title or, failing that, its alt text. A title
+/// containing `{fullwidth}` makes the figure span the margin too.
+fn wrap_figures(events: &mut Vec<Event>, source: &Path, relative: &Path) {
+    let mut idx = 0;
+    while idx < events.len() {
+        let (destination, title) = match (&events[idx], events.get(idx + 1)) {
+            (Event::Start(Tag::Paragraph), Some(Event::Start(Tag::Image(_, destination, title)))) => {
+                (destination.to_string(), title.to_string())
+            }
+            _ => {
+                idx += 1;
+                continue;
+            }
+        };
+
+        let image_end = events[(idx + 2)..].iter()
+            .position(|event| matches!(event, Event::End(Tag::Image(..))))
+            .map(|offset| idx + 2 + offset);
+        let image_end = match image_end {
+            Some(end) if matches!(events.get(end + 1), Some(Event::End(Tag::Paragraph))) => end,
+            _ => {
+                idx += 1;
+                continue;
+            }
+        };
+
+        let alt = plain_text(&events[(idx + 2)..image_end]);
+        let fullwidth = title.contains(FULLWIDTH_MARKER);
+        let title = title.replace(FULLWIDTH_MARKER, "");
+        let caption = if title.trim().is_empty() { alt.as_str() } else { title.trim() };
+
+        let mut html = String::from(if fullwidth { "<figure class=\"fullwidth\">" } else { "<figure>" });
+        html.push_str(&image_html(&destination, &alt, "", image_size(source, relative, &destination)));
+        if !caption.is_empty() {
+            html.push_str("<figcaption>");
+            let _ = escape_html(&mut html, caption);
+            html.push_str("</figcaption>");
+        }
+        html.push_str("</figure>\n");
+
+        events.splice(idx..(image_end + 2), [Event::Html(html.into())]);
+        idx += 1;
+    }
+}
+
+/// Renders every image left outside a figure itself, so that it loads lazily
+/// and, like figures, gives its size up front to save the page reflowing.
+fn render_images(events: &mut Vec<Event>, source: &Path, relative: &Path) {
+    let mut idx = 0;
+    while idx < events.len() {
+        let (destination, title) = match &events[idx] {
+            Event::Start(Tag::Image(_, destination, title)) => (destination.to_string(), title.to_string()),
+            _ => {
+                idx += 1;
+                continue;
+            }
+        };
+        let image_end = match events[(idx + 1)..].iter().position(|event| matches!(event, Event::End(Tag::Image(..)))) {
+            Some(offset) => idx + 1 + offset,
+            None => break,
+        };
+
+        let alt = plain_text(&events[(idx + 1)..image_end]);
+        let html = image_html(&destination, &alt, &title, image_size(source, relative, &destination));
+        events.splice(idx..(image_end + 1), [Event::Html(html.into())]);
+        idx += 1;
+    }
+}
+
+/// An `<img>` loading lazily, with its width and height if they're known.
+fn image_html(destination: &str, alt: &str, title: &str, size: Option<(u32, u32)>) -> String {
+    let mut html = String::from("<img src=\"");
+    let _ = escape_href(&mut html, destination);
+    html.push_str("\" alt=\"");
+    let _ = escape_html(&mut html, alt);
+    html.push('"');
+    if !title.is_empty() {
+        html.push_str(" title=\"");
+        let _ = escape_html(&mut html, title);
+        html.push('"');
+    }
+    if let Some((width, height)) = size {
+        html.push_str(&format!(" width=\"{}\" height=\"{}\"", width, height));
+    }
+    html.push_str(" loading=\"lazy\" />");
+
+    return html;
+}
+
+/// The size of the image at `destination`, as linked from the page at
+/// `relative` in the `source` directory, if it's a local file that can be read.
+fn image_size(source: &Path, relative: &Path, destination: &str) -> Option<(u32, u32)> {
+    if destination.is_empty() || destination.starts_with(['/', '#']) || destination.contains(':') {
+        return None;
+    }
+    let path = &destination[..destination.find(['?', '#']).unwrap_or(destination.len())];
+    let path = urls::normalize(&relative.parent().unwrap_or_else(|| Path::new("")).join(urls::percent_decode(path)))?;
+
+    return images::dimensions(&source.join(path));
+}
+
+/// Finds a paragraph consisting solely of the text `[TOC]`, which the parser
+/// may have split across several text events.
+fn find_toc_marker(events: &[Event]) -> Option<std::ops::Range<usize>> {
+    for (start, event) in events.iter().enumerate() {
+        if !matches!(event, Event::Start(Tag::Paragraph)) {
+            continue;
+        }
+
+        let mut text = String::new();
+        for (offset, event) in events[(start + 1)..].iter().enumerate() {
+            match event {
+                Event::Text(t) => text.push_str(t),
+                Event::End(Tag::Paragraph) if text == "[TOC]" => return Some(start..(start + offset + 2)),
+                _ => break,
+            }
+        }
+    }
+
+    return None;
+}
+
+/// Renders `(level, id, text)` headings as a nested list of links. Skipped
+/// levels are flattened so the list never nests more than one step at a time.
+fn toc_html(headings: &[(usize, String, String)]) -> String {
+    if headings.is_empty() {
+        return String::new();
+    }
+
+    let base = headings.iter().map(|(level, _, _)| *level).min().unwrap_or(1);
+    let mut html = String::from("<nav class=\"toc\">\n<ul>\n");
+    let mut depth = 0;
+
+    for (idx, (level, id, text)) in headings.iter().enumerate() {
+        let target = (level - base).min(depth + 1);
+        if idx > 0 {
+            if target > depth {
+                html.push_str("\n<ul>\n");
+            } else {
+                html.push_str("</li>\n");
+                for _ in target..depth {
+                    html.push_str("</ul>\n</li>\n");
+                }
+            }
+        }
+        depth = target;
+
+        html.push_str("<li><a href=\"#");
+        let _ = escape_href(&mut html, id);
+        html.push_str("\">");
+        let _ = escape_html(&mut html, text);
+        html.push_str("</a>");
+    }
+
+    html.push_str("</li>\n");
+    for _ in 0..depth {
+        html.push_str("</ul>\n</li>\n");
+    }
+    html.push_str("</ul>\n</nav>\n");
+
+    return html;
+}
+
+/// Slugifies `text`, suffixing `-1`, `-2`, ... if the slug is already taken.
+pub(crate) fn unique_slug(text: &str, taken: &mut HashSet<String>) -> String {
+    let base = match slug::slugify(text) {
+        slug if slug.is_empty() => String::from("section"),
+        slug => slug,
+    };
+
+    let mut candidate = base.clone();
+    let mut suffix = 1;
+    while taken.contains(&candidate) {
+        candidate = format!("{}-{}", base, suffix);
+        suffix += 1;
+    }
+    taken.insert(candidate.clone());
+
+    return candidate;
+}
+
+/// Parses every `orbit` block in `markdown`, a whole note with frontmatter,
+/// in order, alongside the line each block starts on.
+pub fn extract_decks(markdown: &str) -> Result<Vec<(usize, Orbit)>> {
+    let markdown = &normalize_source(markdown);
+    let (_, content) = frontmatter::split_frontmatter(markdown)?;
+    let line_offset = markdown[..(markdown.len() - content.len())].matches('\n').count();
+
+    let mut decks = Vec::new();
+    for (event, range) in Parser::new(content).into_offset_iter() {
+        if let Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(language))) = event {
+            if let Some(format) = DeckFormat::from_language(&language) {
+                let line = line_offset + content[..range.start].matches('\n').count() + 1;
+                decks.push((line, deserialize_orbit_codeblock(&content[range], format, line)?));
+            }
+        }
+    }
+
+    return Ok(decks);
+}
+
+/// Parses the deck inside an `orbit` codeblock that starts on `line`.
+fn deserialize_orbit_codeblock(codeblock: &str, format: DeckFormat, line: usize) -> Result<Orbit> {
+    // Drop the opening fence line and, if the block was closed, the closing
+    // one. The deck begins on the line after the opening fence.
+    let deck = codeblock.split_once('\n').map(|(_, rest)| rest).unwrap_or("");
+    let deck = match deck.trim_end().rfind('\n') {
+        Some(idx) if is_fence(&deck[(idx + 1)..]) => &deck[..(idx + 1)],
+        None if is_fence(deck) => "",
+        _ => deck,
+    };
+
+    let end = line + codeblock.trim_end().matches('\n').count();
+
+    return Orbit::parse(deck, format, line + 1)
+        .map_err(|err| err.in_orbit_block(codeblock, line, end));
+}
+
+/// Whether `line` is a closing code fence.
+fn is_fence(line: &str) -> bool {
+    let line = line.trim();
+
+    return (line.starts_with("```") || line.starts_with("~~~")) && line.trim_matches(['`', '~']).is_empty();
+}
+
+/// `text` without a leading byte order mark and with Windows line endings
+/// made Unix ones, so notes saved on any system parse alike.
+pub fn normalize_source(text: &str) -> Cow<'_, str> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    if !text.contains('\r') {
+        return Cow::Borrowed(text);
+    }
+
+    return Cow::Owned(text.replace("\r\n", "\n"));
+}
+
+/// Reads the note or fragment at `path`, normalized with
+/// [`normalize_source`].
+pub fn read_note(path: &Path) -> Result<String> {
+    let text = std::fs::read_to_string(path).map_err(OrbitError::io(path))?;
+
+    return Ok(normalize_source(&text).into_owned());
+}
+
+pub fn is_hidden(entry: &walkdir::DirEntry) -> bool {
+    entry.file_name()
+        .to_str()
+        .map(|s| s.starts_with("."))
+        .unwrap_or(false)
+}
+
+pub fn is_markdown(filename: &OsStr) -> bool {
+    filename.to_string_lossy().ends_with(".md")
+}
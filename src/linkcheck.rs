@@ -0,0 +1,123 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+use regex::Regex;
+use walkdir::WalkDir;
+
+use crate::error::{OrbitError, Result};
+use crate::urls::{percent_decode, unescape};
+
+lazy_static::lazy_static! {
+    static ref HREF: Regex = Regex::new(r#"\shref="([^"]*)""#).unwrap();
+    static ref ID: Regex = Regex::new(r#"\sid="([^"]*)""#).unwrap();
+    /// A footnote reference, whose anchor the footnote pass already vouches
+    /// for, warning about any footnote without a definition.
+    static ref FOOTNOTE_REF: Regex = Regex::new(r#"<sup class="fn">.*?</sup>"#).unwrap();
+    static ref SCHEME: Regex = Regex::new(r"^[A-Za-z][A-Za-z0-9+.-]*:").unwrap();
+}
+
+/// An internal link in the generated site that leads nowhere.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrokenLink {
+    /// The page holding the link, relative to the destination directory.
+    pub page: PathBuf,
+    /// The link as written in the page.
+    pub href: String,
+    /// Whether the file exists but the `#fragment` names no element in it.
+    pub missing_anchor: bool,
+}
+
+impl fmt::Display for BrokenLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.missing_anchor {
+            true => write!(f, "{}: no anchor for `{}`", self.page.display(), self.href),
+            false => write!(f, "{}: broken link to `{}`", self.page.display(), self.href),
+        }
+    }
+}
+
+/// Checks every internal `href` in the HTML files under `destination`, paths
+/// and `#fragment`s both, against the files and element ids actually there.
+/// Links with a scheme or to another host are left alone.
+pub fn check_links(destination: &Path) -> Result<Vec<BrokenLink>> {
+    let mut pages = HashMap::new();
+    for entry in WalkDir::new(destination).sort_by_file_name() {
+        let entry = entry?;
+        if entry.path().extension().is_some_and(|extension| extension == "html") {
+            let html = std::fs::read_to_string(entry.path()).map_err(OrbitError::io(entry.path()))?;
+            let relative = entry.path().strip_prefix(destination).unwrap_or(entry.path()).to_path_buf();
+            pages.insert(relative, html);
+        }
+    }
+
+    let ids: HashMap<&Path, HashSet<String>> = pages.iter()
+        .map(|(page, html)| (page.as_path(), ID.captures_iter(html).map(|id| unescape(&id[1])).collect()))
+        .collect();
+
+    let mut broken = Vec::new();
+    let mut sorted: Vec<&PathBuf> = pages.keys().collect();
+    sorted.sort();
+    for page in sorted {
+        let footnote_refs: Vec<_> = FOOTNOTE_REF.find_iter(&pages[page]).map(|found| found.range()).collect();
+        for captures in HREF.captures_iter(&pages[page]) {
+            let href = unescape(&captures[1]);
+            if href.is_empty() || href.starts_with("//") || SCHEME.is_match(&href) {
+                continue;
+            }
+            let start = captures.get(0).map_or(0, |found| found.start());
+            if footnote_refs.iter().any(|range| range.contains(&start)) {
+                continue;
+            }
+
+            let (path, fragment) = match href.split_once('#') {
+                Some((path, fragment)) => (path, Some(fragment)),
+                None => (href.as_str(), None),
+            };
+            let path = path.split('?').next().unwrap_or_default();
+            let target = match path {
+                "" => page.clone(),
+                path => resolve(page, &percent_decode(path)),
+            };
+            let target = match destination.join(&target).is_dir() {
+                true => target.join("index.html"),
+                false => target,
+            };
+
+            if target.starts_with("..") || !destination.join(&target).exists() {
+                broken.push(BrokenLink { page: page.clone(), href, missing_anchor: false });
+                continue;
+            }
+            // Only generated pages are checked for anchors.
+            if let (Some(fragment), Some(ids)) = (fragment.filter(|f| !f.is_empty()), ids.get(target.as_path())) {
+                if !ids.contains(&percent_decode(fragment)) {
+                    broken.push(BrokenLink { page: page.clone(), href: href.clone(), missing_anchor: true });
+                }
+            }
+        }
+    }
+
+    return Ok(broken);
+}
+
+/// Resolves `href`, written in `page`, to a path relative to the destination.
+/// Absolute paths are taken from the destination's root.
+fn resolve(page: &Path, href: &str) -> PathBuf {
+    let joined = match href.strip_prefix('/') {
+        Some(absolute) => PathBuf::from(absolute),
+        None => page.parent().unwrap_or_else(|| Path::new("")).join(href),
+    };
+
+    let mut target = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(target.components().next_back(), Some(Component::Normal(_))) => {
+                target.pop();
+            }
+            other => target.push(other),
+        }
+    }
+
+    return target;
+}
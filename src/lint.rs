@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::error::{OrbitError, Result};
+use crate::ignore::IgnoreRules;
+use crate::orbit::OrbitCard;
+use crate::site;
+
+/// Prompts longer than this many characters are hard to review at a glance.
+pub const MAX_PROMPT_CHARS: usize = 280;
+
+/// A problem with a card, or with the deck it belongs to.
+#[derive(Debug)]
+pub struct LintIssue {
+    pub path: PathBuf,
+    /// The line of the `orbit` block the card is in.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path.display(), self.line, self.message)
+    }
+}
+
+/// Checks every card in every `orbit` block under `source` for empty or
+/// overlong fields, questions asked more than once across the site, and
+/// malformed attachment URLs. Decks that don't parse are reported too.
+/// Notes `ignore` matches are skipped.
+pub fn lint_site(source: &Path, ignore: &IgnoreRules) -> Result<Vec<LintIssue>> {
+    let mut pages = site::find_pages(source, ignore)?;
+    pages.sort_by(|a, b| a.relative.cmp(&b.relative));
+
+    let mut issues = Vec::new();
+    // Where each question was first seen, keyed by its normalized text.
+    let mut questions: HashMap<String, (PathBuf, usize)> = HashMap::new();
+
+    for page in pages {
+        let markdown = crate::read_note(&page.path)?;
+        let decks = match crate::extract_decks(&markdown) {
+            Ok(decks) => decks,
+            Err(err) => {
+                issues.push(LintIssue { path: page.path.clone(), line: error_line(&err), message: err.to_string() });
+                continue;
+            }
+        };
+
+        for (line, deck) in decks {
+            for (idx, card) in deck.cards().iter().enumerate() {
+                let mut report = |message: String| {
+                    issues.push(LintIssue { path: page.path.clone(), line, message: format!("deck[{}]: {}", idx, message) });
+                };
+
+                for message in card_problems(card) {
+                    report(message);
+                }
+
+                let prompt = card.question().or(card.cloze()).unwrap_or_default();
+                let key = prompt.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+                match questions.get(&key) {
+                    Some((first_path, first_line)) => report(format!(
+                        "same prompt as the card at {}:{}",
+                        first_path.display(),
+                        first_line,
+                    )),
+                    None => {
+                        questions.insert(key, (page.path.clone(), line));
+                    }
+                }
+            }
+        }
+    }
+
+    return Ok(issues);
+}
+
+/// The problems with a single card, regardless of the rest of the site.
+fn card_problems(card: &OrbitCard) -> Vec<String> {
+    let mut problems = Vec::new();
+    let fields = [("question", card.question()), ("answer", card.answer()), ("cloze", card.cloze())];
+    for (name, field) in fields {
+        let field = match field {
+            Some(field) => field,
+            None => continue,
+        };
+
+        if field.trim().is_empty() {
+            problems.push(format!("empty {}", name));
+        } else if field.chars().count() > MAX_PROMPT_CHARS {
+            problems.push(format!("{} is over {} characters", name, MAX_PROMPT_CHARS));
+        }
+    }
+
+    if let Some(cloze) = card.cloze() {
+        if !cloze.contains('{') || !cloze.contains('}') {
+            problems.push("cloze has no `{deletions}`".to_string());
+        }
+    }
+
+    let attachments = [("question_attachments", card.question_attachments()), ("answer_attachments", card.answer_attachments())];
+    for (name, attachment) in attachments {
+        if let Some(attachment) = attachment {
+            if !is_valid_url(attachment) {
+                problems.push(format!("{} `{}` isn't a valid URL", name, attachment));
+            }
+        }
+    }
+
+    return problems;
+}
+
+/// Accepts absolute `http` and `https` URLs with a host, and relative paths.
+fn is_valid_url(url: &str) -> bool {
+    if url.is_empty() || url.chars().any(char::is_whitespace) {
+        return false;
+    }
+
+    match url.split_once("://") {
+        Some((scheme, rest)) => {
+            let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+            (scheme == "http" || scheme == "https") && !host.is_empty()
+        }
+        None => !url.contains(':'),
+    }
+}
+
+/// The line an error from parsing a note points at, if it points anywhere.
+fn error_line(err: &OrbitError) -> usize {
+    match err {
+        OrbitError::OrbitBlock { start, .. } => *start,
+        OrbitError::OrbitDeck { line, .. } | OrbitError::Frontmatter { line, .. } => *line,
+        _ => 1,
+    }
+}
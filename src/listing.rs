@@ -0,0 +1,263 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use chrono::{Datelike, NaiveDateTime};
+use pulldown_cmark::escape::{escape_href, escape_html};
+use serde::Serialize;
+
+use crate::date::parse_date;
+use crate::frontmatter;
+use crate::site::Page;
+use crate::slug::slugify;
+
+/// Directory under the destination holding the generated tag pages.
+pub const TAGS_DIR: &str = "tags";
+
+/// What the generated listings need to know about a page.
+#[derive(Serialize, Debug, Clone)]
+pub struct PageSummary {
+    /// The frontmatter title, or the file name without one.
+    pub title: String,
+    pub date: Option<String>,
+    /// The date parsed, if it's in a format [`parse_date`] understands.
+    #[serde(skip)]
+    pub timestamp: Option<NaiveDateTime>,
+    pub tags: Vec<String>,
+    /// Where the page is rendered, relative to the site root.
+    pub output: PathBuf,
+    /// How many Orbit prompts the page has.
+    pub card_count: usize,
+}
+
+impl PageSummary {
+    /// Summarizes every page, where `sources[i]` is the markdown of
+    /// `pages[i]`, newest first with undated pages last. Pages whose frontmatter doesn't parse are
+    /// listed by file name; their own render reports the error.
+    pub fn collect(pages: &[Page], sources: &[String]) -> Vec<PageSummary> {
+        let mut summaries: Vec<PageSummary> = pages.iter().zip(sources)
+            .map(|(page, markdown)| {
+                let frontmatter = frontmatter::split_frontmatter(markdown)
+                    .map(|(frontmatter, _)| frontmatter)
+                    .unwrap_or_default();
+                let name = page.relative.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let card_count = crate::extract_decks(markdown)
+                    .map(|decks| decks.iter().map(|(_, deck)| deck.prompts().len()).sum())
+                    .unwrap_or(0);
+
+                PageSummary {
+                    title: frontmatter.title.unwrap_or(name),
+                    timestamp: frontmatter.date.as_deref().and_then(parse_date),
+                    date: frontmatter.date,
+                    tags: frontmatter.tags,
+                    output: page.output_path(),
+                    card_count,
+                }
+            })
+            .collect();
+        summaries.sort_by(|a, b| {
+            b.timestamp.cmp(&a.timestamp)
+                .then_with(|| b.date.cmp(&a.date))
+                .then_with(|| a.title.cmp(&b.title))
+        });
+
+        return summaries;
+    }
+}
+
+/// Where the page listing everything tagged `tag` is written.
+pub fn tag_output(tag: &str) -> PathBuf {
+    Path::new(TAGS_DIR).join(format!("{}.html", slugify(tag)))
+}
+
+/// Groups `summaries` by tag, keeping each group in the order given.
+pub fn by_tag(summaries: &[PageSummary]) -> BTreeMap<String, Vec<&PageSummary>> {
+    let mut tags: BTreeMap<String, Vec<&PageSummary>> = BTreeMap::new();
+    for summary in summaries {
+        for tag in &summary.tags {
+            tags.entry(tag.clone()).or_default().push(summary);
+        }
+    }
+
+    return tags;
+}
+
+/// The body of a tag page: every page carrying the tag, newest first, and
+/// a link to the tag's `feed` if there is one.
+pub fn tag_html(summaries: &[&PageSummary], root: &str, feed: Option<&Path>) -> String {
+    let mut html = String::from("<ul class=\"page-list\">\n");
+    for summary in summaries {
+        html.push_str(&summary_item(summary, root));
+    }
+    html.push_str("</ul>\n");
+    if let Some(feed) = feed {
+        html.push_str("<p class=\"tag-feed\"><a href=\"");
+        let _ = escape_href(&mut html, root);
+        let _ = escape_href(&mut html, &feed.to_string_lossy().replace('\\', "/"));
+        html.push_str("\" type=\"application/atom+xml\">Subscribe to this tag</a></p>\n");
+    }
+
+    return html;
+}
+
+/// The body of the tag index: every tag with the number of pages under it.
+pub fn tag_index_html(tags: &BTreeMap<String, Vec<&PageSummary>>, root: &str) -> String {
+    let mut html = String::from("<ul class=\"tag-list\">\n");
+    for (tag, summaries) in tags {
+        html.push_str("<li><a href=\"");
+        let _ = escape_href(&mut html, root);
+        let _ = escape_href(&mut html, &tag_output(tag).to_string_lossy());
+        html.push_str("\">");
+        let _ = escape_html(&mut html, tag);
+        html.push_str(&format!("</a> ({})</li>\n", summaries.len()));
+    }
+    html.push_str("</ul>\n");
+
+    return html;
+}
+
+/// The body of the generated site index: every page, newest first, grouped
+/// under a heading per directory with the top-level pages first.
+pub fn index_html(summaries: &[PageSummary]) -> String {
+    let mut directories: BTreeMap<PathBuf, Vec<&PageSummary>> = BTreeMap::new();
+    for summary in summaries {
+        let directory = summary.output.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+        directories.entry(directory).or_default().push(summary);
+    }
+
+    let mut html = String::new();
+    for (directory, summaries) in directories {
+        if directory.as_os_str().is_empty() {
+            html.push_str("<ul class=\"page-list\">\n");
+        } else {
+            html.push_str("<h2>");
+            let _ = escape_html(&mut html, &directory.to_string_lossy());
+            html.push_str("</h2>\n<ul class=\"page-list\">\n");
+        }
+        for summary in summaries {
+            html.push_str(&summary_item(summary, ""));
+        }
+        html.push_str("</ul>\n");
+    }
+
+    return html;
+}
+
+/// The body of the archive: every dated page, newest first, under a heading
+/// per year and month.
+pub fn archive_html(summaries: &[PageSummary]) -> String {
+    let mut html = String::new();
+    let mut year = None;
+    let mut month = None;
+    for summary in summaries {
+        let timestamp = match summary.timestamp {
+            Some(timestamp) => timestamp,
+            None => continue,
+        };
+
+        if year != Some(timestamp.year()) || month != Some(timestamp.month()) {
+            if year.is_some() {
+                html.push_str("</ul>\n");
+            }
+            if year != Some(timestamp.year()) {
+                html.push_str(&format!("<h2 id=\"{}\">{}</h2>\n", timestamp.year(), timestamp.year()));
+            }
+            html.push_str(&format!("<h3>{}</h3>\n<ul class=\"page-list\">\n", timestamp.format("%B")));
+            year = Some(timestamp.year());
+            month = Some(timestamp.month());
+        }
+        html.push_str(&summary_item(summary, ""));
+    }
+    if year.is_some() {
+        html.push_str("</ul>\n");
+    }
+
+    return html;
+}
+
+/// Every dated page, oldest first, so readers can page through the site in
+/// the order it was written.
+#[derive(Debug, Default)]
+pub struct Chronology {
+    dated: Vec<PageSummary>,
+    /// Each dated page's index in `dated`, by output path.
+    positions: HashMap<PathBuf, usize>,
+}
+
+impl Chronology {
+    /// Orders the dated pages among `summaries`, which are newest first.
+    pub fn new(summaries: Vec<PageSummary>) -> Chronology {
+        let mut dated: Vec<PageSummary> = summaries.into_iter()
+            .filter(|summary| summary.timestamp.is_some())
+            .collect();
+        dated.reverse();
+        let positions = dated.iter().enumerate()
+            .map(|(idx, summary)| (summary.output.clone(), idx))
+            .collect();
+
+        return Chronology { dated, positions };
+    }
+
+    /// The page dated just before `page`, unless it's undated or the oldest.
+    pub fn prev(&self, page: &Page) -> Option<&PageSummary> {
+        let idx = *self.positions.get(&page.output_path())?;
+
+        return idx.checked_sub(1).map(|idx| &self.dated[idx]);
+    }
+
+    /// The page dated just after `page`, unless it's undated or the newest.
+    pub fn next(&self, page: &Page) -> Option<&PageSummary> {
+        let idx = *self.positions.get(&page.output_path())?;
+
+        return self.dated.get(idx + 1);
+    }
+}
+
+/// Renders the links to the pages dated either side of a page, or nothing
+/// if there are none. `root` is the path from the page back to the site root.
+pub fn page_nav_html(prev: Option<&PageSummary>, next: Option<&PageSummary>, root: &str) -> String {
+    if prev.is_none() && next.is_none() {
+        return String::new();
+    }
+
+    let mut html = String::from("<nav class=\"page-nav\">\n");
+    for (rel, label, summary) in [("prev", "← ", prev), ("next", "→ ", next)] {
+        if let Some(summary) = summary {
+            html.push_str(&format!("<a rel=\"{}\" href=\"", rel));
+            let _ = escape_href(&mut html, root);
+            let _ = escape_href(&mut html, &summary.output.to_string_lossy());
+            html.push_str("\">");
+            html.push_str(label);
+            let _ = escape_html(&mut html, &summary.title);
+            html.push_str("</a>\n");
+        }
+    }
+    html.push_str("</nav>\n");
+
+    return html;
+}
+
+/// A list item linking to a page, followed by its date and the number of
+/// prompts it has, if any.
+fn summary_item(summary: &PageSummary, root: &str) -> String {
+    let mut html = String::from("<li><a href=\"");
+    let _ = escape_href(&mut html, root);
+    let _ = escape_href(&mut html, &summary.output.to_string_lossy());
+    html.push_str("\">");
+    let _ = escape_html(&mut html, &summary.title);
+    html.push_str("</a>");
+    if let Some(date) = &summary.date {
+        html.push_str(" <time>");
+        let _ = escape_html(&mut html, date);
+        html.push_str("</time>");
+    }
+    match summary.card_count {
+        0 => {}
+        1 => html.push_str(" <span class=\"card-count\">1 prompt</span>"),
+        count => html.push_str(&format!(" <span class=\"card-count\">{} prompts</span>", count)),
+    }
+    html.push_str("</li>\n");
+
+    return html;
+}
@@ -1,217 +1,357 @@
-mod orbit;
+#![allow(clippy::needless_return)]
+
+mod cli;
+mod serve;
+mod watch;
 
-use std::io::Write;
-use std::path::Path;
-use std::ffi::OsStr;
 use std::error::Error;
+use std::fmt;
+use std::io::{Read as _, Write as _};
+use std::path::{Path, PathBuf};
 use std::result;
 
-use pulldown_cmark::{Parser, Event, Tag, CodeBlockKind, Options};
-use walkdir::WalkDir;
-use handlebars::Handlebars;
-use regex::Regex;
-
-use orbit::Orbit;
+use clap::Parser as _;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::SubscriberExt as _;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt as _;
 
-type Result<T> = result::Result<T, Box<dyn Error>>;
+use orbit_rs::{config, deploy, epub, export, lint, Config, OrbitError, Page, Site};
+use orbit_rs::diagnostic::{Diagnostic, Level};
+use orbit_rs::ignore::IgnoreRules;
+use orbit_rs::site::{NO_DESTINATION, NO_SOURCE};
 
-lazy_static::lazy_static! {
-    static ref NORMAL_FOOTNOTE: Regex = Regex::new("\\[\\^(.*)\\]:(.*)$").unwrap();
-}
+use cli::{BookFormat, BuildArgs, Cli, Command, DeckFormat, DeployArgs, MessageFormat, RenderArgs};
 
-const TEMPLATE: &str = include_str!("../template.html");
-const CSS: &str = include_str!("../tufte.css");
+type Result<T> = result::Result<T, Box<dyn Error>>;
 
-fn main() -> Result<()> {
-    let args: Vec<String> = std::env::args().collect();  
-    let src_dir_opt = args.get(1);
-    let dest_dir_opt = args.get(2);
+const SCAFFOLD_CONFIG: &str = r#"source = "notes"
+destination = "site"
+template = "template.html"
+css = "tufte.css"
+"#;
+
+const NO_DEPLOY_TARGET: &str = "nowhere to deploy to; set `target` in `[deploy]` in orbit.toml";
+
+/// What markdown read from stdin is called in warnings and errors.
+const STDIN_NAME: &str = "<stdin>";
+/// Where markdown read from stdin is rendered as if it came from, which
+/// decides where its links point.
+const STDIN_PAGE: &str = "stdin.md";
+
+/// Prints orbit's own events to stderr, prefixed with their level unless
+/// they're the usual progress messages.
+struct LogFormat;
+
+impl<S, N> FormatEvent<S, N> for LogFormat
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &tracing::Event<'_>) -> fmt::Result {
+        let level = *event.metadata().level();
+        if level != tracing::Level::INFO {
+            write!(writer, "{}: ", level.as_str().to_lowercase())?;
+        }
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
 
-    if let (Some(src), Some(dest)) = (src_dir_opt, dest_dir_opt) {
-        walk_markdown_directory(src, dest)?;
+        return writeln!(writer);
     }
-
-    return Ok(())
 }
 
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+}
 
-fn walk_markdown_directory<P: AsRef<Path>>(source: P, destination: P) -> Result<()> {
-    let walker = WalkDir::new(source).into_iter();
-    for entry in walker.filter_entry(|e| !is_hidden(e)) {
-        let entry = entry?;
-        let filename = entry.file_name();
-        let metadata = entry.metadata()?;
-
-        if !metadata.is_dir() && is_markdown(filename) {
-            let entry_path = entry.path();
-            let markdown = std::fs::read_to_string(&entry_path)?;
-
-            // I want to skip over the frontmatter. Because this is a small
-            // project, I can assume the frontmatter will be four lines long,
-            // excluding the `---` delimitters. So, skipping the first 6
-            // newlines ('\n') is equivalent to skipping all frontmatter.
-            let mut idx: usize = 0;
-            let mut newline_no: u32 = 0;
-            let markdown_bytes = markdown.as_bytes();
-            while newline_no < 6 {
-                if markdown_bytes[idx] == '\n' as u8 {
-                    newline_no += 1;
+fn run() -> Result<()> {
+    let cli = Cli::parse();
+    // Only orbit's own events, leaving out any its dependencies emit.
+    let level = cli.log_level();
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().event_format(LogFormat).with_writer(std::io::stderr))
+        .with(Targets::new().with_target("orbit", level).with_target("orbit_rs", level))
+        .try_init()?;
+    let mut config = Config::load(&cli.config)?;
+
+    match &cli.command {
+        Command::Build(args) => match args.render_args() {
+            Some(render_args) => render_file(config, &render_args)?,
+            None => {
+                args.site.apply(&mut config);
+                let site = build_site(config, args)?;
+
+                if args.watch {
+                    watch::watch_site(site, args.message_format, || {})?;
                 }
-
-                idx += 1
             }
-
-            let render = markdown_to_html(&markdown[idx..])?;
-            let dest_path = destination.as_ref().join(filename).with_extension("html");
-            let mut file = std::fs::File::create(&dest_path)?;
-            write!(&mut file, "{}", render)?;
+        },
+        Command::Init(args) => init_site(&args.directory)?,
+        Command::Serve(args) => {
+            args.site.apply(&mut config);
+            let site = build_site(config, &BuildArgs::default())?;
+            serve::serve_site(site, args.port)?;
+        }
+        Command::Render(args) => render_file(config, args)?,
+        Command::ExportDeck(args) => {
+            let source = args.source.as_ref().or(config.source.as_ref()).ok_or(NO_SOURCE)?;
+            let ignore = IgnoreRules::load(source, &config.exclude)?;
+            let output = args.output.clone().unwrap_or_else(|| PathBuf::from(args.format.default_output()));
+            export_deck(source, &ignore, args.format, &output)?;
+        }
+        Command::Export(args) => {
+            let source = args.source.as_ref().or(config.source.as_ref()).ok_or(NO_SOURCE)?;
+            let ignore = IgnoreRules::load(source, &config.exclude)?;
+            export_book(&config, source, &ignore, args.format, &args.output)?;
+        }
+        Command::Lint(args) => {
+            let source = args.source.as_ref().or(config.source.as_ref()).ok_or(NO_SOURCE)?;
+            let ignore = IgnoreRules::load(source, &config.exclude)?;
+            lint_site(source, &ignore)?;
+        }
+        Command::Deploy(args) => {
+            args.site.apply(&mut config);
+            deploy_site(config, args)?;
+        }
+        Command::Clean(args) => {
+            let destination = args.destination.as_ref().or(config.destination.as_ref());
+            clean_site(destination)?;
         }
     }
 
-    let css_dest_path = destination.as_ref().join("tufte.css");
-    let mut file = std::fs::File::create(&css_dest_path)?;
-    write!(&mut file, "{}", CSS)?;
-
     return Ok(())
 }
 
-fn markdown_to_html(markdown: &str) -> Result<String> {
-    let mut options = Options::empty();
-    options.insert(Options::ENABLE_STRIKETHROUGH);
-    options.insert(Options::ENABLE_SMART_PUNCTUATION);
-    options.insert(Options::ENABLE_FOOTNOTES);
-
-    let (content, footnotes) = split_content_and_footnotes(&markdown);
-
-    let parser = Parser::new_ext(&content, options).into_offset_iter();
-    let mut html_output = String::new();
+/// Builds the site as `args` ask and reports any warnings and broken
+/// internal links, failing on them under `--strict`. A dry run writes
+/// nothing, so links are left unchecked.
+fn build_site(config: Config, args: &BuildArgs) -> Result<Site> {
+    let start = std::time::Instant::now();
+    let (strict, dry_run, format) = (args.strict, args.dry_run, args.message_format);
+    let level = if strict { Level::Error } else { Level::Warning };
+    let mut site = Site::new(config).map_err(|err| report_error(err, format, false))?;
+    site.set_dry_run(dry_run);
+    site.set_prune(args.clean_dest);
+    let summary = site.build(args.force).map_err(|err| report_error(err, format, false))?;
+    for warning in &summary.warnings {
+        report_problem(warning, level, format, false);
+    }
+    let verb = if dry_run { "would build" } else { "built" };
+    tracing::info!("{} {} pages ({} unchanged) in {:.2?}", verb, summary.rendered, summary.skipped, start.elapsed());
+    if summary.pruned > 0 {
+        let verb = if dry_run { "would delete" } else { "deleted" };
+        tracing::info!("{} {} stale files", verb, summary.pruned);
+    }
 
-    let mut in_orbit_block = false;
-    let mut footnote_no: u32 = 0;
+    let broken = match dry_run {
+        true => Vec::new(),
+        false => site.check_links().map_err(|err| report_error(err, format, false))?,
+    };
+    for link in &broken {
+        match format {
+            MessageFormat::Human => eprintln!("broken link: {}", link),
+            MessageFormat::Json => println!("{}", Diagnostic::broken_link(level, link, site.destination()).to_json()),
+        }
+    }
+    let problems = summary.warnings.len() + broken.len();
+    if strict && problems > 0 {
+        return Err(format!("found {} problems, which --strict makes errors", problems).into());
+    }
 
-    let mut events = Vec::new();
-    for event in parser {
-        match event {
-            (Event::FootnoteReference(name), _) => {
-                footnote_no += 1;
-                let footnote_html = format!("<sup class=\"fn\"><a id=\"{}-back\" href=\"#{}\">[{}]</a></sup>", name, name, footnote_no);
-                events.push(Event::Html(footnote_html.into()));
-            }
-            (Event::Start(Tag::Link(foo, destination, bar)), _) => {
-                let mut new_destination = destination.to_string();
-                if destination.ends_with(".md") {
-                    new_destination = destination.replace(".md", ".html");
-                }
+    return Ok(site)
+}
 
-                events.push(Event::Start(Tag::Link(foo, new_destination.into(), bar)));
-            }
-            (Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(language))), range) => {
-                if language.clone().into_string() == "orbit" {
-                    let codeblock = &markdown[range.start..range.end];
-                    let orbit: Orbit = deserialize_orbit_codeblock(codeblock)?;
-                    let orbit_html = orbit.to_html()?;
+/// Builds the site unless `args` say not to, then publishes the
+/// destination to the configured `[deploy]` target.
+fn deploy_site(config: Config, args: &DeployArgs) -> Result<()> {
+    let target = config.deploy.clone().ok_or(NO_DEPLOY_TARGET)?;
+    let destination = match args.no_build {
+        true => config.destination.clone().ok_or(NO_DESTINATION)?,
+        false => {
+            let build_args = BuildArgs { strict: args.strict, message_format: args.message_format, ..BuildArgs::default() };
+            build_site(config, &build_args)?.destination().to_path_buf()
+        }
+    };
 
-                    in_orbit_block = true; 
+    deploy::deploy(&target, &destination, args.dry_run)?;
+    let verb = if args.dry_run { "would deploy" } else { "deployed" };
+    tracing::info!("{} {} to {}", verb, destination.display(), target.name());
 
-                    events.push(Event::Html(orbit_html.into()));
-                }
-            },
-            (Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(language))), _) => {
-                if language.clone().into_string() == "orbit" {
-                    in_orbit_block = false;
-                }
-            }
+    return Ok(());
+}
 
-            _ => {
-                if !in_orbit_block { // Practically, skip over content in Orbit blocks
-                    events.push(event.0);
-                }
-            }
+/// Renders the markdown file `args` name, or stdin if it's `-`, to the
+/// output they name, or stdout if it's `-`, reporting warnings as
+/// [`build_site`] does. Wikilinks resolve against the configured source
+/// directory if the file is in it, and against the file's own directory
+/// otherwise; markdown from stdin is treated as a note in the current
+/// directory.
+fn render_file(mut config: Config, args: &RenderArgs) -> Result<()> {
+    let format = args.message_format;
+    let level = if args.strict { Level::Error } else { Level::Warning };
+    let configured = config.source.as_ref().and_then(|source| source.canonicalize().ok());
+    let (page, source, markdown) = match args.input == Path::new("-") {
+        true => {
+            let mut markdown = String::new();
+            std::io::stdin().read_to_string(&mut markdown)?;
+            let markdown = orbit_rs::normalize_source(&markdown).into_owned();
+            let source = configured.unwrap_or(std::env::current_dir()?);
+            // Includes are resolved against the current directory.
+            let page = Page { path: PathBuf::from(STDIN_NAME), relative: PathBuf::from(STDIN_PAGE) };
+            (page, source, markdown)
         }
+        false => {
+            let path = args.input.canonicalize().map_err(OrbitError::io(&args.input))?;
+            let source = match configured {
+                Some(source) if path.starts_with(&source) => source,
+                _ => path.parent().unwrap_or(Path::new("/")).to_path_buf(),
+            };
+            let markdown = orbit_rs::read_note(&path)?;
+            let relative = path.strip_prefix(&source)?.to_path_buf();
+            (Page { path, relative }, source, markdown)
+        }
+    };
+
+    if let Some(template) = &args.template {
+        config.template = Some(template.clone());
+    }
+    config.minify |= args.minify;
+    // Nothing is written to the destination, but a site needs one.
+    config.destination = Some(config.destination.unwrap_or_else(|| source.clone()));
+    config.source = Some(source);
+    let page_on_stdout = args.output == Path::new("-");
+    let site = Site::new(config).map_err(|err| report_error(err, format, page_on_stdout))?;
+    let (html, warnings) = site.render_standalone(&page, &markdown, args.fragment)
+        .map_err(|err| report_error(err, format, page_on_stdout))?;
+    for warning in &warnings {
+        report_problem(warning, level, format, page_on_stdout);
     }
 
-    pulldown_cmark::html::push_html(&mut html_output, events.into_iter());
-    let footnotes_html = fmt_footnotes_to_html(footnotes)?;
-    html_output.push_str(&footnotes_html);
+    if page_on_stdout {
+        std::io::stdout().write_all(html.as_bytes())?;
+    } else {
+        std::fs::write(&args.output, html).map_err(OrbitError::io(&args.output))?;
+        tracing::info!("rendered {} to {}", page.path.display(), args.output.display());
+    }
+    if args.strict && !warnings.is_empty() {
+        return Err(format!("found {} problems, which --strict makes errors", warnings.len()).into());
+    }
 
-    let mut register = Handlebars::new();
-    register.register_escape_fn(handlebars::no_escape);
+    return Ok(());
+}
 
-    let body_map = &serde_json::json!({"body": html_output});
-    let render = register.render_template(TEMPLATE, body_map)?;
+/// Prints a warning from building the site in `format`.
+fn report_warning(warning: &OrbitError, format: MessageFormat) {
+    report_problem(warning, Level::Warning, format, false);
+}
 
-    return Ok(render);
+/// Prints a problem found building the site in `format`, as a warning or,
+/// under `--strict`, an error. JSON goes to stderr if `page_on_stdout`, so
+/// it doesn't end up in the page.
+fn report_problem(problem: &OrbitError, level: Level, format: MessageFormat, page_on_stdout: bool) {
+    match (format, level) {
+        (MessageFormat::Human, Level::Warning) => eprintln!("warning: {}", problem),
+        (MessageFormat::Human, Level::Error) => eprintln!("error: {}", problem),
+        (MessageFormat::Json, _) => print_json(&Diagnostic::from_error(level, problem).to_json(), page_on_stdout),
+    }
 }
 
-fn split_content_and_footnotes(markdown: &str) -> (String, Vec<String>) {
-    let mut footnotes = Vec::new();
-    let mut content = Vec::new();
+/// Prints `err` as a diagnostic if `format` asks for JSON, to stderr if
+/// `page_on_stdout`, and hands it back to be printed for people on the way
+/// out.
+fn report_error(err: OrbitError, format: MessageFormat, page_on_stdout: bool) -> Box<dyn Error> {
+    if format == MessageFormat::Json {
+        print_json(&Diagnostic::from_error(Level::Error, &err).to_json(), page_on_stdout);
+    }
 
-    for line in markdown.lines() {
-        if line.starts_with("[^") {
-            footnotes.push(line.to_string());
-        } else {
-            content.push(line);
-        }
+    return err.into();
+}
+
+/// Prints a JSON diagnostic on its own line, to stderr if stdout is taken.
+fn print_json(json: &str, to_stderr: bool) {
+    match to_stderr {
+        true => eprintln!("{}", json),
+        false => println!("{}", json),
     }
+}
 
-    return (content.join("\n"), footnotes);
+/// Writes every card under `source` to `output` as a deck in `format`.
+fn export_deck(source: &Path, ignore: &IgnoreRules, format: DeckFormat, output: &Path) -> Result<()> {
+    let cards = export::collect_cards(source, ignore)?;
+    let contents = match format {
+        DeckFormat::AnkiCsv => export::anki_csv(&cards).into_bytes(),
+        DeckFormat::Apkg => export::anki_apkg(&cards)?,
+    };
+    std::fs::write(output, contents).map_err(OrbitError::io(output))?;
+    println!("exported {} cards to {}", cards.len(), output.display());
+
+    return Ok(());
 }
 
-fn fmt_footnotes_to_html(footnotes: Vec<String>) -> Result<String> {
-    let mut markdown = String::from("---\n");
-    for footnote in &footnotes {
-        let captures = NORMAL_FOOTNOTE.captures(&footnote).unwrap();
-        let formatted = format!("1. {} <a class=\"fn-back\" href=\"#{}-back\">↩</a>", &captures[2], &captures[1]);
-        markdown.push_str(&formatted);
+/// Writes the notes under `source` to `output` as a book in `format`.
+fn export_book(config: &Config, source: &Path, ignore: &IgnoreRules, format: BookFormat, output: &Path) -> Result<()> {
+    let book = match format {
+        BookFormat::Epub => epub::build_epub(config, source, ignore)?,
+    };
+    for warning in &book.warnings {
+        report_warning(warning, MessageFormat::Human);
     }
+    std::fs::write(output, &book.bytes)?;
+    println!("exported {} notes to {}", book.chapters, output.display());
 
-    let mut options = Options::empty();
-    options.insert(Options::ENABLE_STRIKETHROUGH);
-    options.insert(Options::ENABLE_SMART_PUNCTUATION);
-    options.insert(Options::ENABLE_FOOTNOTES);
+    return Ok(());
+}
 
-    let mut footnote_no: usize = 0;
-    let parser = Parser::new_ext(&markdown, options);
-    let events = parser.map(|event| match event {
-        Event::Start(Tag::Item) => {
-            let capture = NORMAL_FOOTNOTE.captures(&footnotes[footnote_no]).unwrap();
-            footnote_no += 1;
+/// Prints every problem with the cards under `source`, failing if there
+/// were any so CI can catch them.
+fn lint_site(source: &Path, ignore: &IgnoreRules) -> Result<()> {
+    let issues = lint::lint_site(source, ignore)?;
+    for issue in &issues {
+        println!("{}", issue);
+    }
+    if !issues.is_empty() {
+        return Err(format!("found {} problems with Orbit cards", issues.len()).into());
+    }
 
-            Event::Html(format!("<li id=\"{}\">", &capture[1]).into())
-        }
-        Event::Start(Tag::Link(foo, destination, bar)) => {
-            let mut new_destination = destination.to_string();
-            if destination.ends_with(".md") {
-                new_destination = destination.replace(".md", ".html");
-            }
+    return Ok(());
+}
 
-            Event::Start(Tag::Link(foo, new_destination.into(), bar))
+/// Writes a starter `orbit.toml`, the bundled template and stylesheet, and an
+/// empty notes directory into `directory`. Existing files are left alone.
+fn init_site(directory: &Path) -> Result<()> {
+    std::fs::create_dir_all(directory.join("notes"))?;
+
+    let files = [
+        (config::CONFIG_FILENAME, SCAFFOLD_CONFIG),
+        ("template.html", orbit_rs::TEMPLATE),
+        ("tufte.css", orbit_rs::CSS),
+    ];
+    for (filename, contents) in files {
+        let path = directory.join(filename);
+        if path.exists() {
+            println!("skipping {}: already exists", path.display());
+            continue;
         }
 
-        _ => event,
-    });
-
-    let mut html_output = String::new();
-    pulldown_cmark::html::push_html(&mut html_output, events);
+        std::fs::write(&path, contents)?;
+        println!("created {}", path.display());
+    }
 
-    Ok(html_output)
+    return Ok(())
 }
 
-fn deserialize_orbit_codeblock(codeblock: &str) -> Result<Orbit> {
-    let json = &codeblock[9..(codeblock.len() - 4)];
-    let orbit: Orbit = serde_json::from_str(json)?;
-
-    Ok(orbit)
-}
+fn clean_site(destination: Option<&PathBuf>) -> Result<()> {
+    let destination = destination
+        .ok_or(NO_DESTINATION)?;
 
-fn is_hidden(entry: &walkdir::DirEntry) -> bool {
-    entry.file_name()
-        .to_str()
-        .map(|s| s.starts_with("."))
-        .unwrap_or(false)
-}
+    if destination.exists() {
+        std::fs::remove_dir_all(destination)?;
+        println!("removed {}", destination.display());
+    }
 
-fn is_markdown(filename: &OsStr) -> bool {
-    filename.to_string_lossy().ends_with(".md")
+    return Ok(())
 }
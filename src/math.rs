@@ -0,0 +1,202 @@
+use pulldown_cmark::escape::escape_html;
+
+/// Loads KaTeX and typesets every `\(...\)` and `\[...\]` on the page.
+pub const KATEX_HEAD: &str = r#"<link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/katex@0.16.8/dist/katex.min.css">
+<script defer src="https://cdn.jsdelivr.net/npm/katex@0.16.8/dist/katex.min.js"></script>
+<script defer src="https://cdn.jsdelivr.net/npm/katex@0.16.8/dist/contrib/auto-render.min.js" onload="renderMathInElement(document.body);"></script>
+"#;
+
+/// Brackets the index of an extracted span in the markdown handed to the
+/// parser. Private-use characters pass through pulldown-cmark untouched.
+const PLACEHOLDER_START: char = '\u{E000}';
+const PLACEHOLDER_END: char = '\u{E001}';
+
+/// Pulls `$...$` and `$$...$$` spans out of `markdown` before it is parsed, so
+/// TeX like `a_1 * b_2 * c` isn't mangled into emphasis.
+///
+/// Each span is replaced by a placeholder and rendered to the markup KaTeX's
+/// auto-render extension looks for; [`restore_math`] swaps them back into the
+/// generated HTML. Fenced code blocks, code spans, and `\$` are left alone.
+pub fn extract_math(markdown: &str) -> (String, Vec<String>) {
+    return extract_spans(markdown, render_span);
+}
+
+/// Like [`extract_math`], but keeps each span as its original `$`-delimited
+/// TeX, for markup such as Orbit prompts that typesets math itself.
+pub fn protect_math(markdown: &str) -> (String, Vec<String>) {
+    return extract_spans(markdown, |tex, display| {
+        let delimiter = if display { "$$" } else { "$" };
+        format!("{}{}{}", delimiter, tex, delimiter)
+    });
+}
+
+fn extract_spans(markdown: &str, render: fn(&str, bool) -> String) -> (String, Vec<String>) {
+    let mut output = String::with_capacity(markdown.len());
+    let mut spans = Vec::new();
+    let mut fence: Option<&str> = None;
+    let mut paragraph = String::new();
+
+    for line in markdown.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if let Some(marker) = fence {
+            output.push_str(line);
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            replace_math(&paragraph, &mut output, &mut spans, render);
+            paragraph.clear();
+            output.push_str(line);
+            fence = Some(&trimmed[..3]);
+            continue;
+        }
+
+        // Display math may span lines, so spans are matched a paragraph at a
+        // time rather than a line at a time.
+        paragraph.push_str(line);
+        if line.trim().is_empty() {
+            replace_math(&paragraph, &mut output, &mut spans, render);
+            paragraph.clear();
+        }
+    }
+    replace_math(&paragraph, &mut output, &mut spans, render);
+
+    return (output, spans);
+}
+
+/// Replaces the placeholders left by [`extract_math`] with rendered math.
+pub fn restore_math(html: &str, spans: &[String]) -> String {
+    if spans.is_empty() {
+        return html.to_string();
+    }
+
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find(PLACEHOLDER_START) {
+        output.push_str(&rest[..start]);
+        let after = &rest[(start + PLACEHOLDER_START.len_utf8())..];
+        let end = match after.find(PLACEHOLDER_END) {
+            Some(end) => end,
+            None => {
+                rest = after;
+                continue;
+            }
+        };
+
+        match after[..end].parse::<usize>().ok().and_then(|idx| spans.get(idx)) {
+            Some(span) => output.push_str(span),
+            None => output.push_str(&after[..end]),
+        }
+        rest = &after[(end + PLACEHOLDER_END.len_utf8())..];
+    }
+    output.push_str(rest);
+
+    return output;
+}
+
+fn replace_math(text: &str, output: &mut String, spans: &mut Vec<String>, render: fn(&str, bool) -> String) {
+    let bytes = text.as_bytes();
+    let mut idx = 0;
+    let mut copied = 0;
+
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'\\' => idx += 2,
+            b'`' => {
+                // Skip over the whole code span, matching backtick run lengths.
+                let run = bytes[idx..].iter().take_while(|&&b| b == b'`').count();
+                let fence = &text[idx..(idx + run)];
+                idx = match text[(idx + run)..].find(fence) {
+                    Some(close) => idx + run + close + run,
+                    None => idx + run,
+                };
+            }
+            b'$' => {
+                let display = bytes.get(idx + 1) == Some(&b'$');
+                let found = if display {
+                    find_display_end(text, idx + 2)
+                } else {
+                    find_inline_end(text, idx + 1)
+                };
+
+                match found {
+                    Some((tex_end, span_end)) => {
+                        let delimiter = if display { 2 } else { 1 };
+                        let tex = &text[(idx + delimiter)..tex_end];
+
+                        output.push_str(&text[copied..idx]);
+                        output.push(PLACEHOLDER_START);
+                        output.push_str(&spans.len().to_string());
+                        output.push(PLACEHOLDER_END);
+                        spans.push(render(tex, display));
+
+                        idx = span_end;
+                        copied = span_end;
+                    }
+                    None => idx += if display { 2 } else { 1 },
+                }
+            }
+            _ => idx += 1,
+        }
+    }
+
+    output.push_str(&text[copied.min(text.len())..]);
+}
+
+/// Finds the closing `$$` of display math whose TeX starts at `start`,
+/// returning where the TeX ends and where the closing delimiter ends.
+fn find_display_end(text: &str, start: usize) -> Option<(usize, usize)> {
+    let close = text[start..].find("$$")? + start;
+    if text[start..close].trim().is_empty() {
+        return None;
+    }
+
+    return Some((close, close + 2));
+}
+
+/// Finds the closing `$` of inline math whose TeX starts at `start`. Like
+/// Pandoc, the TeX may not start or end with whitespace and the closing `$`
+/// may not be followed by a digit, so `$5 and $10` stays literal text.
+fn find_inline_end(text: &str, start: usize) -> Option<(usize, usize)> {
+    let bytes = text.as_bytes();
+    if bytes.get(start).is_none_or(|b| b.is_ascii_whitespace()) {
+        return None;
+    }
+
+    let mut idx = start;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'\\' => idx += 2,
+            b'\n' => return None,
+            b'$' => {
+                let before_space = bytes[idx - 1].is_ascii_whitespace();
+                let after_digit = bytes.get(idx + 1).is_some_and(|b| b.is_ascii_digit());
+                if idx > start && !before_space && !after_digit {
+                    return Some((idx, idx + 1));
+                }
+                return None;
+            }
+            _ => idx += 1,
+        }
+    }
+
+    return None;
+}
+
+fn render_span(tex: &str, display: bool) -> String {
+    let (class, open, close) = if display {
+        ("math display", "\\[", "\\]")
+    } else {
+        ("math inline", "\\(", "\\)")
+    };
+
+    let mut html = format!("<span class=\"{}\">{}", class, open);
+    let _ = escape_html(&mut html, tex);
+    html.push_str(close);
+    html.push_str("</span>");
+
+    return html;
+}
@@ -0,0 +1,63 @@
+/// Elements whose content is kept exactly as written.
+const PRESERVED: &[&str] = &["pre", "textarea", "script", "style"];
+
+/// Strips comments from `html` and collapses every run of whitespace to a
+/// single space, or a single newline if it spanned lines. The content of
+/// `<pre>`, `<textarea>`, `<script>`, and `<style>` is left alone.
+pub fn minify_html(html: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+    // Closing tags are found in any case here. ASCII lowercasing keeps byte
+    // offsets, so this lines up with `html`.
+    let lowered = html.to_ascii_lowercase();
+
+    while !rest.is_empty() {
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = match comment.find("-->") {
+                Some(end) => &comment[(end + 3)..],
+                None => "",
+            };
+            continue;
+        }
+
+        if let Some(element) = preserved_element(rest) {
+            let close = format!("</{}", element);
+            let end = lowered[(html.len() - rest.len())..].find(&close)
+                .and_then(|start| rest[start..].find('>').map(|end| start + end + 1))
+                .unwrap_or(rest.len());
+            output.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+
+        let c = rest.chars().next().unwrap_or_default();
+        if c.is_whitespace() {
+            let run = rest.len() - rest.trim_start().len();
+            let newline = rest[..run].contains('\n');
+            // A removed comment can leave two runs side by side.
+            match output.chars().next_back() {
+                Some(' ') if newline => {
+                    output.pop();
+                    output.push('\n');
+                }
+                Some(' ' | '\n') => {}
+                _ => output.push(if newline { '\n' } else { ' ' }),
+            }
+            rest = &rest[run..];
+        } else {
+            output.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    return output;
+}
+
+/// The name of the preserved element `html` starts with, if it does.
+fn preserved_element(html: &str) -> Option<&'static str> {
+    let tag = html.strip_prefix('<')?;
+    PRESERVED.iter().copied().find(|element| {
+        tag.get(..element.len()).is_some_and(|name| name.eq_ignore_ascii_case(element))
+            && tag[element.len()..].starts_with(|c: char| c == '>' || c.is_whitespace())
+    })
+}
@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use pulldown_cmark::escape::escape_href;
+
+use crate::config::NavOptions;
+use crate::site::Page;
+use crate::urls::normalize;
+
+/// A link in the menu atop every page, resolved against the site.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NavLink {
+    pub label: String,
+    target: NavTarget,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum NavTarget {
+    External(String),
+    /// A page in the site, relative to the site root, and the directory the
+    /// link stands for, if any, which it's current on every page in.
+    Page { output: PathBuf, section: Option<PathBuf> },
+}
+
+impl NavLink {
+    /// The link as the template sees it on the page written to `output`,
+    /// which is `root` away from the site root. The URL comes escaped for an
+    /// attribute, so templates print it in triple braces.
+    pub fn to_json(&self, output: &Path, root: &str) -> serde_json::Value {
+        let (url, current) = match &self.target {
+            NavTarget::External(url) => (url.clone(), false),
+            NavTarget::Page { output: target, section } => {
+                let url = format!("{}{}", root, target.to_string_lossy().replace('\\', "/"));
+                let current = target == output || section.as_ref().is_some_and(|section| output.starts_with(section));
+                (url, current)
+            }
+        };
+
+        let mut href = String::new();
+        let _ = escape_href(&mut href, &url);
+
+        return serde_json::json!({ "label": self.label, "url": href, "current": current });
+    }
+}
+
+/// The menu: every item in `[nav]`, then, if `auto` is set, a link to each
+/// top-level directory of `pages` no item already links, to its `index.md`
+/// or else its first page.
+pub fn nav_links(options: &NavOptions, pages: &[Page]) -> Vec<NavLink> {
+    let mut links: Vec<NavLink> = options.items.iter()
+        .map(|item| NavLink { label: item.label.clone(), target: item_target(&item.path) })
+        .collect();
+    if !options.auto {
+        return links;
+    }
+
+    let mut directories: BTreeMap<PathBuf, Vec<&Page>> = BTreeMap::new();
+    for page in pages {
+        let mut components = page.relative.components();
+        if let (Some(directory), Some(_)) = (components.next(), components.next()) {
+            directories.entry(PathBuf::from(directory.as_os_str())).or_default().push(page);
+        }
+    }
+    for (directory, mut pages) in directories {
+        pages.sort_by(|a, b| a.relative.cmp(&b.relative));
+        let index = directory.join("index.md");
+        let page = pages.iter().find(|page| page.relative == index).unwrap_or(&pages[0]);
+        let output = page.output_path();
+        let linked = links.iter().any(|link| match &link.target {
+            NavTarget::Page { output: target, section } => *target == output || section.as_ref() == Some(&directory),
+            NavTarget::External(_) => false,
+        });
+        if linked {
+            continue;
+        }
+        links.push(NavLink {
+            label: directory_label(&directory),
+            target: NavTarget::Page { output, section: Some(directory) },
+        });
+    }
+
+    return links;
+}
+
+/// Where a `[nav]` item's `path` leads.
+fn item_target(path: &str) -> NavTarget {
+    if path.contains("://") || path.starts_with("mailto:") {
+        return NavTarget::External(path.to_string());
+    }
+
+    let relative = normalize(Path::new(path.trim_start_matches('/'))).unwrap_or_default();
+    if path.ends_with('/') || relative.as_os_str().is_empty() {
+        let section = Some(relative.clone()).filter(|section| !section.as_os_str().is_empty());
+        return NavTarget::Page { output: relative.join("index.html"), section };
+    }
+    let output = match relative.extension() {
+        Some(extension) if extension == "md" => relative.with_extension("html"),
+        _ => relative,
+    };
+
+    return NavTarget::Page { output, section: None };
+}
+
+/// A directory's name as a menu label: `field-notes` becomes `Field notes`.
+fn directory_label(directory: &Path) -> String {
+    let name = directory.to_string_lossy().replace(['-', '_'], " ");
+    let mut chars = name.chars();
+
+    return match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => name,
+    };
+}
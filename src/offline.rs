@@ -0,0 +1,109 @@
+use std::path::{Path, PathBuf};
+
+use pulldown_cmark::escape::escape_href;
+use serde_json::json;
+
+use crate::config::Config;
+use crate::images;
+
+pub const MANIFEST_FILENAME: &str = "manifest.json";
+pub const SERVICE_WORKER_FILENAME: &str = "sw.js";
+
+/// The service worker, with `{{version}}` and `{{precache}}` to fill in.
+const SERVICE_WORKER: &str = include_str!("sw.js");
+
+/// Files in the destination that aren't part of the site as readers see it.
+const NOT_CACHED: &[&str] = &[SERVICE_WORKER_FILENAME, crate::redirect::REDIRECTS_FILENAME, "CNAME"];
+
+/// Links the manifest from a page `root` away from the site root, and
+/// registers the service worker beside it. The script finds the worker by
+/// the link, whose URL is fitted to the page like any other.
+pub fn head(root: &str) -> String {
+    let mut head = String::from("<link rel=\"manifest\" href=\"");
+    let _ = escape_href(&mut head, root);
+    head.push_str(MANIFEST_FILENAME);
+    head.push_str("\">\n<script>\nif (\"serviceWorker\" in navigator) {\n");
+    head.push_str("    var manifest = document.querySelector(\"link[rel=manifest]\").href;\n");
+    head.push_str(&format!("    navigator.serviceWorker.register(new URL(\"{}\", manifest));\n", SERVICE_WORKER_FILENAME));
+    head.push_str("}\n</script>\n");
+
+    return head;
+}
+
+/// The web app manifest, named after the site unless `[manifest]` says
+/// otherwise. Icon sizes are read from the icons themselves.
+pub fn web_manifest(config: &Config) -> String {
+    let options = &config.manifest;
+    let name = options.name.as_deref().or(config.title.as_deref()).unwrap_or("Notes");
+    let icons: Vec<serde_json::Value> = options.icons.iter()
+        .map(|icon| {
+            let src = icon.to_string_lossy().replace('\\', "/");
+            let mut icon_json = json!({ "src": src });
+            if let Some((width, height)) = config.source.as_ref().and_then(|source| images::dimensions(&source.join(icon))) {
+                icon_json["sizes"] = format!("{}x{}", width, height).into();
+            }
+            if let Some(extension) = icon.extension() {
+                let extension = extension.to_string_lossy().to_lowercase();
+                let media_type = if extension == "svg" { String::from("image/svg+xml") } else { format!("image/{}", extension) };
+                icon_json["type"] = media_type.into();
+            }
+            icon_json
+        })
+        .collect();
+
+    let mut manifest = json!({
+        "name": name,
+        "short_name": options.short_name.as_deref().unwrap_or(name),
+        "start_url": "./",
+        "scope": "./",
+        "display": "standalone",
+        "icons": icons,
+    });
+    for (key, value) in [
+        ("description", &options.description),
+        ("theme_color", &options.theme_color),
+        ("background_color", &options.background_color),
+    ] {
+        if let Some(value) = value {
+            manifest[key] = value.clone().into();
+        }
+    }
+
+    return serde_json::to_string_pretty(&manifest).unwrap_or_default();
+}
+
+/// The service worker caching every URL in `precache` under `version`,
+/// which changing makes browsers fetch everything afresh.
+pub fn service_worker(precache: &[String], version: &str) -> String {
+    let precache = serde_json::to_string(precache).unwrap_or_default();
+
+    return SERVICE_WORKER.replace("{{version}}", version).replace("{{precache}}", &precache);
+}
+
+/// The URLs, relative to the site root, of the `files` in the destination
+/// that are cached, with an `index.html` as its directory.
+pub fn precache_urls(files: &[PathBuf]) -> Vec<String> {
+    let mut urls: Vec<String> = files.iter()
+        .filter(|file| is_cached(file))
+        .map(|file| {
+            let url = file.to_string_lossy().replace('\\', "/");
+            match url.strip_suffix("index.html") {
+                Some("") => String::from("./"),
+                Some(directory) if directory.ends_with('/') => directory.to_string(),
+                _ => url,
+            }
+        })
+        .collect();
+    urls.sort();
+    urls.dedup();
+
+    return urls;
+}
+
+/// Whether the service worker caches `file`, relative to the destination:
+/// anything but hidden files and those only the server or the browser read.
+pub fn is_cached(file: &Path) -> bool {
+    let hidden = file.components().any(|component| component.as_os_str().to_string_lossy().starts_with('.'));
+
+    return !hidden && !NOT_CACHED.iter().any(|name| Path::new(name) == file);
+}
@@ -1,24 +1,260 @@
-use std::error::Error;
-use std::result;
+use std::path::{Component, Path, PathBuf};
 
 use serde::{Serialize, Deserialize};
 use handlebars::Handlebars;
+use regex::{Captures, Regex};
 
-const REVIEW_START_TEMPLATE: &str = r#"<orbit-reviewarea>"#;
-const PROMPT_TEMPLATE: &str = r#"<orbit-prompt question="{{question}}" answer="{{answer}}"></orbit-prompt>"#;
+use crate::error::{self, OrbitError, Result};
+use crate::{cache, math};
+
+/// Loads the web component that turns review areas into interactive prompts.
+pub const ORBIT_HEAD: &str = r#"<script type="module" src="https://js.withorbit.com/orbit-web-component.js"></script>
+"#;
+
+/// How every review area starts, to tell whether a page has one.
+pub const REVIEW_START: &str = "<orbit-reviewarea";
+/// How every prompt starts, to count a page's prompts.
+pub const PROMPT_START: &str = "<orbit-prompt ";
+const REVIEW_START_TEMPLATE: &str = r#"<orbit-reviewarea{{#if color}} color="{{color}}"{{/if}}>"#;
+const PROMPT_TEMPLATE: &str = r#"<orbit-prompt id="{{id}}"{{#if color}} color="{{color}}"{{/if}} {{#if cloze}}cloze="{{cloze}}"{{else}}question="{{question}}"{{#if question_attachments}} question-attachments="{{question_attachments}}"{{/if}} answer="{{answer}}"{{#if answer_attachments}} answer-attachments="{{answer_attachments}}"{{/if}}{{/if}}></orbit-prompt>"#;
+/// Shown in place of the prompt to readers without JavaScript. The fields are
+/// already rendered to HTML, hence the triple braces.
+const FALLBACK_TEMPLATE: &str = r#"<noscript><details class="orbit-fallback"><summary>{{{question}}}</summary>{{{answer}}}</details></noscript>"#;
 const REVIEW_END: &str = "</orbit-reviewarea>";
 
-type Result<T> = result::Result<T, Box<dyn Error>>;
+lazy_static::lazy_static! {
+    /// The interactive parts of a review area, leaving the fallbacks.
+    static ref INTERACTIVE: Regex =
+        Regex::new(r#"<orbit-reviewarea[^>]*>|</orbit-reviewarea>|<orbit-prompt [^>]*></orbit-prompt>|<noscript>(<details class="orbit-fallback">)|(</details>)</noscript>"#).unwrap();
+}
+
+/// Directory under the destination for attachments from outside the source
+/// directory.
+pub const ATTACHMENTS_DIR: &str = "attachments";
+
+/// A local file a card's attachment refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    /// The file, relative to the source directory. May start with `..`.
+    pub source: PathBuf,
+    /// Where the file belongs, relative to the destination directory.
+    pub output: PathBuf,
+}
+
+impl Attachment {
+    /// Resolves `url`, written in the note at `relative` within the source
+    /// directory, to a local file. URLs with a scheme, absolute paths, and
+    /// fragments aren't local files.
+    fn resolve(url: &str, relative: &Path) -> Option<Attachment> {
+        if url.is_empty() || url.contains(':') || url.starts_with('/') || url.starts_with('#') {
+            return None;
+        }
+
+        let directory = relative.parent().unwrap_or_else(|| Path::new(""));
+        let mut source = PathBuf::new();
+        for component in directory.join(url).components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir if matches!(source.components().next_back(), Some(Component::Normal(_))) => {
+                    source.pop();
+                }
+                other => source.push(other),
+            }
+        }
+
+        // Files outside the source directory wouldn't otherwise be copied, so
+        // they're gathered into a directory of their own.
+        let output = match source.starts_with("..") {
+            true => Path::new(ATTACHMENTS_DIR).join(source.file_name()?),
+            false => source.clone(),
+        };
+
+        return Some(Attachment { source, output });
+    }
+}
+
+/// The serialization of the deck inside an `orbit` codeblock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeckFormat {
+    /// Plain `orbit` blocks: JSON if the deck starts with `{`, TOML if it
+    /// starts with a `[[deck]]` table or a `key = value` line, shorthand if it starts with `Q:` or
+    /// `C:`, and YAML otherwise.
+    Detect,
+    Json,
+    Yaml,
+    Toml,
+    /// `Q:` and `A:` lines, or `C:` for a cloze card, with a blank line
+    /// between cards. Lines without a prefix continue the line before.
+    Shorthand,
+    /// A two-column Markdown table, one card per row: the question, then the
+    /// answer. The header row is skipped.
+    Table,
+}
+
+impl DeckFormat {
+    /// The format named by a fenced block's info string, if it holds a deck:
+    /// `orbit`, `orbit-json`, `orbit-yaml`, `orbit-toml`, `orbit-qa`, or
+    /// `orbit-table`.
+    pub fn from_language(language: &str) -> Option<DeckFormat> {
+        match language.trim() {
+            "orbit" => Some(DeckFormat::Detect),
+            "orbit-json" => Some(DeckFormat::Json),
+            "orbit-yaml" => Some(DeckFormat::Yaml),
+            "orbit-toml" => Some(DeckFormat::Toml),
+            "orbit-qa" => Some(DeckFormat::Shorthand),
+            "orbit-table" => Some(DeckFormat::Table),
+            _ => None,
+        }
+    }
+
+    fn detect(deck: &str) -> DeckFormat {
+        let trimmed = deck.trim_start();
+        let first_line = trimmed.lines().next().unwrap_or_default();
+        if trimmed.starts_with('{') {
+            DeckFormat::Json
+        } else if trimmed.starts_with("[[") || first_line.contains(" = ") {
+            DeckFormat::Toml
+        } else if trimmed.starts_with("Q:") || trimmed.starts_with("C:") {
+            DeckFormat::Shorthand
+        } else {
+            DeckFormat::Yaml
+        }
+    }
+}
+
+/// Prefixes `message` with the path to the field it concerns, unless the
+/// parser already named it.
+fn field_message(path: &serde_path_to_error::Path, message: &str) -> String {
+    let path = path.to_string();
+    if path == "." || message.starts_with(&path) {
+        return message.to_string();
+    }
+
+    return format!("{}: {}", path, message);
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Orbit {
+    /// Orbit's name for the review area's colour, e.g. `blue` or `orange`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    color: Option<String>,
     deck: Vec<OrbitCard>
 }
 
 impl Orbit {
-    pub fn to_html(&self) -> Result<String> {
-        let mut review = String::from(REVIEW_START_TEMPLATE);
+    /// Parses a deck written in `format`. `line` is the line of the file the
+    /// deck starts on, so errors point at the right place in the note.
+    pub fn parse(deck: &str, format: DeckFormat, line: usize) -> Result<Orbit> {
+        let format = match format {
+            DeckFormat::Detect => DeckFormat::detect(deck),
+            format => format,
+        };
+
+        // Parser line numbers are 1-based, so one is taken off `line`. Errors
+        // are prefixed with the path of the field at fault, e.g. `deck[2].answer`.
+        let orbit: Orbit = match format {
+            DeckFormat::Json | DeckFormat::Detect => {
+                let mut deserializer = serde_json::Deserializer::from_str(deck);
+                serde_path_to_error::deserialize(&mut deserializer).map_err(|err| {
+                    let offset = error::byte_offset(deck, err.inner().line(), err.inner().column());
+                    let (err_line, column) = error::line_column(deck, offset);
+                    let message = field_message(err.path(), &err.inner().to_string());
+                    OrbitError::orbit_deck(line + err_line - 1, column, &message)
+                })?
+            }
+            DeckFormat::Yaml => {
+                let deserializer = serde_yaml::Deserializer::from_str(deck);
+                serde_path_to_error::deserialize(deserializer).map_err(|err| {
+                    let (err_line, column) = err.inner().location()
+                        .map(|location| (location.line(), location.column()))
+                        .unwrap_or((1, 1));
+                    let message = field_message(err.path(), &err.inner().to_string());
+                    OrbitError::orbit_deck(line + err_line - 1, column, &message)
+                })?
+            }
+            DeckFormat::Toml => {
+                let deserializer = toml::Deserializer::new(deck);
+                serde_path_to_error::deserialize(deserializer).map_err(|err| {
+                    let offset = err.inner().span().map(|span| span.start).unwrap_or(0);
+                    let (err_line, column) = error::line_column(deck, offset);
+                    let message = field_message(err.path(), &err.inner().message().replace('\n', "; "));
+                    OrbitError::orbit_deck(line + err_line - 1, column, &message)
+                })?
+            }
+            DeckFormat::Shorthand => parse_shorthand(deck, line)?,
+            DeckFormat::Table => parse_table(deck, line)?,
+        };
+        orbit.validate().map_err(|message| OrbitError::orbit_deck(line, 1, &message))?;
+
+        return Ok(orbit);
+    }
+
+    pub fn new(deck: Vec<OrbitCard>) -> Orbit {
+        Orbit { color: None, deck }
+    }
+
+    /// Rewrites attachments that are local files, relative to the note at
+    /// `relative`, to point at their copy under the destination via `root`,
+    /// returning the files to copy.
+    pub fn resolve_attachments(&mut self, relative: &Path, root: &str) -> Vec<Attachment> {
+        let mut attachments = Vec::new();
+        for card in &mut self.deck {
+            for url in [&mut card.question_attachments, &mut card.answer_attachments].into_iter().flatten() {
+                if let Some(attachment) = Attachment::resolve(url, relative) {
+                    *url = format!("{}{}", root, attachment.output.to_string_lossy());
+                    attachments.push(attachment);
+                }
+            }
+        }
+
+        return attachments;
+    }
+
+    /// The cards in this deck.
+    pub fn cards(&self) -> &[OrbitCard] {
+        &self.deck
+    }
+
+    /// Checks that every card is either a cloze deletion or a question and
+    /// answer pair, describing the first that isn't.
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        for (idx, card) in self.deck.iter().enumerate() {
+            let valid = match &card.cloze {
+                Some(_) => card.question.is_none() && card.answer.is_none(),
+                None => card.question.is_some() && card.answer.is_some(),
+            };
+            if !valid {
+                return Err(format!("deck[{}]: needs either `cloze` or both `question` and `answer`", idx));
+            }
+            if card.reversed && card.cloze.is_some() {
+                return Err(format!("deck[{}]: cloze cards can't be `reversed`", idx));
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// The prompts this deck turns into: every card, each followed by its
+    /// answer-to-question twin if it's `reversed`.
+    pub fn prompts(&self) -> Vec<OrbitCard> {
+        let mut prompts = Vec::with_capacity(self.deck.len());
         for card in &self.deck {
+            prompts.push(OrbitCard { reversed: false, ..card.clone() });
+            if card.reversed {
+                prompts.push(card.swapped());
+            }
+        }
+
+        return prompts;
+    }
+
+    /// Renders the deck as a review area, coloured by its own `color` or else
+    /// by `default_color`.
+    pub fn to_html(&self, default_color: Option<&str>) -> Result<String> {
+        let color = self.color.as_deref().or(default_color);
+        let register = Handlebars::new();
+        let mut review = register.render_template(REVIEW_START_TEMPLATE, &serde_json::json!({ "color": color }))?;
+        for card in &self.prompts() {
             let card_as_html = card.to_html()?;
             review.push_str(&card_as_html);
         }
@@ -28,24 +264,277 @@ impl Orbit {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// `html` with every review area replaced by the static fallbacks of its
+/// prompts, for readers that can't run the Orbit component.
+pub fn static_fallback(html: &str) -> String {
+    if !html.contains(REVIEW_START) {
+        return html.to_string();
+    }
+
+    let stripped = INTERACTIVE.replace_all(html, |captures: &Captures| {
+        captures.get(1).or_else(|| captures.get(2)).map_or("", |kept| kept.as_str()).to_string()
+    });
+
+    return stripped.into_owned();
+}
+
+/// Parses a [`DeckFormat::Shorthand`] deck starting on `line` of its file.
+fn parse_shorthand(deck: &str, line: usize) -> Result<Orbit> {
+    let mut cards = Vec::new();
+    let mut card = OrbitCard::default();
+    // The field the previous line went into, for continuation lines.
+    let mut last_field = None;
+
+    for (idx, text) in deck.lines().enumerate() {
+        let trimmed = text.trim();
+        if trimmed.is_empty() {
+            if card != OrbitCard::default() {
+                cards.push(std::mem::take(&mut card));
+            }
+            last_field = None;
+            continue;
+        }
+
+        let (name, value) = match trimmed.split_once(':') {
+            Some((name @ ("Q" | "A" | "C"), value)) => (Some(name), value.trim()),
+            _ => (None, trimmed),
+        };
+        let field = match name.or(last_field) {
+            Some("Q") => Some(&mut card.question),
+            Some("A") => Some(&mut card.answer),
+            Some("C") => Some(&mut card.cloze),
+            _ => None,
+        };
+
+        match field {
+            Some(slot) if name.is_some() && slot.is_some() => {
+                let message = format!("deck[{}]: second `{}:` line in one card", cards.len(), name.unwrap_or_default());
+                return Err(OrbitError::orbit_deck(line + idx, 1, &message));
+            }
+            Some(slot) => match slot {
+                Some(existing) => {
+                    existing.push('\n');
+                    existing.push_str(value);
+                }
+                None => *slot = Some(value.to_string()),
+            },
+            None => {
+                return Err(OrbitError::orbit_deck(line + idx, 1, "expected a line starting with `Q:`, `A:`, or `C:`"));
+            }
+        }
+        last_field = name.or(last_field);
+    }
+    if card != OrbitCard::default() {
+        cards.push(card);
+    }
+
+    return Ok(Orbit { color: None, deck: cards });
+}
+
+/// Parses a [`DeckFormat::Table`] deck starting on `line` of its file.
+fn parse_table(deck: &str, line: usize) -> Result<Orbit> {
+    let mut cards = Vec::new();
+    let mut seen_header = false;
+
+    for (idx, text) in deck.lines().enumerate() {
+        let trimmed = text.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+
+        let cells = table_cells(trimmed);
+        if !seen_header {
+            seen_header = true;
+            continue;
+        }
+        if cells.iter().all(|cell| !cell.is_empty() && cell.chars().all(|c| matches!(c, '-' | ':'))) {
+            continue;
+        }
+
+        match cells.as_slice() {
+            [question, answer] if !question.is_empty() && !answer.is_empty() => cards.push(OrbitCard {
+                question: Some(question.clone()),
+                answer: Some(answer.clone()),
+                ..OrbitCard::default()
+            }),
+            _ => {
+                let message = format!("deck[{}]: expected a row with a question and an answer", cards.len());
+                return Err(OrbitError::orbit_deck(line + idx, 1, &message));
+            }
+        }
+    }
+
+    return Ok(Orbit { color: None, deck: cards });
+}
+
+/// Splits a Markdown table row into its trimmed cells. The outer pipes are
+/// optional and `\|` is a literal pipe.
+fn table_cells(row: &str) -> Vec<String> {
+    let row = row.strip_prefix('|').unwrap_or(row);
+    let row = match row.strip_suffix('|') {
+        Some(rest) if !rest.ends_with('\\') => rest,
+        _ => row,
+    };
+
+    let mut cells = vec![String::new()];
+    let mut chars = row.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                cells.last_mut().unwrap().push('|');
+                chars.next();
+            }
+            '|' => cells.push(String::new()),
+            c => cells.last_mut().unwrap().push(c),
+        }
+    }
+
+    return cells.into_iter().map(|cell| cell.trim().to_string()).collect();
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
 pub struct OrbitCard {
-    question: String,
-    answer: String,
+    /// Identifies the card to Orbit across edits, so its review history is
+    /// kept. Derived from the question or cloze text when not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    question: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    answer: Option<String>,
+    /// Colour of this prompt, overriding the review area's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    color: Option<String>,
+    /// Also prompt with the answer, expecting the question.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    reversed: bool,
+    /// Text with `{curly braced}` deletions, making this a cloze card in
+    /// place of a question and answer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cloze: Option<String>,
+    /// URL of an image shown alongside the question.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    question_attachments: Option<String>,
+    /// URL of an image shown alongside the answer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    answer_attachments: Option<String>,
 }
 
 impl OrbitCard {
+    /// A cloze card deleting each `{curly braced}` span of `cloze`.
+    pub fn from_cloze(cloze: String) -> OrbitCard {
+        OrbitCard { cloze: Some(cloze), ..OrbitCard::default() }
+    }
+
+    /// The reverse of this card, with question and answer swapped. An
+    /// explicit `id` gets a `-reversed` suffix to keep the two apart.
+    fn swapped(&self) -> OrbitCard {
+        OrbitCard {
+            id: self.id.as_ref().map(|id| format!("{}-reversed", id)),
+            question: self.answer.clone(),
+            answer: self.question.clone(),
+            question_attachments: self.answer_attachments.clone(),
+            answer_attachments: self.question_attachments.clone(),
+            color: self.color.clone(),
+            reversed: false,
+            cloze: None,
+        }
+    }
+
+    /// The card's `id`, or one derived from a hash of its question or cloze
+    /// text, so it only changes when the prompt itself does.
+    pub fn id(&self) -> String {
+        if let Some(id) = &self.id {
+            return id.clone();
+        }
+
+        let prompt = self.cloze.as_deref().or(self.question.as_deref()).unwrap_or_default();
+        let hash = cache::hash_bytes(prompt.as_bytes());
+
+        return hash[..16].to_string();
+    }
+
+    pub fn question(&self) -> Option<&str> {
+        self.question.as_deref()
+    }
+
+    pub fn answer(&self) -> Option<&str> {
+        self.answer.as_deref()
+    }
+
+    pub fn cloze(&self) -> Option<&str> {
+        self.cloze.as_deref()
+    }
+
+    pub fn question_attachments(&self) -> Option<&str> {
+        self.question_attachments.as_deref()
+    }
+
+    pub fn answer_attachments(&self) -> Option<&str> {
+        self.answer_attachments.as_deref()
+    }
+
     pub fn to_html(&self) -> Result<String> {
         let card_map = &serde_json::json!({
-            "question": self.question,
-            "answer": self.answer
+            "id": self.id(),
+            "color": self.color,
+            "question": self.question.as_deref().map(render_field),
+            "answer": self.answer.as_deref().map(render_field),
+            "question_attachments": self.question_attachments,
+            "answer_attachments": self.answer_attachments,
+            "cloze": self.cloze.as_deref().map(render_field),
         });
 
+        // Every value lands in an attribute, so the registry's default HTML
+        // escaping stays on. Markup rendered from the fields survives as
+        // escaped text, which the prompt decodes back into markup.
+        let register = Handlebars::new();
+        let mut render = register.render_template(PROMPT_TEMPLATE, card_map)?;
 
-        let mut register = Handlebars::new();
-        register.register_escape_fn(handlebars::no_escape);
-        let render = register.render_template(PROMPT_TEMPLATE, card_map)?;
+        // A cloze card is asked with its deletions blanked out and answered
+        // with them highlighted.
+        let fallback_map = &match self.cloze.as_deref() {
+            Some(cloze) => serde_json::json!({
+                "question": render_field(&replace_deletions(cloze, |_| "[…]".to_string())),
+                "answer": render_field(&replace_deletions(cloze, |deletion| format!("<mark>{}</mark>", deletion))),
+            }),
+            None => serde_json::json!({
+                "question": self.question.as_deref().map(render_field),
+                "answer": self.answer.as_deref().map(render_field),
+            }),
+        };
+        render.push_str(&register.render_template(FALLBACK_TEMPLATE, fallback_map)?);
 
         return Ok(render);
     }
 }
+
+/// Renders a card field as inline markdown. TeX is left between its `$`
+/// delimiters for the prompt to typeset.
+fn render_field(field: &str) -> String {
+    let (markdown, math_spans) = math::protect_math(field);
+
+    return math::restore_math(&crate::render_inline(&markdown), &math_spans);
+}
+
+/// Replaces each `{deletion}` in cloze text with `replace(deletion)`, leaving
+/// braces inside `$` math alone.
+pub(crate) fn replace_deletions<F: FnMut(&str) -> String>(cloze: &str, mut replace: F) -> String {
+    let (text, math_spans) = math::protect_math(cloze);
+
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text.as_str();
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+
+        output.push_str(&rest[..start]);
+        output.push_str(&replace(&rest[(start + 1)..end]));
+        rest = &rest[(end + 1)..];
+    }
+    output.push_str(rest);
+
+    return math::restore_math(&output, &math_spans);
+}
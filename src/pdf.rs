@@ -0,0 +1,43 @@
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process::Command;
+
+use crate::error::{OrbitError, Result};
+
+/// Headless browsers that can print to PDF, under the names they're
+/// usually installed as.
+const BROWSER_COMMANDS: &[&str] = &["chromium", "chromium-browser", "google-chrome", "google-chrome-stable"];
+
+/// Prints the page at `html` to `pdf` with `browser`, or the first headless
+/// Chrome or Chromium on the path if it's not given.
+pub fn print_to_pdf(html: &Path, pdf: &Path, browser: Option<&Path>) -> Result<()> {
+    let html = html.canonicalize().map_err(OrbitError::io(html))?;
+    let commands: Vec<&Path> = match browser {
+        Some(browser) => vec![browser],
+        None => BROWSER_COMMANDS.iter().map(Path::new).collect(),
+    };
+
+    for command in commands {
+        let output = Command::new(command)
+            .args(["--headless", "--disable-gpu", "--no-pdf-header-footer"])
+            .arg(format!("--print-to-pdf={}", pdf.display()))
+            .arg(&html)
+            .output();
+        match output {
+            Ok(output) if output.status.success() => return Ok(()),
+            Ok(output) => {
+                return Err(OrbitError::Pdf {
+                    path: html,
+                    message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+                });
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound && browser.is_none() => continue,
+            Err(err) => return Err(OrbitError::io(command)(err)),
+        }
+    }
+
+    return Err(OrbitError::Pdf {
+        path: html,
+        message: String::from("no headless Chrome or Chromium is installed; set `browser` in `[print]`"),
+    });
+}
@@ -0,0 +1,65 @@
+use std::path::{Path, PathBuf};
+
+use pulldown_cmark::escape::{escape_href, escape_html};
+
+use crate::urls::normalize;
+
+/// Netlify's file of redirects, written if `netlify_redirects` is set.
+pub const REDIRECTS_FILENAME: &str = "_redirects";
+
+/// A page's old URL, from `aliases` in its frontmatter, and where it went.
+pub struct Redirect {
+    /// The alias as written, without a leading `/`.
+    pub from: String,
+    /// Where the stub page for the alias is written, relative to the site
+    /// root.
+    pub output: PathBuf,
+    /// The page's URL relative to the site root.
+    pub to: String,
+}
+
+/// Where the stub page for `alias` goes, relative to the site root: the
+/// file it names, or `index.html` in it if it's a directory like `old/` or
+/// has no extension. `None` if it climbs out of the site.
+pub fn alias_output(alias: &str) -> Option<PathBuf> {
+    let alias = alias.trim_start_matches('/');
+    let path = normalize(Path::new(alias))?;
+    if path.as_os_str().is_empty() || alias.ends_with('/') || path.extension().is_none() {
+        return Some(path.join("index.html"));
+    }
+
+    return Some(path);
+}
+
+/// A page sending readers straight on to `url`, relative to it, and telling
+/// search engines the page there is the one to index.
+pub fn redirect_html(url: &str) -> String {
+    let mut href = String::new();
+    let _ = escape_href(&mut href, url);
+    let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Redirecting…</title>\n");
+    html.push_str(&format!("<link rel=\"canonical\" href=\"{}\">\n", href));
+    html.push_str(&format!("<meta http-equiv=\"refresh\" content=\"0; url={}\">\n", href));
+    html.push_str("<meta name=\"robots\" content=\"noindex\">\n</head>\n<body>\n<p>This page has moved to <a href=\"");
+    html.push_str(&href);
+    html.push_str("\">");
+    let _ = escape_html(&mut html, url);
+    html.push_str("</a>.</p>\n</body>\n</html>\n");
+
+    return html;
+}
+
+/// Netlify's `_redirects` for `redirects`, as permanent redirects. `base_url`
+/// gives the path the site is served under, if it's not the root.
+pub fn netlify_redirects(redirects: &[Redirect], base_url: Option<&str>) -> String {
+    let base_path = base_url
+        .and_then(|base_url| base_url.split_once("://"))
+        .and_then(|(_, rest)| rest.find('/').map(|idx| rest[idx..].trim_end_matches('/').to_string()))
+        .unwrap_or_default();
+
+    let mut lines = String::new();
+    for redirect in redirects {
+        lines.push_str(&format!("{}/{} {}/{} 301\n", base_path, redirect.from, base_path, redirect.to));
+    }
+
+    return lines;
+}
@@ -0,0 +1,129 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+
+use pulldown_cmark::escape::{escape_href, escape_html};
+use serde::Serialize;
+
+use crate::backlinks::outgoing_links;
+use crate::frontmatter;
+use crate::site::Page;
+use crate::wikilink::NoteIndex;
+
+/// What a link between two pages, either way, adds to how related they are.
+const LINK_WEIGHT: usize = 3;
+/// What each tag two pages share adds.
+const TAG_WEIGHT: usize = 2;
+/// What each page both link to adds.
+const SHARED_LINK_WEIGHT: usize = 1;
+
+/// A page listed under another as related to it.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RelatedPage {
+    /// The related page's frontmatter title, or its file name without one.
+    pub title: String,
+    /// Where the related page is rendered, relative to the site root.
+    pub output: PathBuf,
+}
+
+/// For every page, the pages most related to it through the tags they share
+/// and the links between them, most related first.
+#[derive(Debug, Default)]
+pub struct Related {
+    related: HashMap<PathBuf, Vec<RelatedPage>>,
+}
+
+impl Related {
+    /// Scores every pair of pages, where `sources[i]` is the markdown of
+    /// `pages[i]`, keeping the `limit` most related to each.
+    pub fn new(pages: &[Page], sources: &[String], notes: &NoteIndex, limit: usize) -> Related {
+        if limit == 0 {
+            return Related::default();
+        }
+
+        let outputs: HashMap<PathBuf, usize> = pages.iter().enumerate()
+            .map(|(idx, page)| (page.output_path(), idx))
+            .collect();
+        let mut titles = Vec::new();
+        let mut tagged: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        let mut linked_from: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        let mut scores: Vec<HashMap<usize, usize>> = vec![HashMap::new(); pages.len()];
+
+        for (idx, (page, markdown)) in pages.iter().zip(sources).enumerate() {
+            let (frontmatter, content) = match frontmatter::split_frontmatter(markdown) {
+                Ok((frontmatter, content)) => (frontmatter, content),
+                // The page's own render reports the error.
+                Err(_) => (Default::default(), markdown.as_str()),
+            };
+            let name = page.relative.file_stem().map(|stem| stem.to_string_lossy().into_owned());
+            titles.push(frontmatter.title.or(name).unwrap_or_default());
+            for tag in frontmatter.tags.into_iter().collect::<BTreeSet<_>>() {
+                tagged.entry(tag).or_default().push(idx);
+            }
+
+            let targets: BTreeSet<usize> = outgoing_links(page, content, notes).iter()
+                .filter_map(|target| outputs.get(target).copied())
+                .filter(|&target| target != idx)
+                .collect();
+            for target in targets {
+                *scores[idx].entry(target).or_default() += LINK_WEIGHT;
+                *scores[target].entry(idx).or_default() += LINK_WEIGHT;
+                linked_from.entry(target).or_default().push(idx);
+            }
+        }
+
+        for (weight, groups) in [(TAG_WEIGHT, tagged.values().collect::<Vec<_>>()), (SHARED_LINK_WEIGHT, linked_from.values().collect())] {
+            for group in groups {
+                for &a in group {
+                    for &b in group.iter().filter(|&&b| b != a) {
+                        *scores[a].entry(b).or_default() += weight;
+                    }
+                }
+            }
+        }
+
+        let related = pages.iter().zip(scores)
+            .map(|(page, scores)| {
+                let mut ranked: Vec<(usize, usize)> = scores.into_iter().collect();
+                ranked.sort_by(|(a, a_score), (b, b_score)| {
+                    b_score.cmp(a_score).then_with(|| titles[*a].cmp(&titles[*b])).then_with(|| a.cmp(b))
+                });
+                let ranked = ranked.into_iter()
+                    .take(limit)
+                    .map(|(idx, _)| RelatedPage { title: titles[idx].clone(), output: pages[idx].output_path() })
+                    .collect();
+                (page.output_path(), ranked)
+            })
+            .collect();
+
+        return Related { related };
+    }
+
+    /// The pages most related to `page`, most related first.
+    pub fn to(&self, page: &Page) -> &[RelatedPage] {
+        match self.related.get(&page.output_path()) {
+            Some(related) => related,
+            None => &[],
+        }
+    }
+}
+
+/// Renders the "See also" section appended to a page, or nothing if no page
+/// is related to it. `root` is the path from the page back to the site root.
+pub fn related_html(related: &[RelatedPage], root: &str) -> String {
+    if related.is_empty() {
+        return String::new();
+    }
+
+    let mut html = String::from("<section class=\"related\">\n<h2>See also</h2>\n<ul>\n");
+    for page in related {
+        html.push_str("<li><a href=\"");
+        let _ = escape_href(&mut html, root);
+        let _ = escape_href(&mut html, &page.output.to_string_lossy());
+        html.push_str("\">");
+        let _ = escape_html(&mut html, &page.title);
+        html.push_str("</a></li>\n");
+    }
+    html.push_str("</ul>\n</section>\n");
+
+    return html;
+}
@@ -0,0 +1,87 @@
+// Searches search-index.json, which sits beside this script at the site root,
+// as the reader types. Every word of the query must appear somewhere in a
+// page; matches in titles, tags, and headings count for more than in the text.
+(function () {
+    var root = new URL(".", document.currentScript.src);
+    var input = document.getElementById("search-input");
+    var results = document.getElementById("search-results");
+    var pages = [];
+
+    fetch(new URL("search-index.json", root))
+        .then(function (response) { return response.json(); })
+        .then(function (index) {
+            pages = index;
+            var query = new URLSearchParams(location.search).get("q");
+            if (query) {
+                input.value = query;
+            }
+            search();
+        });
+    input.addEventListener("input", search);
+
+    function search() {
+        var words = input.value.toLowerCase().split(/\s+/).filter(Boolean);
+        results.textContent = "";
+        if (!words.length) {
+            return;
+        }
+
+        pages
+            .map(function (page) { return { page: page, score: score(page, words) }; })
+            .filter(function (result) { return result.score > 0; })
+            .sort(function (a, b) { return b.score - a.score; })
+            .slice(0, 50)
+            .forEach(function (result) { results.appendChild(item(result.page, words)); });
+    }
+
+    function score(page, words) {
+        var title = page.title.toLowerCase();
+        var tags = page.tags.join(" ").toLowerCase();
+        var headings = page.headings.map(function (heading) { return heading.text; }).join(" ").toLowerCase();
+        var text = page.text.toLowerCase();
+        var total = 0;
+        for (var i = 0; i < words.length; i++) {
+            var word = words[i];
+            var found = (title.indexOf(word) >= 0 ? 10 : 0)
+                + (tags.indexOf(word) >= 0 ? 5 : 0)
+                + (headings.indexOf(word) >= 0 ? 3 : 0)
+                + (text.indexOf(word) >= 0 ? 1 : 0);
+            if (!found) {
+                return 0;
+            }
+            total += found;
+        }
+        return total;
+    }
+
+    function item(page, words) {
+        var li = document.createElement("li");
+        var link = document.createElement("a");
+        var heading = page.headings.find(function (heading) {
+            return words.every(function (word) { return heading.text.toLowerCase().indexOf(word) >= 0; });
+        });
+        link.href = new URL(page.url + (heading ? "#" + heading.id : ""), root);
+        link.textContent = page.title;
+        li.appendChild(link);
+
+        var context = snippet(page.text, words[0]) || page.excerpt;
+        if (context) {
+            var p = document.createElement("p");
+            p.className = "excerpt";
+            p.textContent = context;
+            li.appendChild(p);
+        }
+        return li;
+    }
+
+    // Some of the text around the first place `word` appears.
+    function snippet(text, word) {
+        var at = text.toLowerCase().indexOf(word);
+        if (at < 0) {
+            return null;
+        }
+        var start = Math.max(0, at - 60);
+        var end = Math.min(text.length, at + word.length + 100);
+        return (start > 0 ? "…" : "") + text.slice(start, end) + (end < text.length ? "…" : "");
+    }
+})();
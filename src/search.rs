@@ -0,0 +1,129 @@
+use std::collections::HashSet;
+
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use serde::Serialize;
+
+use crate::frontmatter::{self, Frontmatter};
+use crate::listing::PageSummary;
+use crate::social::shorten;
+use crate::wikilink;
+
+pub const INDEX_FILENAME: &str = "search-index.json";
+pub const SCRIPT_FILENAME: &str = "search.js";
+
+/// The searcher run by the generated search page, fetching the index beside
+/// itself.
+pub const SCRIPT: &str = include_str!("search.js");
+
+/// A page as the client-side searcher sees it.
+#[derive(Serialize, Debug)]
+pub struct SearchEntry {
+    pub title: String,
+    /// The page's URL relative to the site root.
+    pub url: String,
+    pub excerpt: Option<String>,
+    pub headings: Vec<SearchHeading>,
+    pub tags: Vec<String>,
+    /// The page's prose, leaving out code blocks and Orbit decks.
+    pub text: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SearchHeading {
+    pub text: String,
+    /// The heading's anchor in the page.
+    pub id: String,
+}
+
+impl SearchEntry {
+    /// Indexes the page `summary` describes, written as `markdown`, under
+    /// `url`.
+    pub fn new(summary: &PageSummary, markdown: &str, url: String) -> SearchEntry {
+        let (frontmatter, content) = frontmatter::split_frontmatter(markdown)
+            .unwrap_or_else(|_| (Frontmatter::default(), markdown));
+        let mut options = Options::empty();
+        options.insert(Options::ENABLE_FOOTNOTES);
+        options.insert(Options::ENABLE_HEADING_ATTRIBUTES);
+        options.insert(Options::ENABLE_TABLES);
+
+        let mut text = String::new();
+        let mut first_paragraph: Option<String> = None;
+        let mut paragraph: Option<String> = None;
+        let mut heading: Option<(Option<String>, String)> = None;
+        let mut heading_ids = HashSet::new();
+        let mut headings = Vec::new();
+        let mut in_code_block = false;
+
+        for event in Parser::new_ext(content, options) {
+            // Blocks are kept apart in the text, inline markup isn't.
+            match &event {
+                Event::Start(Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(..))
+                    | Event::End(Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(..)) => {}
+                Event::Start(_) | Event::End(_) | Event::SoftBreak | Event::HardBreak => text.push(' '),
+                _ => {}
+            }
+
+            match event {
+                Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+                Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+                Event::Start(Tag::Paragraph) if first_paragraph.is_none() => paragraph = Some(String::new()),
+                Event::End(Tag::Paragraph) => {
+                    if let Some(paragraph) = paragraph.take().filter(|paragraph| !paragraph.trim().is_empty()) {
+                        first_paragraph = Some(paragraph);
+                    }
+                }
+                Event::Start(Tag::Heading(_, id, _)) => heading = Some((id.map(str::to_string), String::new())),
+                Event::End(Tag::Heading(..)) => {
+                    if let Some((id, heading_text)) = heading.take() {
+                        let id = match id {
+                            Some(id) => {
+                                heading_ids.insert(id.clone());
+                                id
+                            }
+                            None => crate::unique_slug(&heading_text, &mut heading_ids),
+                        };
+                        headings.push(SearchHeading { text: heading_text, id });
+                    }
+                }
+                Event::Text(fragment) | Event::Code(fragment) if !in_code_block => {
+                    text.push_str(&fragment);
+                    if let Some(paragraph) = paragraph.as_mut() {
+                        paragraph.push_str(&fragment);
+                    }
+                    if let Some((_, heading_text)) = heading.as_mut() {
+                        heading_text.push_str(&fragment);
+                    }
+                }
+                Event::SoftBreak | Event::HardBreak => {
+                    if let Some(paragraph) = paragraph.as_mut() {
+                        paragraph.push(' ');
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let excerpt = frontmatter.description
+            .or_else(|| first_paragraph.map(|paragraph| paragraph.split_whitespace().collect::<Vec<_>>().join(" ")))
+            .map(|excerpt| shorten(wikilink::strip_wikilinks(&excerpt)));
+
+        return SearchEntry {
+            title: summary.title.clone(),
+            url,
+            excerpt,
+            headings,
+            tags: summary.tags.clone(),
+            text: wikilink::strip_wikilinks(&text.split_whitespace().collect::<Vec<_>>().join(" ")),
+        };
+    }
+}
+
+/// The body of the generated search page.
+pub fn search_html() -> String {
+    let mut html = String::from("<form class=\"search\" role=\"search\" onsubmit=\"return false\">\n");
+    html.push_str("<input type=\"search\" id=\"search-input\" placeholder=\"Search\" aria-label=\"Search\" autofocus>\n");
+    html.push_str("</form>\n<ul id=\"search-results\" class=\"page-list\"></ul>\n");
+    html.push_str(&format!("<script src=\"{}\"></script>\n", SCRIPT_FILENAME));
+
+    return html;
+}
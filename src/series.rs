@@ -0,0 +1,143 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use pulldown_cmark::escape::{escape_href, escape_html};
+use serde::Serialize;
+
+use crate::date::parse_date;
+use crate::frontmatter;
+use crate::site::Page;
+
+/// A page in a series.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SeriesPage {
+    /// The page's frontmatter title, or its file name without one.
+    pub title: String,
+    /// Where the page is rendered, relative to the site root.
+    pub output: PathBuf,
+    pub date: Option<String>,
+}
+
+/// Where a page stands in its series.
+#[derive(Debug, Clone, Copy)]
+pub struct SeriesPlace<'a> {
+    /// The series' name, as given by `series` in frontmatter.
+    pub name: &'a str,
+    /// Every page in the series, in order.
+    pub pages: &'a [SeriesPage],
+    /// The page's index in `pages`.
+    pub index: usize,
+}
+
+impl SeriesPlace<'_> {
+    /// The page before this one, unless it's the first.
+    pub fn prev(&self) -> Option<&SeriesPage> {
+        self.index.checked_sub(1).map(|index| &self.pages[index])
+    }
+
+    /// The page after this one, unless it's the last.
+    pub fn next(&self) -> Option<&SeriesPage> {
+        self.pages.get(self.index + 1)
+    }
+}
+
+/// Every series of pages in a site, grouped by the `series` key in their
+/// frontmatter.
+#[derive(Debug, Default)]
+pub struct Series {
+    series: BTreeMap<String, Vec<SeriesPage>>,
+    /// Each page in a series, by output path, with its series and index.
+    places: HashMap<PathBuf, (String, usize)>,
+}
+
+impl Series {
+    /// Groups the pages naming a series, where `sources[i]` is the markdown
+    /// of `pages[i]`. A series runs by `series_part`, then date, then path.
+    pub fn new(pages: &[Page], sources: &[String]) -> Series {
+        let mut grouped: BTreeMap<String, Vec<_>> = BTreeMap::new();
+        for (page, markdown) in pages.iter().zip(sources) {
+            let frontmatter = match frontmatter::split_frontmatter(markdown) {
+                Ok((frontmatter, _)) => frontmatter,
+                // The page's own render reports the error.
+                Err(_) => continue,
+            };
+            let name = match frontmatter.series {
+                Some(name) => name,
+                None => continue,
+            };
+            let date = frontmatter.date;
+            let timestamp = date.as_deref().and_then(parse_date);
+            let title = frontmatter.title.unwrap_or_else(|| {
+                page.relative.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default()
+            });
+            let key = (frontmatter.series_part.is_none(), frontmatter.series_part, timestamp.is_none(), timestamp, page.relative.clone());
+            grouped.entry(name).or_default().push((key, SeriesPage { title, output: page.output_path(), date }));
+        }
+
+        let mut series = BTreeMap::new();
+        let mut places = HashMap::new();
+        for (name, mut pages) in grouped {
+            pages.sort_by(|(a, _), (b, _)| a.cmp(b));
+            let pages: Vec<SeriesPage> = pages.into_iter().map(|(_, page)| page).collect();
+            for (index, page) in pages.iter().enumerate() {
+                places.insert(page.output.clone(), (name.clone(), index));
+            }
+            series.insert(name, pages);
+        }
+
+        return Series { series, places };
+    }
+
+    /// Whether the page written to `output` is in a series.
+    pub fn contains(&self, output: &Path) -> bool {
+        self.places.contains_key(output)
+    }
+
+    /// Where `page` stands in its series, if it's in one.
+    pub fn of(&self, page: &Page) -> Option<SeriesPlace<'_>> {
+        let (name, index) = self.places.get(&page.output_path())?;
+
+        return Some(SeriesPlace { name, pages: &self.series[name], index: *index });
+    }
+}
+
+/// Renders the box listing every page in the series `place` is in, the
+/// current one unlinked, with links to the pages either side. `root` is the
+/// path from the page back to the site root.
+pub fn series_html(place: &SeriesPlace, root: &str) -> String {
+    let mut html = String::from("<nav class=\"series\">\n<p>Part ");
+    html.push_str(&format!("{} of {} in <em>", place.index + 1, place.pages.len()));
+    let _ = escape_html(&mut html, place.name);
+    html.push_str("</em></p>\n<ol>\n");
+    for (index, page) in place.pages.iter().enumerate() {
+        if index == place.index {
+            html.push_str("<li aria-current=\"page\">");
+            let _ = escape_html(&mut html, &page.title);
+            html.push_str("</li>\n");
+        } else {
+            html.push_str("<li>");
+            push_link(&mut html, page, root);
+            html.push_str("</li>\n");
+        }
+    }
+    html.push_str("</ol>\n");
+    for (class, label, page) in [("series-prev", "Previous: ", place.prev()), ("series-next", "Next: ", place.next())] {
+        if let Some(page) = page {
+            html.push_str(&format!("<p class=\"{}\">{}", class, label));
+            push_link(&mut html, page, root);
+            html.push_str("</p>\n");
+        }
+    }
+    html.push_str("</nav>\n");
+
+    return html;
+}
+
+fn push_link(html: &mut String, page: &SeriesPage, root: &str) {
+    html.push_str("<a href=\"");
+    let _ = escape_href(&mut *html, root);
+    let _ = escape_href(&mut *html, &page.output.to_string_lossy());
+    html.push_str("\">");
+    let _ = escape_html(&mut *html, &page.title);
+    html.push_str("</a>");
+}
@@ -0,0 +1,137 @@
+use std::error::Error;
+use std::path::{Component, Path, PathBuf};
+use std::result;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tiny_http::{Header, Request, Response, Server};
+
+use orbit_rs::Site;
+
+use crate::cli::MessageFormat;
+use crate::watch;
+
+/// Path the live-reload script polls for the current build number.
+const RELOAD_ENDPOINT: &str = "/__orbit/build";
+
+/// Polls the server once a second and reloads the page once the build number
+/// changes.
+const RELOAD_SCRIPT: &str = r#"<script>
+(function () {
+    var current = null;
+    setInterval(function () {
+        fetch("/__orbit/build").then(function (r) { return r.text(); }).then(function (build) {
+            if (current !== null && build !== current) { location.reload(); }
+            current = build;
+        }).catch(function () {});
+    }, 1000);
+})();
+</script>"#;
+
+type Result<T> = result::Result<T, Box<dyn Error>>;
+
+/// Serves the site's destination over HTTP while watching its source,
+/// blocking forever.
+pub fn serve_site(site: Site, port: u16) -> Result<()> {
+    let address = format!("127.0.0.1:{}", port);
+    let server = Server::http(&address).map_err(|err| err as Box<dyn Error>)?;
+    tracing::info!("serving {} at http://{}", site.destination().display(), address);
+
+    let build = Arc::new(AtomicU64::new(0));
+    let root = site.destination().to_path_buf();
+    let server_build = Arc::clone(&build);
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let build_no = server_build.load(Ordering::SeqCst);
+            if let Err(err) = respond(request, &root, build_no) {
+                tracing::warn!("failed to respond: {}", err);
+            }
+        }
+    });
+
+    watch::watch_site(site, MessageFormat::Human, || {
+        build.fetch_add(1, Ordering::SeqCst);
+    })?;
+
+    return Ok(());
+}
+
+fn respond(request: Request, root: &Path, build_no: u64) -> Result<()> {
+    let url_path = request.url().split(['?', '#']).next().unwrap_or("/").to_string();
+    if url_path == RELOAD_ENDPOINT {
+        request.respond(Response::from_string(build_no.to_string()))?;
+        return Ok(());
+    }
+
+    let file_path = match resolve_path(root, &url_path) {
+        Some(path) => path,
+        None => {
+            request.respond(Response::from_string("404 Not Found").with_status_code(404))?;
+            return Ok(());
+        }
+    };
+
+    let mime = mime_type(&file_path);
+    let content_type = Header::from_bytes("Content-Type", mime)
+        .map_err(|_| "invalid content type header")?;
+
+    let mut body = std::fs::read(&file_path)?;
+    if mime.starts_with("text/html") {
+        body = inject_reload_script(&String::from_utf8_lossy(&body)).into_bytes();
+    }
+
+    request.respond(Response::from_data(body).with_header(content_type))?;
+
+    return Ok(());
+}
+
+/// Maps a URL path onto a file under `root`, trying `index.html` for
+/// directories and an `.html` extension for extensionless paths.
+fn resolve_path(root: &Path, url_path: &str) -> Option<PathBuf> {
+    let relative = Path::new(url_path.trim_start_matches('/'));
+    if relative.components().any(|c| !matches!(c, Component::Normal(_))) {
+        return None;
+    }
+
+    let path = root.join(relative);
+    let candidates = [
+        path.clone(),
+        path.join("index.html"),
+        path.with_extension("html"),
+    ];
+
+    return candidates.into_iter().find(|candidate| candidate.is_file());
+}
+
+fn inject_reload_script(html: &str) -> String {
+    match html.rfind("</body>") {
+        Some(idx) => format!("{}{}{}", &html[..idx], RELOAD_SCRIPT, &html[idx..]),
+        None => format!("{}{}", html, RELOAD_SCRIPT),
+    }
+}
+
+fn mime_type(path: &Path) -> &'static str {
+    let extension = path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "txt" => "text/plain; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "pdf" => "application/pdf",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        _ => "application/octet-stream",
+    }
+}
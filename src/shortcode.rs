@@ -0,0 +1,163 @@
+use pulldown_cmark::escape::escape_html;
+use regex::Regex;
+
+use crate::error::{OrbitError, Result};
+
+const OPEN: &str = "{{<";
+const CLOSE: &str = ">}}";
+
+lazy_static::lazy_static! {
+    static ref ARGUMENT: Regex =
+        Regex::new(r#"([A-Za-z_][\w-]*)=(?:"((?:[^"\\]|\\.)*)"|(\S+))|"((?:[^"\\]|\\.)*)"|(\S+)"#).unwrap();
+}
+
+/// Replaces every `{{< name key="value" >}}` in `markdown` outside code with
+/// what `render` makes of the shortcode's name and template data. A
+/// shortcode followed by `{{< /name >}}` wraps the text between, which its
+/// template gets as `inner`.
+///
+/// The data holds each `key=value` argument under its key and the rest, in
+/// order, as `args`, all HTML-escaped so they're safe in attributes.
+pub fn expand_shortcodes<F>(markdown: &str, mut render: F) -> Result<String>
+where
+    F: FnMut(&str, &serde_json::Value) -> Result<String>,
+{
+    let code = fenced_ranges(markdown);
+    let mut output = String::with_capacity(markdown.len());
+    let mut last = 0;
+    let mut search_from = 0;
+
+    while let Some(offset) = markdown[search_from..].find(OPEN) {
+        let start = search_from + offset;
+        search_from = start + OPEN.len();
+        if code.iter().any(|range| range.contains(&start)) || in_code_span(markdown, start) {
+            continue;
+        }
+
+        let line = markdown[..start].matches('\n').count() + 1;
+        let error = |message: String| OrbitError::Shortcode { line, message };
+        let tag_end = match markdown[start..].find(CLOSE) {
+            Some(end) => start + end,
+            None => return Err(error(format!("`{}` is never closed with `{}`", OPEN, CLOSE))),
+        };
+        let tag = markdown[(start + OPEN.len())..tag_end].trim();
+        if tag.starts_with('/') {
+            return Err(error(format!("`{}{}{}` closes no shortcode", OPEN, tag, CLOSE)));
+        }
+
+        let (name, data) = parse_tag(tag).ok_or_else(|| error(String::from("shortcode has no name")))?;
+        let mut data = data;
+        let mut end = tag_end + CLOSE.len();
+        if let Some((inner, close_end)) = find_inner(markdown, end, name) {
+            data["inner"] = inner.into();
+            end = close_end;
+        }
+
+        let html = render(name, &data).map_err(|err| error(err.to_string()))?;
+        output.push_str(&markdown[last..start]);
+        output.push_str(html.trim_end());
+        last = end;
+        search_from = end;
+    }
+    output.push_str(&markdown[last..]);
+
+    return Ok(output);
+}
+
+/// Splits a shortcode tag into its name and template data.
+fn parse_tag(tag: &str) -> Option<(&str, serde_json::Value)> {
+    let (name, arguments) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut data = serde_json::Map::new();
+    let mut positional = Vec::new();
+    for captures in ARGUMENT.captures_iter(arguments) {
+        match captures.get(1) {
+            Some(key) => {
+                let value = captures.get(2).map(|value| unquote(value.as_str()))
+                    .or_else(|| captures.get(3).map(|value| value.as_str().to_string()))
+                    .unwrap_or_default();
+                data.insert(key.as_str().to_string(), escape(&value).into());
+            }
+            None => {
+                let value = captures.get(4).map(|value| unquote(value.as_str()))
+                    .or_else(|| captures.get(5).map(|value| value.as_str().to_string()))
+                    .unwrap_or_default();
+                positional.push(serde_json::Value::from(escape(&value)));
+            }
+        }
+    }
+    data.insert(String::from("args"), positional.into());
+
+    return Some((name, data.into()));
+}
+
+/// The text between the shortcode `name` ending at `from` and its closing
+/// tag, and where that tag ends, if it's closed before it's opened again.
+fn find_inner<'a>(markdown: &'a str, from: usize, name: &str) -> Option<(&'a str, usize)> {
+    let closing = Regex::new(&format!(r"\{{\{{<\s*/{}\s*>\}}\}}", regex::escape(name))).ok()?;
+    let reopening = Regex::new(&format!(r"\{{\{{<\s*{}[\s>]", regex::escape(name))).ok()?;
+
+    let close = closing.find(&markdown[from..])?;
+    if reopening.find(&markdown[from..]).is_some_and(|open| open.start() < close.start()) {
+        return None;
+    }
+
+    return Some((&markdown[from..(from + close.start())], from + close.end()));
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    let _ = escape_html(&mut escaped, value);
+
+    return escaped;
+}
+
+/// `value` with its backslash escapes undone.
+fn unquote(value: &str) -> String {
+    let mut unquoted = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.extend(chars.next()),
+            c => unquoted.push(c),
+        }
+    }
+
+    return unquoted;
+}
+
+/// The byte ranges of the fenced code blocks in `markdown`.
+pub(crate) fn fenced_ranges(markdown: &str) -> Vec<std::ops::Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut fence: Option<(&str, usize)> = None;
+    let mut offset = 0;
+    for line in markdown.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        match fence {
+            Some((marker, start)) if trimmed.starts_with(marker) => {
+                ranges.push(start..(offset + line.len()));
+                fence = None;
+            }
+            Some(_) => {}
+            None if trimmed.starts_with("```") || trimmed.starts_with("~~~") => fence = Some((&trimmed[..3], offset)),
+            None => {}
+        }
+        offset += line.len();
+    }
+    if let Some((_, start)) = fence {
+        ranges.push(start..markdown.len());
+    }
+
+    return ranges;
+}
+
+/// Whether `offset` falls inside a code span, judging by the backticks
+/// earlier on its line.
+pub(crate) fn in_code_span(markdown: &str, offset: usize) -> bool {
+    let line_start = markdown[..offset].rfind('\n').map_or(0, |newline| newline + 1);
+
+    return markdown[line_start..offset].matches('`').count() % 2 == 1;
+}
@@ -0,0 +1,1569 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use handlebars::Handlebars;
+use pulldown_cmark::escape::escape_html;
+use regex::Regex;
+use rayon::prelude::*;
+use walkdir::WalkDir;
+
+use crate::cache::{self, BuildCache, CacheEntry};
+use crate::config::{Config, Profile, Theme};
+use crate::error::{OrbitError, Result};
+use crate::frontmatter::{self, Frontmatter};
+use crate::gitdates::GitDates;
+use crate::ignore::IgnoreRules;
+use crate::orbit::Orbit;
+use crate::listing::{self, Chronology, PageSummary};
+use crate::backlinks::{self, Backlinks};
+use crate::related::{self, Related};
+use crate::series::{self, Series, SeriesPage};
+use crate::wikilink::{self, NoteIndex};
+use crate::linkcheck::{self, BrokenLink};
+use crate::nav::NavLink;
+use crate::urls::{self, UrlStyle};
+use crate::{book, feed, helpers, highlight, hooks, images, include, math, minify, nav, offline, orbit, pdf, redirect, search, shortcode, sitejson, sitemap, social};
+use crate::RenderedPage;
+
+const PAGE_TEMPLATE: &str = "page";
+
+lazy_static::lazy_static! {
+    /// A note's embed placeholder, alone in its paragraph or inline.
+    static ref EMBED: Regex = {
+        let placeholder = format!(r#"{}([^"]*)">(.*?)</div>"#, regex::escape(wikilink::EMBED_START));
+        Regex::new(&format!("<p>{0}</p>\n?|{0}", placeholder)).unwrap()
+    };
+    static ref HEADING: Regex = Regex::new(r#"<h([1-6])[^>]* id="([^"]*)""#).unwrap();
+}
+
+/// Prefixes the names shortcode templates are registered under.
+const SHORTCODE_PREFIX: &str = "shortcode/";
+
+/// Templates that load the Orbit component themselves, say behind
+/// `{{#if has_orbit}}`, don't get a second copy injected.
+const ORBIT_SCRIPT_SRC: &str = "orbit-web-component.js";
+
+/// Generated when the source doesn't have an `index.md` of its own.
+const INDEX_PAGE: &str = "index.html";
+
+/// Generated listing every dated page by year and month.
+const ARCHIVE_PAGE: &str = "archive.html";
+
+/// Generated to search the site from, if `search` is set.
+const SEARCH_PAGE: &str = "search.html";
+
+/// Generated with every card on the site.
+const REVIEW_PAGE: &str = "review.html";
+
+/// Generated with every chapter of the book, if `[book]` lists any.
+const BOOK_PAGE: &str = "book.html";
+
+/// Appended to the stylesheet when tables are enabled.
+const TABLE_CSS: &str = include_str!("tables.css");
+/// Appended to the stylesheet so sized images and diagrams keep their
+/// aspect ratio.
+const IMAGE_CSS: &str = include_str!("images.css");
+/// Appended to the stylesheet for the `dark` and `auto` themes.
+const DARK_CSS: &str = include_str!("dark.css");
+/// Appended to the stylesheet in the print profile.
+const PRINT_CSS: &str = include_str!("print.css");
+/// A button cycling through the colour themes, remembering the reader's
+/// choice. Templates include it with `{{> theme-toggle}}`; a partial of the
+/// same name replaces it.
+const THEME_TOGGLE_PARTIAL: &str = include_str!("theme-toggle.html");
+/// The menu from `[nav]`, marking the link to the current page or section.
+/// Templates include it with `{{> site-nav}}`; a partial of the same name
+/// replaces it.
+const SITE_NAV_PARTIAL: &str = include_str!("site-nav.html");
+
+pub const NO_SOURCE: &str = "no source directory given; pass one or set `source` in orbit.toml";
+pub const NO_DESTINATION: &str = "no destination directory given; pass one or set `destination` in orbit.toml";
+
+/// A markdown file belonging to a site.
+#[derive(Debug, Clone)]
+pub struct Page {
+    /// Where the markdown lives on disk.
+    pub path: PathBuf,
+    /// The path relative to the site's source directory.
+    pub relative: PathBuf,
+}
+
+impl Page {
+    /// Where the rendered page is written, relative to the destination.
+    pub fn output_path(&self) -> PathBuf {
+        self.relative.with_extension("html")
+    }
+
+    /// The relative path from this page back to the site root, e.g. `../../`
+    /// for `a/b/page.md`.
+    pub fn root_prefix(&self) -> String {
+        root_prefix(&self.relative)
+    }
+}
+
+/// Counts of the pages handled by a single build.
+#[derive(Debug, Default)]
+pub struct BuildSummary {
+    pub rendered: usize,
+    pub skipped: usize,
+    /// Problems in individual pages that were rendered around.
+    pub warnings: Vec<OrbitError>,
+    /// Files deleted from the destination for no longer coming from any
+    /// source, if asked for.
+    pub pruned: usize,
+}
+
+/// What the rest of the site says about each page: the pages linking to it,
+/// those related to it, the series it's in, and the pages dated either side.
+pub(crate) struct Neighbours {
+    backlinks: Backlinks,
+    related: Related,
+    series: Series,
+    chronology: Chronology,
+}
+
+impl Neighbours {
+    /// The pages before and after `page` for the template: in its series if
+    /// it's in one, or else by date.
+    fn adjacent(&self, page: &Page) -> (Option<serde_json::Value>, Option<serde_json::Value>) {
+        if let Some(series) = self.series.of(page) {
+            let link = |series_page: &SeriesPage| page_link(&series_page.title, &series_page.output, series_page.date.as_deref());
+            return (series.prev().map(link), series.next().map(link));
+        }
+
+        let link = |summary: &PageSummary| page_link(&summary.title, &summary.output, summary.date.as_deref());
+        return (self.chronology.prev(page).map(link), self.chronology.next(page).map(link));
+    }
+}
+
+/// A source directory of notes rendered into a destination directory through
+/// a compiled page template.
+pub struct Site {
+    config: Config,
+    source: PathBuf,
+    destination: PathBuf,
+    registry: Handlebars<'static>,
+    /// The same templates without HTML escaping, for shortcodes, whose
+    /// arguments arrive escaped and whose `inner` is markdown.
+    shortcodes: Handlebars<'static>,
+    /// Hash of the page template, layouts, and partials, for the build
+    /// manifest.
+    templates_hash: String,
+    /// Commit dates of the source files, read only if `git_dates` is set.
+    git_dates: GitDates,
+    /// Files under the source left out of the site.
+    ignore: IgnoreRules,
+    /// Render everything but only log what would be written.
+    dry_run: bool,
+    /// Delete files in the destination that the build didn't account for.
+    prune: bool,
+    /// Every file the current build has written to the destination.
+    written: Mutex<HashSet<PathBuf>>,
+    /// The menu atop every page, as of the current build's pages.
+    nav: Mutex<Vec<NavLink>>,
+}
+
+impl Site {
+    /// Prepares a site from `config`, which must name both a source and a
+    /// destination directory. The page template, layouts, and partials are
+    /// loaded and compiled here. The print profile always renders in the
+    /// light theme.
+    pub fn new(mut config: Config) -> Result<Site> {
+        if config.profile == Profile::Print {
+            config.markdown.print = true;
+            config.theme = Theme::Light;
+        }
+        let source = config.source.clone().ok_or(OrbitError::MissingSetting(NO_SOURCE))?;
+        let destination = config.destination.clone().ok_or(OrbitError::MissingSetting(NO_DESTINATION))?;
+
+        let mut templates = vec![(PAGE_TEMPLATE.to_string(), config.load_template(crate::TEMPLATE)?)];
+        templates.extend(config.load_layouts()?);
+        // Kept apart from layouts, which could share their names.
+        templates.extend(config.load_shortcodes()?.into_iter()
+            .map(|(name, template)| (format!("{}{}", SHORTCODE_PREFIX, name), template)));
+
+        let partials = config.load_partials()?;
+
+        let mut registry = Handlebars::new();
+        helpers::register(&mut registry);
+
+        registry.register_partial("theme-toggle", THEME_TOGGLE_PARTIAL)?;
+        registry.register_partial("site-nav", SITE_NAV_PARTIAL)?;
+        let mut templates_key = String::new();
+        for (name, partial) in &partials {
+            registry.register_partial(name, partial)?;
+            templates_key.push_str(name);
+            templates_key.push_str(partial);
+        }
+        for (name, template) in &templates {
+            registry.register_template_string(name, template)?;
+            templates_key.push_str(name);
+            templates_key.push_str(template);
+        }
+        let templates_hash = cache::hash_bytes(templates_key.as_bytes());
+        let mut shortcodes = registry.clone();
+        shortcodes.register_escape_fn(handlebars::no_escape);
+
+        let ignore = IgnoreRules::load(&source, &config.exclude)?;
+        let git_dates = match config.git_dates {
+            true => GitDates::load(&source),
+            false => GitDates::default(),
+        };
+
+        return Ok(Site {
+            config,
+            source,
+            destination,
+            registry,
+            shortcodes,
+            templates_hash,
+            git_dates,
+            ignore,
+            dry_run: false,
+            prune: false,
+            written: Mutex::new(HashSet::new()),
+            nav: Mutex::new(Vec::new()),
+        });
+    }
+
+    /// Makes the site render everything as usual but leave the destination
+    /// alone, logging each file it would have written instead.
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    /// Makes builds delete every file in the destination that doesn't come
+    /// from a source, such as the pages of renamed or removed notes. Hidden
+    /// files, like a `.git` directory, are kept.
+    pub fn set_prune(&mut self, prune: bool) {
+        self.prune = prune;
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn source(&self) -> &Path {
+        &self.source
+    }
+
+    pub fn destination(&self) -> &Path {
+        &self.destination
+    }
+
+    /// Whether the file or directory at `relative`, within the source, is
+    /// left out of the site by `.orbitignore` or the `exclude` setting.
+    pub fn is_ignored(&self, relative: &Path, is_dir: bool) -> bool {
+        self.ignore.is_ignored(relative, is_dir)
+    }
+
+    /// Renders every markdown file under the source directory and copies
+    /// everything else alongside.
+    ///
+    /// Files whose content hash matches the build manifest from the previous
+    /// run are skipped unless `force` is set or the templates, stylesheet, or
+    /// configuration changed since.
+    pub fn build(&self, force: bool) -> Result<BuildSummary> {
+        if !self.dry_run {
+            std::fs::create_dir_all(&self.destination).map_err(OrbitError::io(&self.destination))?;
+        }
+        self.written.lock().unwrap().clear();
+        self.run_hook("before_build", &self.config.hooks.before_build, &[])?;
+
+        let pages = self.pages()?;
+        self.update_nav(&pages);
+        self.check_outputs(&pages)?;
+        let notes = self.note_index(&pages)?;
+
+        // Any page can link to any other, so adding, removing, or renaming a
+        // note invalidates every page along with the templates and config.
+        let css = self.config.load_css(crate::CSS)?;
+        let site_key = format!(
+            "{}{}{}{:?}",
+            self.templates_hash,
+            css,
+            serde_json::to_string(&self.config)?,
+            notes.outputs(),
+        );
+        let site_hash = cache::hash_bytes(site_key.as_bytes());
+        let mut old_cache = BuildCache::load(&self.destination);
+        if force {
+            tracing::debug!("rebuilding every page, as asked");
+            old_cache = BuildCache::default();
+        } else if old_cache.site_hash() != site_hash {
+            tracing::debug!("rebuilding every page, as the templates, stylesheet, configuration, or set of notes changed");
+            old_cache = BuildCache::default();
+        }
+        let mut new_cache = BuildCache::new(site_hash);
+
+        for entry in walk_source(&self.source, &self.ignore) {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() || crate::is_markdown(entry.file_name()) {
+                continue;
+            }
+
+            let relative = entry.path().strip_prefix(&self.source)
+                .expect("walked entries live under the source directory")
+                .to_path_buf();
+            let contents = std::fs::read(entry.path()).map_err(OrbitError::io(entry.path()))?;
+            let hash = cache::hash_bytes(&contents);
+            if old_cache.is_fresh(&relative, &hash, &self.destination) {
+                tracing::trace!("unchanged {}", relative.display());
+            } else {
+                self.write_output(&self.destination.join(&relative), &contents)?;
+                tracing::trace!("copied {}", relative.display());
+            }
+            new_cache.insert(relative.clone(), CacheEntry { hash, output: relative, extra: Vec::new() });
+        }
+
+        let sources = read_sources(&pages)?;
+        let neighbours = self.neighbours(&pages, &sources, &notes);
+        let start = Instant::now();
+        self.write_site_pages(&pages, &sources, &notes)?;
+        tracing::debug!("wrote listings, feed, and other site pages in {:.2?}", start.elapsed());
+        let indices: HashMap<&Path, usize> = pages.iter().enumerate()
+            .map(|(idx, page)| (page.relative.as_path(), idx))
+            .collect();
+
+        let results = pages.par_iter().zip(&sources)
+            .map(|(page, markdown)| {
+                // A page also changes when the pages linking to it, related
+                // to it, in its series, or dated either side of it do, when
+                // it's committed again, or when a fragment it includes or a
+                // note it embeds changes. Includes that fail are reported
+                // when the page is rendered.
+                let expanded = include::expand_includes(markdown, &page.path).unwrap_or_default();
+                let key = format!(
+                    "{}{}{:?}{:?}{:?}{:?}{:?}{:?}{:?}",
+                    markdown,
+                    expanded,
+                    neighbours.backlinks.to(page),
+                    neighbours.related.to(page),
+                    neighbours.series.of(page),
+                    neighbours.chronology.prev(page),
+                    neighbours.chronology.next(page),
+                    self.git_dates.get(&page.relative),
+                    embedded_sources(markdown, &notes, &indices, &sources),
+                );
+                let hash = cache::hash_bytes(key.as_bytes());
+                let output = self.url_style().published(&page.output_path());
+                if old_cache.is_fresh(&page.relative, &hash, &self.destination) {
+                    tracing::debug!("skipped {}, which is unchanged since the last build", page.relative.display());
+                    let extra = old_cache.get(&page.relative).map(|entry| entry.extra.clone()).unwrap_or_default();
+                    return Ok((page, CacheEntry { hash, output, extra }, None));
+                }
+
+                let start = Instant::now();
+                let (warnings, extra) = self.write_markdown(page, markdown, &notes, &neighbours)?;
+                // A dry run has already said it would write the page.
+                if !self.dry_run {
+                    tracing::info!("built {}", page.relative.display());
+                }
+                tracing::debug!("rendered {} in {:.2?}", page.relative.display(), start.elapsed());
+
+                Ok((page, CacheEntry { hash, output, extra }, Some(warnings)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // Pages are printed once their stylesheet is there.
+        self.write_css()?;
+        let mut summary = BuildSummary::default();
+        let mut built = Vec::new();
+        for (page, mut entry, warnings) in results {
+            let mut warnings = match warnings {
+                Some(warnings) => warnings,
+                None => {
+                    summary.skipped += 1;
+                    new_cache.insert(page.relative.clone(), entry);
+                    continue;
+                }
+            };
+            summary.rendered += 1;
+            if self.config.profile == Profile::Print && self.config.print.pdf {
+                match self.write_pdf(page) {
+                    Ok(pdf) => entry.extra.push(pdf),
+                    Err(err) => warnings.push(err.in_file(&page.path)),
+                }
+            }
+            let output = self.destination.join(&entry.output);
+            let env = [("ORBIT_PAGE", page.path.as_os_str()), ("ORBIT_OUTPUT", output.as_os_str())];
+            if let Err(err) = self.run_hook("after_page", &self.config.hooks.after_page, &env) {
+                warnings.push(err.in_file(&page.path));
+            }
+            built.push(output);
+            // Pages with warnings stay out of the manifest, so the warnings
+            // are repeated until they're fixed.
+            match warnings.is_empty() {
+                true => new_cache.insert(page.relative.clone(), entry),
+                false => summary.warnings.extend(warnings),
+            }
+        }
+
+        if self.prune {
+            // The offline files are written after pruning, so the service
+            // worker doesn't cache stale files, but they're no less current.
+            if self.config.offline {
+                let mut written = self.written.lock().unwrap();
+                for name in [offline::MANIFEST_FILENAME, offline::SERVICE_WORKER_FILENAME] {
+                    written.insert(self.destination.join(name));
+                }
+            }
+            summary.pruned = self.prune_destination(&new_cache)?;
+        }
+        // Last, so the service worker caches everything else.
+        self.write_offline()?;
+        if !self.dry_run {
+            new_cache.save(&self.destination)?;
+        }
+        let built: Vec<String> = built.iter().map(|output| output.to_string_lossy().into_owned()).collect();
+        self.run_hook("after_build", &self.config.hooks.after_build, &[("ORBIT_PAGES", OsStr::new(&built.join("\n")))])?;
+
+        return Ok(summary);
+    }
+
+    /// Runs the `[hooks]` command `command`, if one is set, with the source
+    /// and destination directories and `env` in its environment.
+    fn run_hook(&self, hook: &'static str, command: &Option<String>, env: &[(&str, &OsStr)]) -> Result<()> {
+        let command = match command {
+            Some(command) => command,
+            None => return Ok(()),
+        };
+        if self.dry_run {
+            tracing::info!("would run the {} hook `{}`", hook, command);
+            return Ok(());
+        }
+
+        let mut env = env.to_vec();
+        env.push(("ORBIT_SOURCE", self.source.as_os_str()));
+        env.push(("ORBIT_DESTINATION", self.destination.as_os_str()));
+        tracing::debug!("running the {} hook `{}`", hook, command);
+
+        return hooks::run_hook(hook, command, &env);
+    }
+
+    /// Reads, renders, and writes out a single page, returning any warnings.
+    pub fn write_page(&self, page: &Page) -> Result<Vec<OrbitError>> {
+        let markdown = crate::read_note(&page.path)?;
+        if !self.config.drafts && is_draft(&markdown) {
+            return Ok(Vec::new());
+        }
+        let pages = self.pages()?;
+        self.update_nav(&pages);
+        let notes = self.note_index(&pages)?;
+        let sources = read_sources(&pages)?;
+        let neighbours = self.neighbours(&pages, &sources, &notes);
+        self.write_site_pages(&pages, &sources, &notes)?;
+
+        let (warnings, _) = self.write_markdown(page, &markdown, &notes, &neighbours)?;
+
+        return Ok(warnings);
+    }
+
+    /// Renders `markdown` as `page` without writing anything, returning the
+    /// finished page, or just its body if `fragment` is set, and any
+    /// warnings. Wikilinks resolve against the rest of the source directory,
+    /// and images are left at their original size.
+    pub fn render_standalone(&self, page: &Page, markdown: &str, fragment: bool) -> Result<(String, Vec<OrbitError>)> {
+        let pages = self.pages()?;
+        self.update_nav(&pages);
+        let notes = self.note_index(&pages)?;
+        let sources = read_sources(&pages)?;
+        let neighbours = self.neighbours(&pages, &sources, &notes);
+
+        let rendered = self.render_note(page, markdown, &notes, &neighbours)?;
+        let output = page.output_path();
+        let html = match fragment {
+            true => self.url_style().rewrite_html(&rendered.body, &output),
+            false => {
+                let render = self.render_template(page, &rendered, Some(&neighbours))
+                    .map_err(|err| err.in_file(&page.path))?;
+                self.finish_html(self.inject_scripts(render, &rendered, &page.root_prefix()), &output)
+            }
+        };
+        let warnings = rendered.warnings.into_iter()
+            .map(|warning| warning.in_file(&page.path))
+            .collect();
+
+        return Ok((html, warnings));
+    }
+
+    /// Deletes every file in the destination that this build neither wrote
+    /// nor found up to date in `cache`, along with directories left empty,
+    /// returning how many files went. Hidden files and directories are kept.
+    fn prune_destination(&self, cache: &BuildCache) -> Result<usize> {
+        let written = self.written.lock().unwrap();
+        let outputs: HashSet<PathBuf> = cache.outputs().map(|output| self.destination.join(output)).collect();
+
+        let mut pruned = 0;
+        let mut directories = Vec::new();
+        let walker = WalkDir::new(&self.destination).min_depth(1).into_iter();
+        for entry in walker.filter_entry(|e| !crate::is_hidden(e)) {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type().is_dir() {
+                directories.push(path.to_path_buf());
+                continue;
+            }
+            if written.contains(path) || outputs.contains(path) {
+                continue;
+            }
+
+            pruned += 1;
+            if self.dry_run {
+                tracing::info!("would delete {}", path.display());
+                continue;
+            }
+            std::fs::remove_file(path).map_err(OrbitError::io(path))?;
+            tracing::info!("deleted {}", path.display());
+        }
+        if !self.dry_run {
+            // Deepest first, so emptying a directory can empty its parent.
+            // Removing one that isn't empty fails, as it should.
+            for directory in directories.iter().rev() {
+                let _ = std::fs::remove_dir(directory);
+            }
+        }
+
+        return Ok(pruned);
+    }
+
+    /// Works out the menu for `pages`, which every page rendered after this
+    /// carries.
+    fn update_nav(&self, pages: &[Page]) {
+        *self.nav.lock().unwrap() = nav::nav_links(&self.config.nav, pages);
+    }
+
+    /// Indexes `pages` and the site's other files for wikilinks and embeds.
+    fn note_index(&self, pages: &[Page]) -> Result<NoteIndex> {
+        let mut notes = NoteIndex::new(pages);
+        notes.index_files(&find_files(&self.source, &self.ignore)?);
+
+        return Ok(notes);
+    }
+
+    /// Writes everything generated from the site as a whole rather than from
+    /// a single note: listings, the review page, the book, the feed,
+    /// `site.json`, redirects, and the sitemap.
+    fn write_site_pages(&self, pages: &[Page], sources: &[String], notes: &NoteIndex) -> Result<()> {
+        let summaries = PageSummary::collect(pages, sources);
+        let mut generated = self.write_listings(&summaries)?;
+        generated.extend(self.write_review(pages, sources)?);
+        generated.extend(self.write_search(pages, sources, &summaries)?);
+        generated.extend(self.write_book(pages, notes)?);
+        self.write_feed(pages, sources, &summaries, notes)?;
+        self.write_site_json(pages, sources, &summaries, notes)?;
+        self.write_redirects(pages, sources)?;
+        self.write_sitemap(pages, &generated)?;
+
+        return Ok(());
+    }
+
+    /// Writes `search-index.json` with every page, the script searching it,
+    /// and `search.html` to search from unless it was authored, if `search`
+    /// is set. Returns the search page's path if it was generated.
+    pub fn write_search(&self, pages: &[Page], sources: &[String], summaries: &[PageSummary]) -> Result<Option<PathBuf>> {
+        if !self.config.search {
+            return Ok(None);
+        }
+
+        let entries: Vec<search::SearchEntry> = summaries.iter()
+            .filter_map(|summary| {
+                let idx = pages.iter().position(|page| page.output_path() == summary.output)?;
+                let url = self.url_style().permalink(&summary.output);
+                Some(search::SearchEntry::new(summary, &sources[idx], url))
+            })
+            .collect();
+
+        let index_path = self.destination.join(search::INDEX_FILENAME);
+        self.write_output(&index_path, serde_json::to_string(&entries)?)?;
+        self.write_output(&self.destination.join(search::SCRIPT_FILENAME), search::SCRIPT)?;
+
+        let output = Path::new(SEARCH_PAGE);
+        if summaries.iter().any(|summary| summary.output == output) {
+            return Ok(None);
+        }
+        self.write_generated(output, "Search", search::search_html())?;
+
+        return Ok(Some(output.to_path_buf()));
+    }
+
+    /// Writes the web app manifest and a service worker caching every file
+    /// in the destination, if `offline` is set.
+    fn write_offline(&self) -> Result<()> {
+        if !self.config.offline {
+            return Ok(());
+        }
+        self.write_output(&self.destination.join(offline::MANIFEST_FILENAME), offline::web_manifest(&self.config))?;
+
+        let mut files: HashSet<PathBuf> = self.written.lock().unwrap().iter()
+            .filter_map(|path| path.strip_prefix(&self.destination).ok())
+            .map(Path::to_path_buf)
+            .collect();
+        if self.destination.is_dir() {
+            for entry in WalkDir::new(&self.destination) {
+                let entry = entry?;
+                if entry.file_type().is_file() {
+                    files.insert(entry.path().strip_prefix(&self.destination).unwrap_or(entry.path()).to_path_buf());
+                }
+            }
+        }
+        let mut files: Vec<PathBuf> = files.into_iter().filter(|file| offline::is_cached(file)).collect();
+        files.sort();
+        let urls = offline::precache_urls(&files);
+
+        // Any file changing changes the version, so readers get it.
+        let mut key = String::new();
+        for file in &files {
+            let contents = std::fs::read(self.destination.join(file)).unwrap_or_default();
+            key.push_str(&format!("{}{}", file.display(), cache::hash_bytes(&contents)));
+        }
+        let version = &cache::hash_bytes(key.as_bytes())[..16];
+
+        return self.write_output(&self.destination.join(offline::SERVICE_WORKER_FILENAME), offline::service_worker(&urls, version));
+    }
+
+    /// Writes `site.json`, describing every page in path order, if
+    /// `site_json` is set.
+    pub fn write_site_json(&self, pages: &[Page], sources: &[String], summaries: &[PageSummary], notes: &NoteIndex) -> Result<()> {
+        if !self.config.site_json {
+            return Ok(());
+        }
+
+        let mut entries: Vec<sitejson::PageData> = summaries.iter()
+            .filter_map(|summary| {
+                let idx = pages.iter().position(|page| page.output_path() == summary.output)?;
+                let url = self.url_style().permalink(&summary.output);
+                Some(sitejson::PageData::new(&pages[idx], summary, &sources[idx], notes, url))
+            })
+            .collect();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        return self.write_output(&self.destination.join(sitejson::SITE_JSON_FILENAME), serde_json::to_string_pretty(&entries)?);
+    }
+
+    /// Writes a page redirecting from each of the `aliases` in pages'
+    /// frontmatter to the page, and Netlify's `_redirects` for them all if
+    /// `netlify_redirects` is set.
+    pub fn write_redirects(&self, pages: &[Page], sources: &[String]) -> Result<()> {
+        let mut outputs: HashMap<PathBuf, &Path> = pages.iter()
+            .map(|page| (self.url_style().published(&page.output_path()), page.relative.as_path()))
+            .collect();
+        let mut redirects = Vec::new();
+        for (page, markdown) in pages.iter().zip(sources) {
+            // Broken frontmatter is reported when the page renders.
+            let aliases = frontmatter::split_frontmatter(markdown).map(|(frontmatter, _)| frontmatter.aliases).unwrap_or_default();
+            for alias in aliases {
+                let output = redirect::alias_output(&alias).ok_or_else(|| OrbitError::FrontmatterKey {
+                    key: String::from("aliases"),
+                    message: format!("`{}` is outside the site", alias),
+                }.in_file(&page.path))?;
+                if let Some(other) = outputs.insert(output.clone(), &page.relative) {
+                    return Err(OrbitError::OutputCollision { output, first: other.to_path_buf(), second: page.relative.clone() });
+                }
+                let from = alias.trim_start_matches('/').to_string();
+                redirects.push(redirect::Redirect { from, output, to: self.url_style().permalink(&page.output_path()) });
+            }
+        }
+
+        for redirect in &redirects {
+            let url = match &self.config.base_url {
+                Some(base_url) => urls::absolute_url(base_url, &redirect.to),
+                None => format!("{}{}", root_prefix(&redirect.output), redirect.to),
+            };
+            let url = if url.is_empty() { String::from("./") } else { url };
+            self.write_output(&self.destination.join(&redirect.output), redirect::redirect_html(&url))?;
+        }
+        if self.config.netlify_redirects && !redirects.is_empty() {
+            let netlify = redirect::netlify_redirects(&redirects, self.config.base_url.as_deref());
+            self.write_output(&self.destination.join(redirect::REDIRECTS_FILENAME), netlify)?;
+        }
+
+        return Ok(());
+    }
+
+    /// Writes `book.html`, the chapters the `[book]` summary or list of notes
+    /// names on one page after a table of contents. Returns its path if
+    /// there were any chapters and no page of the same name was authored.
+    pub fn write_book(&self, pages: &[Page], notes: &NoteIndex) -> Result<Option<PathBuf>> {
+        let options = &self.config.book;
+        let (chapters, setting) = match &options.summary {
+            Some(summary) => {
+                let markdown = crate::read_note(&self.source.join(summary))?;
+                (book::parse_summary(&markdown, summary, notes), "[book] summary")
+            }
+            None => {
+                let chapters = options.notes.iter()
+                    .map(|note| book::Chapter { relative: note.clone(), depth: 0 })
+                    .collect();
+                (chapters, "[book] notes")
+            }
+        };
+        let output = Path::new(BOOK_PAGE);
+        if chapters.is_empty() || pages.iter().any(|page| page.output_path() == output) {
+            return Ok(None);
+        }
+
+        let mut chapter_pages = Vec::with_capacity(chapters.len());
+        for chapter in &chapters {
+            let page = pages.iter()
+                .find(|page| page.relative == chapter.relative || page.relative.with_extension("") == chapter.relative)
+                .ok_or_else(|| OrbitError::UnknownNote { note: chapter.relative.clone(), setting })?;
+            chapter_pages.push((page, chapter.depth));
+        }
+        let anchors: HashMap<PathBuf, String> = chapter_pages.iter()
+            .map(|(page, _)| (page.output_path(), book::chapter_id(&page.relative)))
+            .collect();
+
+        let mut contents = Vec::new();
+        let mut body = String::new();
+        let mut footnotes = 0;
+        let mut has_math = false;
+        let mut word_count = 0;
+        for (page, depth) in chapter_pages {
+            let markdown = crate::read_note(&page.path)?;
+            // Warnings are reported when the chapter's own page renders.
+            let mut rendered = self.render_markdown(page, &markdown, notes).map_err(|err| err.in_file(&page.path))?;
+            self.print_review_areas(&mut rendered);
+            has_math |= rendered.has_math;
+            word_count += rendered.word_count;
+
+            let id = book::chapter_id(&page.relative);
+            let html = urls::rebase_html(&rendered.body, &page.output_path(), output);
+            let html = book::anchor_chapter(&html, &id, &anchors);
+            let (html, count) = book::renumber_footnotes(&html, footnotes);
+            footnotes += count;
+
+            let frontmatter = &rendered.frontmatter;
+            let title = frontmatter.title.clone()
+                .unwrap_or_else(|| page.relative.file_stem().unwrap_or_default().to_string_lossy().into_owned());
+            body.push_str("<section class=\"chapter\" id=\"");
+            let _ = escape_html(&mut body, &id);
+            body.push_str("\">\n");
+            // An inferred title is already the chapter's first heading.
+            if !frontmatter.inferred_title || self.config.markdown.strip_title_heading {
+                body.push_str("<h1>");
+                let _ = escape_html(&mut body, &title);
+                body.push_str("</h1>\n");
+            }
+            body.push_str(&html);
+            body.push_str("</section>\n");
+            contents.push((title, id, depth));
+        }
+
+        let title = options.title.clone().or_else(|| self.config.title.clone()).unwrap_or_else(|| String::from("Book"));
+        let body = format!("{}{}", book::toc_html(&contents), body);
+        let rendered = RenderedPage {
+            frontmatter: Frontmatter { title: Some(title), ..Frontmatter::default() },
+            has_math,
+            has_orbit: body.contains(orbit::REVIEW_START),
+            card_count: body.matches(orbit::PROMPT_START).count(),
+            word_count,
+            reading_time: word_count.div_ceil(crate::WORDS_PER_MINUTE).max(1),
+            body,
+            warnings: Vec::new(),
+            attachments: Vec::new(),
+        };
+        self.write_rendered(output, rendered)?;
+
+        return Ok(Some(output.to_path_buf()));
+    }
+
+    /// Writes `review.html`, a single review area holding every card on the
+    /// site, or only those from the pages selected by the `[review]` table.
+    /// Returns its path if there were any cards to write.
+    pub fn write_review(&self, pages: &[Page], sources: &[String]) -> Result<Option<PathBuf>> {
+        let review = &self.config.review;
+        let mut cards = Vec::new();
+        for (page, markdown) in pages.iter().zip(sources) {
+            let in_directory = review.directories.is_empty()
+                || review.directories.iter().any(|directory| page.relative.starts_with(directory));
+            let tagged = review.tags.is_empty() || frontmatter::split_frontmatter(markdown)
+                .map(|(frontmatter, _)| frontmatter.tags.iter().any(|tag| review.tags.contains(tag)))
+                .unwrap_or(false);
+            if !in_directory || !tagged {
+                continue;
+            }
+
+            // Broken decks are reported when their own page renders.
+            if let Ok(decks) = crate::extract_decks(markdown) {
+                for (_, mut deck) in decks {
+                    // The review page sits at the root of the site.
+                    deck.resolve_attachments(&page.relative, "");
+                    cards.extend(deck.prompts());
+                }
+            }
+        }
+        if cards.is_empty() {
+            return Ok(None);
+        }
+
+        let output = PathBuf::from(REVIEW_PAGE);
+        let body = Orbit::new(cards).to_html(self.config.markdown.orbit_color.as_deref())?;
+        self.write_generated(&output, "Review", body)?;
+
+        return Ok(Some(output));
+    }
+
+    /// Writes the pages generated from the whole site's frontmatter: a page
+    /// per tag, an index of tags, and a site index and archive unless they
+    /// were authored.
+    /// Returns the paths written, relative to the destination.
+    pub fn write_listings(&self, summaries: &[PageSummary]) -> Result<Vec<PathBuf>> {
+        let mut generated = Vec::new();
+
+        let index = Path::new(INDEX_PAGE);
+        if !summaries.iter().any(|summary| summary.output == index) {
+            let title = self.config.title.as_deref().unwrap_or("Index");
+            self.write_generated(index, title, listing::index_html(summaries))?;
+            generated.push(index.to_path_buf());
+        }
+
+        let archive = Path::new(ARCHIVE_PAGE);
+        let dated = summaries.iter().any(|summary| summary.timestamp.is_some());
+        if dated && !summaries.iter().any(|summary| summary.output == archive) {
+            self.write_generated(archive, "Archive", listing::archive_html(summaries))?;
+            generated.push(archive.to_path_buf());
+        }
+
+        let tags = listing::by_tag(summaries);
+        if tags.is_empty() {
+            return Ok(generated);
+        }
+
+        for (tag, tagged) in &tags {
+            let output = listing::tag_output(tag);
+            let feed = self.config.base_url.as_ref().map(|_| feed::tag_feed_output(tag));
+            let body = listing::tag_html(tagged, &root_prefix(&output), feed.as_deref());
+            self.write_generated(&output, &format!("Tagged “{}”", tag), body)?;
+            generated.push(output);
+        }
+
+        let output = Path::new(listing::TAGS_DIR).join("index.html");
+        let body = listing::tag_index_html(&tags, &root_prefix(&output));
+        self.write_generated(&output, "Tags", body)?;
+        generated.push(output);
+
+        return Ok(generated);
+    }
+
+    /// Fails if two pages would be written to the same file, as `a.md` and
+    /// `A.md` are once paths are slugified.
+    fn check_outputs(&self, pages: &[Page]) -> Result<()> {
+        let mut outputs: HashMap<PathBuf, &Path> = HashMap::new();
+        for page in pages {
+            let output = self.url_style().published(&page.output_path());
+            if let Some(other) = outputs.insert(output.clone(), &page.relative) {
+                return Err(OrbitError::OutputCollision { output, first: other.to_path_buf(), second: page.relative.clone() });
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// How pages are laid out in the destination.
+    pub fn url_style(&self) -> UrlStyle {
+        UrlStyle { clean: self.config.clean_urls, slugify: self.config.slugify_paths }
+    }
+
+    /// Every internal link in the built site that leads nowhere.
+    pub fn check_links(&self) -> Result<Vec<BrokenLink>> {
+        linkcheck::check_links(&self.destination)
+    }
+
+    /// Every markdown file under the source directory but the book's
+    /// summary, leaving out drafts unless the config asks for them.
+    pub fn pages(&self) -> Result<Vec<Page>> {
+        let mut pages = find_pages(&self.source, &self.ignore)?;
+        if let Some(summary) = &self.config.book.summary {
+            pages.retain(|page| page.relative != *summary);
+        }
+        if self.config.drafts {
+            return Ok(pages);
+        }
+
+        // Unreadable pages are kept, so building them reports the error.
+        let pages = pages.into_iter()
+            .filter(|page| {
+                let draft = crate::read_note(&page.path).is_ok_and(|markdown| is_draft(&markdown));
+                if draft {
+                    tracing::debug!("skipped {}, which is a draft", page.relative.display());
+                }
+                !draft
+            })
+            .collect();
+
+        return Ok(pages);
+    }
+
+    /// Places a rendered page into its layout, or the page template if its
+    /// frontmatter doesn't name one, with what its `neighbours` say about it
+    /// unless the site generated it.
+    pub(crate) fn render_template(&self, page: &Page, rendered: &RenderedPage, neighbours: Option<&Neighbours>) -> Result<String> {
+        // Frontmatter keys sit at the top level of the template data, so the
+        // template can use `{{title}}` directly. `body` always wins a collision.
+        // It and `social_meta` are rendered HTML, which templates print with
+        // `{{{body}}}`; everything else is escaped.
+        let mut template_map = serde_json::to_value(&rendered.frontmatter)?;
+        template_map["body"] = rendered.body.clone().into();
+        template_map["root"] = page.root_prefix().into();
+        template_map["has_math"] = rendered.has_math.into();
+        template_map["has_orbit"] = rendered.has_orbit.into();
+        template_map["card_count"] = rendered.card_count.into();
+        template_map["word_count"] = rendered.word_count.into();
+        template_map["reading_time"] = rendered.reading_time.into();
+        template_map["stylesheets"] = self.stylesheets().into();
+        let output = page.output_path();
+        let nav: Vec<serde_json::Value> = self.nav.lock().unwrap().iter()
+            .map(|link| link.to_json(&output, &page.root_prefix()))
+            .collect();
+        template_map["nav"] = nav.into();
+        if let Some(neighbours) = neighbours {
+            template_map["related"] = serde_json::to_value(neighbours.related.to(page))?;
+            if let Some(series) = neighbours.series.of(page) {
+                template_map["series_prev"] = serde_json::to_value(series.prev())?;
+                template_map["series_next"] = serde_json::to_value(series.next())?;
+                let index: Vec<serde_json::Value> = series.pages.iter().enumerate()
+                    .map(|(idx, series_page)| serde_json::json!({
+                        "title": series_page.title,
+                        "output": series_page.output,
+                        "current": idx == series.index,
+                    }))
+                    .collect();
+                template_map["series_index"] = index.into();
+            }
+            let (prev, next) = neighbours.adjacent(page);
+            template_map["prev"] = prev.into();
+            template_map["next"] = next.into();
+        }
+        // Dates given in frontmatter win over those from git.
+        if let Some(dates) = self.git_dates.get(&page.relative) {
+            for (key, date) in [("created", &dates.created), ("updated", &dates.updated)] {
+                if template_map.get(key).is_none_or(|value| value.is_null()) {
+                    template_map[key] = date.clone().into();
+                }
+            }
+        }
+        template_map["permalink"] = self.url_style().permalink(&page.output_path()).into();
+        let excerpt = social::excerpt(&rendered.body);
+        template_map["excerpt"] = excerpt.clone().into();
+        template_map["social_meta"] = self.social_meta(page, rendered, excerpt.as_deref()).into();
+        template_map["site"] = serde_json::json!({
+            "title": self.config.title,
+            "base_url": self.config.base_url,
+            "description": self.config.description,
+            "author": self.config.author,
+            "theme": self.config.theme,
+        });
+        let layout = rendered.frontmatter.layout.as_deref().unwrap_or(PAGE_TEMPLATE);
+        if !self.registry.has_template(layout) {
+            return Err(OrbitError::UnknownLayout(layout.to_string()));
+        }
+        let render = self.registry.render(layout, &template_map)?;
+
+        return Ok(render);
+    }
+
+    /// The Open Graph and Twitter card tags for a page. Its image and URL
+    /// are only given when `base_url` is set, as both must be absolute.
+    fn social_meta(&self, page: &Page, rendered: &RenderedPage, excerpt: Option<&str>) -> String {
+        let frontmatter = &rendered.frontmatter;
+        let output = page.output_path();
+        let (url, image) = match &self.config.base_url {
+            Some(base_url) => (
+                Some(urls::absolute_url(base_url, &self.url_style().permalink(&output))),
+                frontmatter.image.as_deref().and_then(|image| social::resolve_url(base_url, &output, image)),
+            ),
+            None => (None, None),
+        };
+
+        let preview = social::Preview {
+            title: frontmatter.title.as_deref(),
+            description: frontmatter.description.as_deref().or(excerpt),
+            image: image.as_deref(),
+            url: url.as_deref(),
+            site_name: self.config.title.as_deref(),
+        };
+
+        return social::meta_tags(&preview);
+    }
+
+    /// Copies a non-markdown file to the same relative location under the
+    /// destination, so images and attachments resolve next to the pages.
+    pub fn copy_asset(&self, path: &Path, relative: &Path) -> Result<()> {
+        return self.copy_output(path, &self.destination.join(relative));
+    }
+
+    /// Writes the site stylesheet, the code highlighting stylesheet, and
+    /// copies of the configured extra stylesheets.
+    pub fn write_css(&self) -> Result<()> {
+        let mut css = self.config.load_css(crate::CSS)?;
+        if self.config.markdown.tables {
+            css.push('\n');
+            css.push_str(TABLE_CSS);
+        }
+        css.push('\n');
+        css.push_str(IMAGE_CSS);
+        css.push('\n');
+        css.push_str(DARK_CSS);
+        if self.config.profile == Profile::Print {
+            css.push('\n');
+            css.push_str(PRINT_CSS);
+        }
+        self.write_output(&self.destination.join("tufte.css"), css)?;
+
+        let theme = self.config.highlight_theme.as_deref().unwrap_or(highlight::DEFAULT_THEME);
+        let syntax_css = highlight::theme_css(theme)?;
+        self.write_output(&self.destination.join(highlight::CSS_FILENAME), syntax_css)?;
+
+        for path in &self.config.stylesheets {
+            if let Some(name) = path.file_name() {
+                self.copy_output(path, &self.destination.join(name))?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// The stylesheets every page links after the main one, relative to the
+    /// site root: the code highlighting styles, then the configured extras.
+    pub fn stylesheets(&self) -> Vec<String> {
+        let extras = self.config.stylesheets.iter()
+            .filter_map(|path| path.file_name())
+            .map(|name| name.to_string_lossy().into_owned());
+
+        return std::iter::once(highlight::CSS_FILENAME.to_string()).chain(extras).collect();
+    }
+
+    /// Writes `feed.xml` with the newest dated pages, and a feed under
+    /// `feeds/` for each tag, if `base_url` is set.
+    pub fn write_feed(&self, pages: &[Page], sources: &[String], summaries: &[PageSummary], notes: &NoteIndex) -> Result<()> {
+        let base_url = match &self.config.base_url {
+            Some(base_url) => base_url,
+            None => return Ok(()),
+        };
+
+        // The site's feed, then one per tag, each with its title, where
+        // it's written, and the page it stands for.
+        let title = self.config.title.clone().unwrap_or_default();
+        let mut feeds = vec![(title, PathBuf::from(feed::FEED_FILENAME), String::new(), summaries.iter().collect::<Vec<_>>())];
+        for (tag, tagged) in listing::by_tag(summaries) {
+            let title = match &self.config.title {
+                Some(site) => format!("{}: {}", site, tag),
+                None => tag.clone(),
+            };
+            let page = self.url_style().permalink(&listing::tag_output(&tag));
+            feeds.push((title, feed::tag_feed_output(&tag), page, tagged));
+        }
+
+        // Pages carrying several tags go in several feeds, but are only
+        // rendered once.
+        let mut rendered: HashMap<&Path, feed::FeedEntry> = HashMap::new();
+        for (title, output, page, selected) in feeds {
+            // Summaries are already newest first.
+            let dated = selected.into_iter()
+                .filter(|summary| summary.timestamp.is_some())
+                .take(self.config.feed.limit);
+
+            let mut entries = Vec::new();
+            for summary in dated {
+                if !rendered.contains_key(summary.output.as_path()) {
+                    rendered.insert(&summary.output, self.feed_entry(pages, sources, summary, notes)?);
+                }
+                entries.push(rendered[summary.output.as_path()].clone());
+            }
+
+            let xml = feed::atom_feed(&self.config, base_url, &title, &output, &page, &entries);
+            self.write_output(&self.destination.join(output), xml)?;
+        }
+
+        return Ok(());
+    }
+
+    /// The feed entry for `summary`, with its rendered body if the feed
+    /// carries full content.
+    fn feed_entry<'a>(&self, pages: &[Page], sources: &[String], summary: &'a PageSummary, notes: &NoteIndex) -> Result<feed::FeedEntry<'a>> {
+        let idx = pages.iter().position(|page| page.output_path() == summary.output);
+        let content = match idx {
+            Some(idx) if self.config.feed.full_content => {
+                let page = &pages[idx];
+                let rendered = self.render_markdown(page, &sources[idx], notes)?;
+                Some(self.url_style().rewrite_html(&rendered.body, &summary.output))
+            }
+            _ => None,
+        };
+        let link = self.url_style().permalink(&summary.output);
+        let updated = idx.and_then(|idx| self.git_dates.get(&pages[idx].relative)).map(|dates| dates.updated.clone());
+
+        return Ok(feed::FeedEntry { summary, link, content, updated });
+    }
+
+    /// Writes `sitemap.xml` listing every page and the `generated` listings,
+    /// if `base_url` is set. Pages are dated by their last commit if
+    /// `git_dates` is set, or else by their source's modification time.
+    pub fn write_sitemap(&self, pages: &[Page], generated: &[PathBuf]) -> Result<()> {
+        let base_url = match &self.config.base_url {
+            Some(base_url) => base_url,
+            None => return Ok(()),
+        };
+
+        let mut entries: Vec<sitemap::SitemapEntry> = pages.iter()
+            .map(|page| {
+                let modified = match self.git_dates.get(&page.relative) {
+                    Some(dates) => sitemap::parse_modified(&dates.updated),
+                    None => sitemap::modified(&page.path),
+                };
+                (page.output_path(), modified)
+            })
+            .chain(generated.iter().map(|output| (output.clone(), None)))
+            .map(|(output, modified)| sitemap::SitemapEntry { output: self.url_style().permalink(&output).into(), modified })
+            .collect();
+        entries.sort_by(|a, b| a.output.cmp(&b.output));
+
+        let xml = sitemap::sitemap(base_url, &entries);
+
+        return self.write_output(&self.destination.join(sitemap::SITEMAP_FILENAME), xml);
+    }
+
+    /// Renders `markdown`, expanding its shortcodes if a shortcodes
+    /// directory is configured and filling in the notes it embeds.
+    fn render_markdown(&self, page: &Page, markdown: &str, notes: &NoteIndex) -> Result<RenderedPage> {
+        return self.render_embedding(page, markdown, notes, &mut vec![page.relative.clone()]);
+    }
+
+    /// Renders `markdown` like [`Site::render_markdown`]. `embedding` holds
+    /// the notes whose embeds are being filled in, outermost first, so a note
+    /// that ends up embedding itself is caught.
+    fn render_embedding(&self, page: &Page, markdown: &str, notes: &NoteIndex, embedding: &mut Vec<PathBuf>) -> Result<RenderedPage> {
+        let mut rendered = self.expand_and_render(page, markdown, notes)?;
+        if !rendered.body.contains(wikilink::EMBED_START) {
+            return Ok(rendered);
+        }
+
+        let body = std::mem::take(&mut rendered.body);
+        let mut last = 0;
+        for captures in EMBED.captures_iter(&body) {
+            let whole = captures.get(0).unwrap();
+            rendered.body.push_str(&body[last..whole.start()]);
+            last = whole.end();
+
+            // Placeholders alone in a paragraph match the first alternative.
+            let alone = captures.get(1).is_some();
+            let target = captures.get(1).or_else(|| captures.get(3)).unwrap().as_str();
+            let fallback = captures.get(2).or_else(|| captures.get(4)).unwrap().as_str();
+            match self.render_embed(page, &urls::unescape(target), notes, embedding) {
+                Ok(embedded) => {
+                    rendered.body.push_str(&embedded.body);
+                    rendered.has_math |= embedded.has_math;
+                    rendered.has_orbit |= embedded.has_orbit;
+                    rendered.warnings.extend(embedded.warnings);
+                    rendered.attachments.extend(embedded.attachments);
+                }
+                Err(err) => {
+                    rendered.warnings.push(err);
+                    match alone {
+                        true => rendered.body.push_str(&format!("<p>{}</p>\n", fallback)),
+                        false => rendered.body.push_str(fallback),
+                    }
+                }
+            }
+        }
+        rendered.body.push_str(&body[last..]);
+
+        return Ok(rendered);
+    }
+
+    /// Renders the note at output path `target`, or just the section under
+    /// the heading its fragment names, to embed in `page`.
+    fn render_embed(&self, page: &Page, target: &str, notes: &NoteIndex, embedding: &mut Vec<PathBuf>) -> Result<RenderedPage> {
+        let error = |message: &str| OrbitError::Embed { target: target.to_string(), message: message.to_string() };
+        let (output, heading) = target.split_once('#').unwrap_or((target, ""));
+        let relative = notes.source(Path::new(output)).ok_or_else(|| error("no such note"))?.to_path_buf();
+        if embedding.contains(&relative) {
+            return Err(error("the note embeds itself"));
+        }
+
+        let embedded = Page { path: self.source.join(&relative), relative };
+        let markdown = crate::read_note(&embedded.path)?;
+        embedding.push(embedded.relative.clone());
+        let rendered = self.render_embedding(&embedded, &markdown, notes, embedding);
+        embedding.pop();
+        let mut rendered = rendered?;
+
+        if !heading.is_empty() {
+            rendered.body = section(&rendered.body, heading).ok_or_else(|| error("no such heading"))?.to_string();
+        }
+        let body = urls::rebase_html(&rendered.body, &embedded.output_path(), &page.output_path());
+        rendered.body = format!("<div class=\"embed\">\n{}</div>\n", body);
+
+        return Ok(rendered);
+    }
+
+    /// Renders `markdown` with its fragments included, expanding its
+    /// shortcodes if a shortcodes directory is configured.
+    fn expand_and_render(&self, page: &Page, markdown: &str, notes: &NoteIndex) -> Result<RenderedPage> {
+        let markdown = include::expand_includes(markdown, &page.path).map_err(|err| err.in_file(&page.path))?;
+        if self.config.shortcodes.is_none() {
+            return crate::render_page_with(&markdown, &self.config.markdown, notes, &self.source, &page.relative)
+                .map_err(|err| err.in_file(&page.path));
+        }
+
+        let markdown = shortcode::expand_shortcodes(&markdown, |name, data| {
+            let template = format!("{}{}", SHORTCODE_PREFIX, name);
+            if !self.shortcodes.has_template(&template) {
+                return Err(OrbitError::UnknownShortcode(name.to_string()));
+            }
+            return Ok(self.shortcodes.render(&template, data)?);
+        });
+        let markdown = markdown.map_err(|err| err.in_file(&page.path))?;
+
+        return crate::render_page_with(&markdown, &self.config.markdown, notes, &self.source, &page.relative)
+            .map_err(|err| err.in_file(&page.path));
+    }
+
+    /// Writes `contents` to `dest_path` in the destination, creating its
+    /// directory, or only logs that it would in a dry run.
+    fn write_output<C: AsRef<[u8]>>(&self, dest_path: &Path, contents: C) -> Result<()> {
+        self.written.lock().unwrap().insert(dest_path.to_path_buf());
+        if self.dry_run {
+            tracing::info!("would write {}", dest_path.display());
+            return Ok(());
+        }
+        create_parent_dir(dest_path)?;
+        std::fs::write(dest_path, contents).map_err(OrbitError::io(dest_path))?;
+
+        return Ok(());
+    }
+
+    /// Copies the file at `path` to `dest_path` in the destination, creating
+    /// its directory, or only logs that it would in a dry run.
+    fn copy_output(&self, path: &Path, dest_path: &Path) -> Result<()> {
+        self.written.lock().unwrap().insert(dest_path.to_path_buf());
+        if self.dry_run {
+            tracing::info!("would write {}", dest_path.display());
+            return Ok(());
+        }
+        create_parent_dir(dest_path)?;
+        std::fs::copy(path, dest_path).map_err(OrbitError::io(path))?;
+
+        return Ok(());
+    }
+
+    /// Adds the scripts `rendered` needs to the head of the finished page.
+    fn inject_scripts(&self, mut render: String, rendered: &RenderedPage, root: &str) -> String {
+        if rendered.has_math {
+            render = inject_head(&render, math::KATEX_HEAD);
+        }
+        if rendered.has_orbit && !render.contains(ORBIT_SCRIPT_SRC) {
+            render = inject_head(&render, orbit::ORBIT_HEAD);
+        }
+        if self.config.offline {
+            render = inject_head(&render, &offline::head(root));
+        }
+
+        return render;
+    }
+
+    /// Readies a page rendered for plain output path `output` to be written:
+    /// its links are fitted to the [`UrlStyle`] and it's minified if asked.
+    fn finish_html(&self, render: String, output: &Path) -> String {
+        let render = self.url_style().rewrite_html(&render, output);
+        if self.config.minify {
+            return minify::minify_html(&render);
+        }
+
+        return render;
+    }
+
+    /// Renders a page the site generates itself through the page template.
+    fn write_generated(&self, output: &Path, title: &str, body: String) -> Result<()> {
+        let frontmatter = Frontmatter { title: Some(title.to_string()), ..Frontmatter::default() };
+        let has_orbit = body.contains(orbit::REVIEW_START);
+        let card_count = body.matches(orbit::PROMPT_START).count();
+        let rendered = RenderedPage {
+            frontmatter,
+            body,
+            has_math: false,
+            has_orbit,
+            card_count,
+            // Listings aren't prose to be read through.
+            word_count: 0,
+            reading_time: 0,
+            warnings: Vec::new(),
+            attachments: Vec::new(),
+        };
+
+        return self.write_rendered(output, rendered);
+    }
+
+    /// Writes `rendered`, a page the site generates itself, to `output`
+    /// through the page template.
+    fn write_rendered(&self, output: &Path, mut rendered: RenderedPage) -> Result<()> {
+        let page = Page { path: self.destination.join(output), relative: output.to_path_buf() };
+        self.print_review_areas(&mut rendered);
+        let render = self.inject_scripts(self.render_template(&page, &rendered, None)?, &rendered, &page.root_prefix());
+        let render = self.finish_html(render, output);
+
+        return self.write_output(&self.destination.join(self.url_style().published(output)), render);
+    }
+
+    /// Works out what every page's neighbours are, where `sources[i]` is the
+    /// markdown of `pages[i]`.
+    fn neighbours(&self, pages: &[Page], sources: &[String], notes: &NoteIndex) -> Neighbours {
+        let series = Series::new(pages, sources);
+        // Pages in a series link along it instead, so they'd never link
+        // back to the pages dated either side.
+        let summaries = PageSummary::collect(pages, sources).into_iter()
+            .filter(|summary| !series.contains(&summary.output))
+            .collect();
+
+        Neighbours {
+            backlinks: Backlinks::new(pages, sources, notes),
+            related: Related::new(pages, sources, notes, self.config.related.limit),
+            series,
+            chronology: Chronology::new(summaries),
+        }
+    }
+
+    /// Renders `page` with its place in its series or else links to the pages
+    /// dated either side, its backlinks, and the pages related to it, warning
+    /// of frontmatter keys neither Orbit nor the templates use.
+    fn render_note(&self, page: &Page, markdown: &str, notes: &NoteIndex, neighbours: &Neighbours) -> Result<RenderedPage> {
+        let root = page.root_prefix();
+        let mut rendered = self.render_markdown(page, markdown, notes)?;
+        match neighbours.series.of(page) {
+            Some(series) => rendered.body.push_str(&series::series_html(&series, &root)),
+            None => {
+                let (prev, next) = (neighbours.chronology.prev(page), neighbours.chronology.next(page));
+                rendered.body.push_str(&listing::page_nav_html(prev, next, &root));
+            }
+        }
+        rendered.body.push_str(&backlinks::backlinks_html(&neighbours.backlinks.to(page), &root));
+        rendered.body.push_str(&related::related_html(neighbours.related.to(page), &root));
+        for key in rendered.frontmatter.unknown_keys() {
+            if self.config.frontmatter_keys.iter().any(|allowed| allowed == key) {
+                continue;
+            }
+            let message = match frontmatter::suggest_key(key) {
+                Some(known) => format!("unknown key; did you mean `{}`?", known),
+                None => String::from("unknown key; list it in `frontmatter_keys` if a template uses it"),
+            };
+            rendered.warnings.push(OrbitError::FrontmatterKey { key: key.to_string(), message });
+        }
+        self.print_review_areas(&mut rendered);
+
+        return Ok(rendered);
+    }
+
+    /// In the print profile, replaces the review areas in `rendered` with
+    /// their fallbacks, opened so the answers are on the page.
+    fn print_review_areas(&self, rendered: &mut RenderedPage) {
+        if self.config.profile != Profile::Print || !rendered.has_orbit {
+            return;
+        }
+
+        rendered.body = orbit::static_fallback(&rendered.body)
+            .replace("<details class=\"orbit-fallback\">", "<details class=\"orbit-fallback\" open>");
+        rendered.has_orbit = false;
+    }
+
+    /// Prints the page built from `page` to a PDF beside it, returning the
+    /// PDF's path relative to the destination.
+    fn write_pdf(&self, page: &Page) -> Result<PathBuf> {
+        let output = page.output_path().with_extension("pdf");
+        let dest_path = self.destination.join(&output);
+        self.written.lock().unwrap().insert(dest_path.clone());
+        if self.dry_run {
+            tracing::info!("would write {}", dest_path.display());
+            return Ok(output);
+        }
+
+        let html = self.destination.join(self.url_style().published(&page.output_path()));
+        pdf::print_to_pdf(&html, &dest_path, self.config.print.browser.as_deref())?;
+        tracing::debug!("printed {}", output.display());
+
+        return Ok(output);
+    }
+
+    /// Renders and writes out `page`, returning its warnings and the other
+    /// files written for it, relative to the destination.
+    fn write_markdown(
+        &self,
+        page: &Page,
+        markdown: &str,
+        notes: &NoteIndex,
+        neighbours: &Neighbours,
+    ) -> Result<(Vec<OrbitError>, Vec<PathBuf>)> {
+        let mut rendered = self.render_note(page, markdown, notes, neighbours)?;
+        let mut image_warnings = Vec::new();
+        let mut extra = Vec::new();
+        rendered.body = images::add_srcsets(
+            &rendered.body,
+            &page.relative,
+            &self.source,
+            &self.config.images,
+            |image, variant, width| {
+                extra.push(variant.to_path_buf());
+                let dest_path = self.destination.join(variant);
+                self.written.lock().unwrap().insert(dest_path.clone());
+                if self.dry_run {
+                    tracing::info!("would write {}", dest_path.display());
+                    return Ok(());
+                }
+                return images::resize(&self.source.join(image), &dest_path, width);
+            },
+            &mut image_warnings,
+        );
+        let render = self.render_template(page, &rendered, Some(neighbours))
+            .map_err(|err| err.in_file(&page.path))?;
+        let render = self.inject_scripts(render, &rendered, &page.root_prefix());
+        let render = self.finish_html(render, &page.output_path());
+
+        self.write_output(&self.destination.join(self.url_style().published(&page.output_path())), render)?;
+
+        let mut warnings: Vec<OrbitError> = rendered.warnings.into_iter()
+            .chain(image_warnings)
+            .map(|warning| warning.in_file(&page.path))
+            .collect();
+        for attachment in &rendered.attachments {
+            // A missing attachment leaves a broken image, not a broken page.
+            if let Err(err) = self.copy_asset(&self.source.join(&attachment.source), &attachment.output) {
+                warnings.push(err.in_file(&page.path));
+            }
+            extra.push(attachment.output.clone());
+        }
+
+        return Ok((warnings, extra));
+    }
+}
+
+/// Every file and directory under `source`, skipping hidden and ignored
+/// ones.
+fn walk_source<'a>(source: &'a Path, ignore: &'a IgnoreRules) -> impl Iterator<Item = walkdir::Result<walkdir::DirEntry>> + 'a {
+    WalkDir::new(source).into_iter().filter_entry(move |entry| {
+        let relative = entry.path().strip_prefix(source).unwrap_or(entry.path());
+        !crate::is_hidden(entry) && !ignore.is_ignored(relative, entry.file_type().is_dir())
+    })
+}
+
+/// Every markdown file under `source`, skipping hidden and ignored files and
+/// directories.
+pub fn find_pages(source: &Path, ignore: &IgnoreRules) -> Result<Vec<Page>> {
+    let mut pages = Vec::new();
+    for entry in walk_source(source, ignore) {
+        let entry = entry?;
+        if entry.file_type().is_dir() || !crate::is_markdown(entry.file_name()) {
+            continue;
+        }
+
+        let relative = entry.path().strip_prefix(source)
+            .expect("walked entries live under the source directory")
+            .to_path_buf();
+        pages.push(Page { path: entry.path().to_path_buf(), relative });
+    }
+
+    return Ok(pages);
+}
+
+/// Whether `markdown` is marked `draft: true` in its frontmatter.
+pub(crate) fn is_draft(markdown: &str) -> bool {
+    frontmatter::split_frontmatter(markdown).is_ok_and(|(frontmatter, _)| frontmatter.draft)
+}
+
+/// The relative path from `relative`, a path under the site root, back to
+/// the root.
+pub(crate) fn root_prefix(relative: &Path) -> String {
+    let depth = relative.components().count().saturating_sub(1);
+
+    return "../".repeat(depth);
+}
+
+/// The markdown of every note `markdown` embeds, and every note those
+/// embed in turn. `indices` maps each page's relative path to its place in
+/// `sources`.
+fn embedded_sources<'a>(
+    markdown: &str,
+    notes: &NoteIndex,
+    indices: &HashMap<&Path, usize>,
+    sources: &'a [String],
+) -> Vec<&'a str> {
+    let mut seen = Vec::new();
+    let mut pending: Vec<&str> = wikilink::embeds(markdown).collect();
+    while let Some(target) = pending.pop() {
+        let idx = notes.resolve(target)
+            .and_then(|output| notes.source(output))
+            .and_then(|relative| indices.get(relative));
+        if let Some(&idx) = idx {
+            if !seen.contains(&idx) {
+                seen.push(idx);
+                pending.extend(wikilink::embeds(&sources[idx]));
+            }
+        }
+    }
+
+    return seen.into_iter().map(|idx| sources[idx].as_str()).collect();
+}
+
+/// The part of `html` from the heading with id `id` up to the next heading
+/// of the same or a higher level.
+fn section<'a>(html: &'a str, id: &str) -> Option<&'a str> {
+    let start = HEADING.captures_iter(html).find(|captures| &captures[2] == id)?;
+    let level = &start[1];
+    let from = start.get(0).unwrap().start();
+    let end = HEADING.captures_iter(&html[from..]).skip(1)
+        .find(|captures| &captures[1] <= level)
+        .map_or(html.len(), |captures| from + captures.get(0).unwrap().start());
+
+    return Some(&html[from..end]);
+}
+
+/// Every file under `source` but the markdown, skipping hidden and ignored
+/// files and directories, relative to `source`.
+fn find_files(source: &Path, ignore: &IgnoreRules) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in walk_source(source, ignore) {
+        let entry = entry?;
+        if entry.file_type().is_dir() || crate::is_markdown(entry.file_name()) {
+            continue;
+        }
+
+        let relative = entry.path().strip_prefix(source)
+            .expect("walked entries live under the source directory")
+            .to_path_buf();
+        files.push(relative);
+    }
+
+    return Ok(files);
+}
+
+/// Reads the markdown of every page, in the same order.
+fn read_sources(pages: &[Page]) -> Result<Vec<String>> {
+    return pages.par_iter()
+        .map(|page| crate::read_note(&page.path))
+        .collect();
+}
+
+/// A page before or after another, as the template sees it.
+fn page_link(title: &str, output: &Path, date: Option<&str>) -> serde_json::Value {
+    serde_json::json!({ "title": title, "output": output, "date": date })
+}
+
+fn create_parent_dir(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(OrbitError::io(parent))?;
+    }
+
+    return Ok(());
+}
+
+/// Inserts `snippet` just before `</head>`, or leaves `html` alone if the
+/// template has no head.
+fn inject_head(html: &str, snippet: &str) -> String {
+    match html.find("</head>") {
+        Some(idx) => format!("{}{}{}", &html[..idx], snippet, &html[idx..]),
+        None => html.to_string(),
+    }
+}
@@ -0,0 +1,59 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::backlinks;
+use crate::frontmatter;
+use crate::listing::PageSummary;
+use crate::search::{SearchEntry, SearchHeading};
+use crate::site::Page;
+use crate::wikilink::NoteIndex;
+
+pub const SITE_JSON_FILENAME: &str = "site.json";
+
+/// A page as other tools see it in `site.json`.
+#[derive(Serialize, Debug)]
+pub struct PageData {
+    /// The page's markdown, relative to the source directory.
+    pub path: PathBuf,
+    /// The page's URL relative to the site root.
+    pub url: String,
+    pub title: String,
+    pub date: Option<String>,
+    pub tags: Vec<String>,
+    /// The `path` of every page this one links to, by wikilink or by a
+    /// relative link to its markdown.
+    pub links: Vec<PathBuf>,
+    pub headings: Vec<SearchHeading>,
+    /// How many Orbit prompts the page has.
+    pub card_count: usize,
+    pub excerpt: Option<String>,
+}
+
+impl PageData {
+    /// Describes `page`, which `summary` summarizes and is written as
+    /// `markdown`, under `url`.
+    pub fn new(page: &Page, summary: &PageSummary, markdown: &str, notes: &NoteIndex, url: String) -> PageData {
+        let content = frontmatter::split_frontmatter(markdown).map_or(markdown, |(_, content)| content);
+        let mut links: Vec<PathBuf> = backlinks::outgoing_links(page, content, notes).iter()
+            .filter_map(|output| notes.source(output))
+            .filter(|linked| **linked != page.relative)
+            .map(PathBuf::from)
+            .collect();
+        links.sort();
+        links.dedup();
+        let entry = SearchEntry::new(summary, markdown, url);
+
+        return PageData {
+            path: page.relative.clone(),
+            url: entry.url,
+            title: entry.title,
+            date: summary.date.clone(),
+            tags: entry.tags,
+            links,
+            headings: entry.headings,
+            card_count: summary.card_count,
+            excerpt: entry.excerpt,
+        };
+    }
+}
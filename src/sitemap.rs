@@ -0,0 +1,46 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+use pulldown_cmark::escape::escape_html;
+
+use crate::urls;
+
+pub const SITEMAP_FILENAME: &str = "sitemap.xml";
+
+/// A page to list in the sitemap.
+pub struct SitemapEntry {
+    /// The page's URL relative to the site root.
+    pub output: PathBuf,
+    /// When the page's source last changed, if known.
+    pub modified: Option<SystemTime>,
+}
+
+/// The modification time of `path`, or nothing if the filesystem doesn't
+/// record one.
+pub fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// The time in RFC 3339 timestamp `timestamp`, such as a commit date.
+pub fn parse_modified(timestamp: &str) -> Option<SystemTime> {
+    DateTime::parse_from_rfc3339(timestamp).ok().map(SystemTime::from)
+}
+
+/// Builds a sitemap listing `entries` as absolute URLs under `base_url`.
+pub fn sitemap(base_url: &str, entries: &[SitemapEntry]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for entry in entries {
+        xml.push_str("  <url>\n    <loc>");
+        let _ = escape_html(&mut xml, &urls::absolute_url(base_url, &entry.output.to_string_lossy()));
+        xml.push_str("</loc>\n");
+        if let Some(modified) = entry.modified {
+            let modified: DateTime<Utc> = modified.into();
+            xml.push_str(&format!("    <lastmod>{}</lastmod>\n", modified.format("%Y-%m-%d")));
+        }
+        xml.push_str("  </url>\n");
+    }
+    xml.push_str("</urlset>\n");
+
+    return xml;
+}
@@ -0,0 +1,20 @@
+/// Lowercases `text` and collapses every run of non-alphanumeric characters
+/// into a single `-`, e.g. `"Hello, World!"` becomes `"hello-world"`.
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    let mut pending_dash = false;
+
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            if pending_dash && !slug.is_empty() {
+                slug.push('-');
+            }
+            pending_dash = false;
+            slug.extend(c.to_lowercase());
+        } else {
+            pending_dash = true;
+        }
+    }
+
+    return slug;
+}
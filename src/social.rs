@@ -0,0 +1,106 @@
+use std::path::Path;
+
+use pulldown_cmark::escape::escape_html;
+use regex::Regex;
+
+use crate::urls::{self, normalize, unescape};
+
+/// Longest excerpt taken from a page's text, in characters.
+const EXCERPT_CHARS: usize = 200;
+
+lazy_static::lazy_static! {
+    static ref PARAGRAPH: Regex = Regex::new(r"(?s)<p>(.*?)</p>").unwrap();
+    static ref NOTE: Regex =
+        Regex::new(r#"(?s)<sup class="fn">.*?</sup>|<span class="(?:sidenote|marginnote)">.*?</span>"#).unwrap();
+    static ref TAG: Regex = Regex::new(r"<[^>]*>").unwrap();
+    static ref SCHEME: Regex = Regex::new(r"^[A-Za-z][A-Za-z0-9+.-]*:").unwrap();
+}
+
+/// What a link preview shows for a page.
+#[derive(Debug, Default)]
+pub struct Preview<'a> {
+    pub title: Option<&'a str>,
+    pub description: Option<&'a str>,
+    /// Absolute URL of the preview image.
+    pub image: Option<&'a str>,
+    /// Absolute URL of the page.
+    pub url: Option<&'a str>,
+    pub site_name: Option<&'a str>,
+}
+
+/// The plain text of the first paragraph of `html` with any text in it,
+/// cut at a word boundary if it's long. Footnote references and margin
+/// notes are left out.
+pub fn excerpt(html: &str) -> Option<String> {
+    let text = PARAGRAPH.captures_iter(html)
+        .map(|captures| {
+            let text = NOTE.replace_all(&captures[1], "");
+            let text = TAG.replace_all(&text, "");
+            unescape(&text).split_whitespace().collect::<Vec<_>>().join(" ")
+        })
+        .find(|text| !text.is_empty())?;
+
+    return Some(shorten(text));
+}
+
+/// `text` cut at a word boundary if it's longer than an excerpt should be.
+pub(crate) fn shorten(text: String) -> String {
+    if text.chars().count() <= EXCERPT_CHARS {
+        return text;
+    }
+
+    let cut: String = text.chars().take(EXCERPT_CHARS).collect();
+    let cut = match cut.rfind(' ') {
+        Some(space) => &cut[..space],
+        None => &cut,
+    };
+
+    return format!("{}…", cut.trim_end_matches([',', ';', ':', '.']));
+}
+
+/// `url`, written relative to the page with plain output path `output`, as
+/// an absolute URL under `base_url`. URLs with a scheme are left as they are.
+pub fn resolve_url(base_url: &str, output: &Path, url: &str) -> Option<String> {
+    if SCHEME.is_match(url) {
+        return Some(url.to_string());
+    }
+
+    let base_url = base_url.trim_end_matches('/');
+    if let Some(host) = url.strip_prefix("//") {
+        let scheme = base_url.split_once("//").map_or("https:", |(scheme, _)| scheme);
+        return Some(format!("{}//{}", scheme, host));
+    }
+    let path = match url.strip_prefix('/') {
+        Some(absolute) => normalize(Path::new(absolute))?,
+        None => normalize(&output.parent().unwrap_or_else(|| Path::new("")).join(url))?,
+    };
+
+    return Some(urls::absolute_url(base_url, &path.to_string_lossy()));
+}
+
+/// Open Graph and Twitter card `<meta>` tags for `preview`.
+pub fn meta_tags(preview: &Preview) -> String {
+    let mut html = String::new();
+    let card = if preview.image.is_some() { "summary_large_image" } else { "summary" };
+    push_meta(&mut html, "name", "twitter:card", Some(card));
+
+    push_meta(&mut html, "property", "og:type", Some("article"));
+    push_meta(&mut html, "property", "og:title", preview.title);
+    push_meta(&mut html, "property", "og:description", preview.description);
+    push_meta(&mut html, "property", "og:image", preview.image);
+    push_meta(&mut html, "property", "og:url", preview.url);
+    push_meta(&mut html, "property", "og:site_name", preview.site_name);
+    push_meta(&mut html, "name", "twitter:title", preview.title);
+    push_meta(&mut html, "name", "twitter:description", preview.description);
+    push_meta(&mut html, "name", "twitter:image", preview.image);
+
+    return html;
+}
+
+fn push_meta(html: &mut String, attribute: &str, name: &str, content: Option<&str>) {
+    if let Some(content) = content {
+        html.push_str(&format!("<meta {}=\"{}\" content=\"", attribute, name));
+        let _ = escape_html(&mut *html, content);
+        html.push_str("\">\n");
+    }
+}
@@ -0,0 +1,48 @@
+// Caches every page and file of the site when it's installed, so it can be
+// read offline. Pages are served from the cache and refreshed from the
+// network behind the scenes; anything else fetched, such as the Orbit
+// component or KaTeX, is cached the first time it's seen.
+var VERSION = "{{version}}";
+var PRECACHE = {{precache}};
+var CACHE = "orbit-" + VERSION;
+
+self.addEventListener("install", function (event) {
+    event.waitUntil(
+        caches.open(CACHE)
+            .then(function (cache) { return cache.addAll(PRECACHE); })
+            .then(function () { return self.skipWaiting(); })
+    );
+});
+
+self.addEventListener("activate", function (event) {
+    event.waitUntil(
+        caches.keys()
+            .then(function (keys) {
+                return Promise.all(keys
+                    .filter(function (key) { return key.indexOf("orbit-") === 0 && key !== CACHE; })
+                    .map(function (key) { return caches.delete(key); }));
+            })
+            .then(function () { return self.clients.claim(); })
+    );
+});
+
+self.addEventListener("fetch", function (event) {
+    if (event.request.method !== "GET") {
+        return;
+    }
+    event.respondWith(caches.open(CACHE).then(function (cache) {
+        return cache.match(event.request, { ignoreSearch: true }).then(function (cached) {
+            var fetched = fetch(event.request).then(function (response) {
+                if (response.ok || response.type === "opaque") {
+                    cache.put(event.request, response.clone());
+                }
+                return response;
+            });
+            if (cached) {
+                event.waitUntil(fetched.catch(function () {}));
+                return cached;
+            }
+            return fetched;
+        });
+    }));
+});
@@ -0,0 +1,502 @@
+//! The pipeline turning a note's parsed markdown into HTML.
+//!
+//! Every event the parser produces is handed down a chain of
+//! [`EventTransformer`]s, each of which can rewrite it, swallow it, or pass
+//! it on to the next; what comes out of the end of the chain is kept as it
+//! is. Once the whole note has been through, each transformer gets a last
+//! look at everything kept. [`default_pipeline`] builds the chain Orbit
+//! renders with, which can be extended or reordered and handed to
+//! [`render_page_with_pipeline`](crate::render_page_with_pipeline).
+
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::Path;
+
+use pulldown_cmark::escape::{escape_href, escape_html};
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Tag};
+
+use crate::config::RenderOptions;
+use crate::error::{OrbitError, Result};
+use crate::frontmatter::Frontmatter;
+use crate::orbit::{Attachment, DeckFormat};
+use crate::wikilink::{self, NoteIndex};
+use crate::{graphviz, highlight, Footnote};
+
+/// One step of the pipeline.
+pub trait EventTransformer {
+    /// Handles `event`, taken from `range` of the content, pushing whatever
+    /// it becomes onto `events`, the output so far. Returns the event if it
+    /// should carry on down the pipeline, or `None` if it's been dealt with.
+    fn transform<'e>(
+        &mut self,
+        event: Event<'e>,
+        range: Range<usize>,
+        events: &mut Vec<Event<'e>>,
+        context: &mut RenderContext,
+    ) -> Result<Option<Event<'e>>>;
+
+    /// Reworks the whole output once every event has been through.
+    fn finish(&mut self, _events: &mut Vec<Event>, _context: &mut RenderContext) -> Result<()> {
+        return Ok(());
+    }
+}
+
+/// The note being rendered, and what the pipeline has found out about it.
+pub struct RenderContext<'a> {
+    /// The markdown being parsed, footnote definitions taken out, which
+    /// event ranges index into.
+    pub content: &'a str,
+    pub frontmatter: &'a Frontmatter,
+    pub options: &'a RenderOptions,
+    pub notes: &'a NoteIndex,
+    /// The source directory, against which local images are resolved.
+    pub source: &'a Path,
+    /// The note's path within the source directory.
+    pub relative: &'a Path,
+    /// The relative path from the note back to the site root.
+    pub root: String,
+    /// Problems that shouldn't stop the page rendering.
+    pub warnings: Vec<OrbitError>,
+    /// Local files the note's Orbit cards refer to.
+    pub attachments: Vec<Attachment>,
+    /// `(level, id, text)` of every heading kept, in order.
+    pub headings: Vec<(usize, String, String)>,
+    /// How many words of prose the note has.
+    pub word_count: usize,
+    pub(crate) footnotes: Vec<Footnote>,
+    /// The line of the file each line of `content` was taken from.
+    pub(crate) lines: Vec<usize>,
+    pub(crate) line_offset: usize,
+}
+
+impl RenderContext<'_> {
+    /// The line of the file an offset into `content` falls on.
+    pub fn source_line(&self, offset: usize) -> usize {
+        let line = self.content[..offset].matches('\n').count();
+
+        return self.lines.get(line).copied().unwrap_or(self.line_offset + 1);
+    }
+
+    /// Whether footnotes are rendered as sidenotes rather than a list at
+    /// the end of the page.
+    pub fn sidenotes(&self) -> bool {
+        !self.options.print && self.frontmatter.sidenotes.unwrap_or(self.options.sidenotes)
+    }
+}
+
+/// The pipeline Orbit renders notes with, in order.
+pub fn default_pipeline() -> Vec<Box<dyn EventTransformer>> {
+    return vec![
+        Box::new(WordCounter::default()),
+        Box::new(FootnoteHandler::default()),
+        Box::new(LinkRewriter::default()),
+        Box::new(HeadingHandler::default()),
+        Box::new(OrbitHandler::default()),
+        Box::new(CodeBlockHandler::default()),
+        Box::new(TableWrapper),
+        Box::new(TaskListHandler),
+        Box::new(WikilinkResolver),
+        Box::new(FigureWrapper),
+        Box::new(TocInserter),
+    ];
+}
+
+/// Counts the words of prose, leaving out code blocks and Orbit decks.
+#[derive(Default)]
+pub struct WordCounter {
+    in_code_block: bool,
+    prose: String,
+}
+
+impl EventTransformer for WordCounter {
+    fn transform<'e>(&mut self, event: Event<'e>, _: Range<usize>, _: &mut Vec<Event<'e>>, _: &mut RenderContext) -> Result<Option<Event<'e>>> {
+        // Smart punctuation splits text at apostrophes, so words are only
+        // counted once the text between blocks is joined back up.
+        match &event {
+            Event::Start(Tag::CodeBlock(_)) => self.in_code_block = true,
+            Event::End(Tag::CodeBlock(_)) => self.in_code_block = false,
+            Event::Text(text) | Event::Code(text) if !self.in_code_block => self.prose.push_str(text),
+            Event::Start(Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(..))
+                | Event::End(Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(..)) => {}
+            _ => self.prose.push(' '),
+        }
+
+        return Ok(Some(event));
+    }
+
+    fn finish(&mut self, _: &mut Vec<Event>, context: &mut RenderContext) -> Result<()> {
+        context.word_count += self.prose.split_whitespace().count();
+
+        return Ok(());
+    }
+}
+
+/// Renders footnote references as sidenotes, margin notes, or numbered
+/// references to the list it adds to the end of the page.
+#[derive(Default)]
+pub struct FootnoteHandler {
+    /// Footnotes numbered in the order they're first referenced.
+    numbered: Vec<String>,
+    /// Every footnote referenced at all, margin notes and sidenotes included.
+    referenced: HashSet<String>,
+}
+
+impl EventTransformer for FootnoteHandler {
+    fn transform<'e>(&mut self, event: Event<'e>, range: Range<usize>, events: &mut Vec<Event<'e>>, context: &mut RenderContext) -> Result<Option<Event<'e>>> {
+        let name = match event {
+            Event::FootnoteReference(name) => name,
+            event => return Ok(Some(event)),
+        };
+        let definition = crate::footnote_definition(&context.footnotes, &name);
+        if self.referenced.insert(name.to_string()) && definition.is_none() {
+            context.warnings.push(OrbitError::Footnote {
+                line: context.source_line(range.start),
+                message: format!("`[^{}]` has no definition", name),
+            });
+        }
+
+        let footnote_html = if name.starts_with('+') && !context.options.print {
+            crate::marginnote_html(&name[1..], definition.unwrap_or_default())
+        } else if context.sidenotes() {
+            crate::sidenote_html(&name, definition.unwrap_or_default())
+        } else {
+            // Later references to a footnote share its number, and only the
+            // first is where its back link returns to.
+            let mut id = String::new();
+            let _ = escape_html(&mut id, &name);
+            let mut href = String::new();
+            let _ = escape_href(&mut href, &name);
+            match self.numbered.iter().position(|numbered| **numbered == *name) {
+                Some(idx) => format!("<sup class=\"fn\"><a href=\"#{}\">[{}]</a></sup>", href, idx + 1),
+                None => {
+                    self.numbered.push(name.to_string());
+                    format!("<sup class=\"fn\"><a id=\"{}-back\" href=\"#{}\">[{}]</a></sup>", id, href, self.numbered.len())
+                }
+            }
+        };
+        events.push(Event::Html(footnote_html.into()));
+
+        return Ok(None);
+    }
+
+    fn finish(&mut self, events: &mut Vec<Event>, context: &mut RenderContext) -> Result<()> {
+        for footnote in context.footnotes.iter().filter(|footnote| !self.referenced.contains(&footnote.name)) {
+            context.warnings.push(OrbitError::Footnote {
+                line: footnote.line,
+                message: format!("`[^{}]` is never referenced", footnote.name),
+            });
+        }
+        // The list follows the numbering, so margin notes only join it in print.
+        let footnotes: Vec<&Footnote> = self.numbered.iter()
+            .filter_map(|name| context.footnotes.iter().find(|footnote| footnote.name == *name))
+            .collect();
+        if !context.sidenotes() && !footnotes.is_empty() {
+            events.push(Event::Html(crate::fmt_footnotes_to_html(&footnotes)?.into()));
+        }
+
+        return Ok(());
+    }
+}
+
+/// Points links at notes to their HTML, and decorates links to other sites
+/// as the `[markdown]` table asks.
+#[derive(Default)]
+pub struct LinkRewriter {
+    /// Whether each open link was opened as decorated external link markup.
+    external_links: Vec<bool>,
+}
+
+impl EventTransformer for LinkRewriter {
+    fn transform<'e>(&mut self, event: Event<'e>, _: Range<usize>, events: &mut Vec<Event<'e>>, context: &mut RenderContext) -> Result<Option<Event<'e>>> {
+        match event {
+            Event::Start(Tag::Link(link_type, destination, title)) => {
+                let new_destination = crate::rewrite_link(&destination);
+                match crate::external_link_html(&new_destination, &title, context.options) {
+                    Some(open) => {
+                        events.push(Event::Html(open.into()));
+                        self.external_links.push(true);
+                    }
+                    None => {
+                        events.push(Event::Start(Tag::Link(link_type, new_destination.into(), title)));
+                        self.external_links.push(false);
+                    }
+                }
+            }
+            Event::End(Tag::Link(link_type, destination, title)) => {
+                match self.external_links.pop() {
+                    Some(true) => events.push(Event::Html("</a>".into())),
+                    _ => events.push(Event::End(Tag::Link(link_type, destination, title))),
+                }
+            }
+            event => return Ok(Some(event)),
+        }
+
+        return Ok(None);
+    }
+}
+
+/// Gives every heading a unique id, and a permalink if asked, noting it
+/// for the table of contents. Drops a title heading that's already the
+/// page's title if `strip_title_heading` is set.
+#[derive(Default)]
+pub struct HeadingHandler {
+    /// Where the open heading's start tag sits in the output.
+    heading_start: Option<usize>,
+    /// The slugs already handed out, so repeated headings get distinct ids.
+    heading_ids: HashSet<String>,
+    title_stripped: bool,
+}
+
+impl EventTransformer for HeadingHandler {
+    fn transform<'e>(&mut self, event: Event<'e>, _: Range<usize>, events: &mut Vec<Event<'e>>, context: &mut RenderContext) -> Result<Option<Event<'e>>> {
+        let level = match event {
+            Event::Start(Tag::Heading(level, id, classes)) => {
+                self.heading_start = Some(events.len());
+                events.push(Event::Start(Tag::Heading(level, id, classes)));
+                return Ok(None);
+            }
+            Event::End(Tag::Heading(level, _, _)) if self.heading_start.is_some() => level,
+            event => return Ok(Some(event)),
+        };
+
+        let start = self.heading_start.take().unwrap_or_default();
+        let strip_title = context.options.strip_title_heading && context.frontmatter.inferred_title;
+        if strip_title && !self.title_stripped && level == HeadingLevel::H1 {
+            events.truncate(start);
+            self.title_stripped = true;
+            return Ok(None);
+        }
+        let (id, classes) = match &events[start] {
+            Event::Start(Tag::Heading(_, id, classes)) => (id.map(str::to_string), classes.join(" ")),
+            _ => (None, String::new()),
+        };
+        let text = crate::plain_text(&events[(start + 1)..]);
+        let id = match id {
+            Some(id) => {
+                self.heading_ids.insert(id.clone());
+                id
+            }
+            None => crate::unique_slug(&text, &mut self.heading_ids),
+        };
+
+        let mut open = format!("<{} id=\"", level);
+        let _ = escape_html(&mut open, &id);
+        open.push('"');
+        if !classes.is_empty() {
+            open.push_str(" class=\"");
+            let _ = escape_html(&mut open, &classes);
+            open.push('"');
+        }
+        open.push('>');
+        events[start] = Event::Html(open.into());
+        context.headings.push((level as usize, id.clone(), text));
+
+        if context.options.heading_permalinks {
+            let mut permalink = String::from(" <a class=\"permalink\" href=\"#");
+            let _ = escape_href(&mut permalink, &id);
+            permalink.push_str("\">¶</a>");
+            events.push(Event::Html(permalink.into()));
+        }
+        events.push(Event::Html(format!("</{}>\n", level).into()));
+
+        return Ok(None);
+    }
+}
+
+/// Renders `orbit` blocks as review areas, swallowing the deck inside.
+#[derive(Default)]
+pub struct OrbitHandler {
+    in_orbit_block: bool,
+}
+
+impl EventTransformer for OrbitHandler {
+    fn transform<'e>(&mut self, event: Event<'e>, range: Range<usize>, events: &mut Vec<Event<'e>>, context: &mut RenderContext) -> Result<Option<Event<'e>>> {
+        match &event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(language))) => {
+                let format = match DeckFormat::from_language(language) {
+                    Some(format) => format,
+                    None => return Ok(Some(event)),
+                };
+                let codeblock = &context.content[range.start..range.end];
+                let line = context.source_line(range.start);
+                // A broken deck shouldn't sink the whole page, so it is
+                // reported as a warning and left out.
+                let orbit_html = match crate::deserialize_orbit_codeblock(codeblock, format, line) {
+                    Ok(mut orbit) => {
+                        context.attachments.extend(orbit.resolve_attachments(context.relative, &context.root));
+                        orbit.to_html(context.options.orbit_color.as_deref())?
+                    }
+                    Err(err) => {
+                        let mut html = String::from("<pre class=\"orbit-error\">This review deck couldn’t be read: ");
+                        let _ = escape_html(&mut html, &err.to_string());
+                        html.push_str("</pre>\n");
+                        context.warnings.push(err);
+                        html
+                    }
+                };
+                self.in_orbit_block = true;
+                events.push(Event::Html(orbit_html.into()));
+            }
+            Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(language))) if DeckFormat::from_language(language).is_some() => {
+                self.in_orbit_block = false;
+            }
+            _ if self.in_orbit_block => {}
+            _ => return Ok(Some(event)),
+        }
+
+        return Ok(None);
+    }
+}
+
+/// Renders fenced code blocks that name a language: highlighted, as an
+/// epigraph, or as a Graphviz diagram if `graphviz` is set.
+#[derive(Default)]
+pub struct CodeBlockHandler {
+    /// The language and accumulated text of the block being rendered.
+    code_block: Option<(String, String)>,
+}
+
+impl EventTransformer for CodeBlockHandler {
+    fn transform<'e>(&mut self, event: Event<'e>, range: Range<usize>, events: &mut Vec<Event<'e>>, context: &mut RenderContext) -> Result<Option<Event<'e>>> {
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(language))) => {
+                match language.split([',', ' ']).next().filter(|token| !token.is_empty()) {
+                    Some(token) => self.code_block = Some((token.to_string(), String::new())),
+                    None => return Ok(Some(Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(language))))),
+                }
+            }
+            Event::Text(text) if self.code_block.is_some() => {
+                if let Some((_, code)) = self.code_block.as_mut() {
+                    code.push_str(&text);
+                }
+            }
+            Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(language))) => {
+                let (token, code) = match self.code_block.take() {
+                    Some(code_block) => code_block,
+                    None => return Ok(Some(Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(language))))),
+                };
+                let block_html = match token.as_str() {
+                    "epigraph" => crate::epigraph_html(&code),
+                    // A graph that can't be laid out is shown as code.
+                    token if context.options.graphviz && graphviz::LANGUAGES.contains(&token) => {
+                        graphviz::render_svg(&code).unwrap_or_else(|message| {
+                            context.warnings.push(OrbitError::Graphviz { line: context.source_line(range.start), message });
+                            highlight::highlight_code(token, &code)
+                        })
+                    }
+                    _ => highlight::highlight_code(&token, &code),
+                };
+                events.push(Event::Html(block_html.into()));
+            }
+            event => return Ok(Some(event)),
+        }
+
+        return Ok(None);
+    }
+}
+
+/// Wraps tables so wide ones scroll within the text column instead of
+/// spilling into the margin.
+pub struct TableWrapper;
+
+impl EventTransformer for TableWrapper {
+    fn transform<'e>(&mut self, event: Event<'e>, _: Range<usize>, events: &mut Vec<Event<'e>>, _: &mut RenderContext) -> Result<Option<Event<'e>>> {
+        match event {
+            Event::Start(Tag::Table(alignments)) => {
+                events.push(Event::Html("<div class=\"table-wrapper\">\n".into()));
+                events.push(Event::Start(Tag::Table(alignments)));
+            }
+            Event::End(Tag::Table(alignments)) => {
+                events.push(Event::End(Tag::Table(alignments)));
+                events.push(Event::Html("</div>\n".into()));
+            }
+            event => return Ok(Some(event)),
+        }
+
+        return Ok(None);
+    }
+}
+
+/// Renders task list markers as disabled checkboxes, classing their items
+/// to style them by.
+pub struct TaskListHandler;
+
+impl EventTransformer for TaskListHandler {
+    fn transform<'e>(&mut self, event: Event<'e>, _: Range<usize>, events: &mut Vec<Event<'e>>, _: &mut RenderContext) -> Result<Option<Event<'e>>> {
+        let checked = match event {
+            Event::TaskListMarker(checked) => checked,
+            event => return Ok(Some(event)),
+        };
+
+        // The marker directly follows its item's start tag, which is swapped
+        // for one carrying classes to style the item by.
+        if let Some(Event::Start(Tag::Item)) = events.last() {
+            events.pop();
+            let class = if checked { "task-list-item checked" } else { "task-list-item" };
+            events.push(Event::Html(format!("<li class=\"{}\">", class).into()));
+        }
+
+        let checkbox = if checked {
+            "<input type=\"checkbox\" class=\"task-checkbox\" disabled checked> "
+        } else {
+            "<input type=\"checkbox\" class=\"task-checkbox\" disabled> "
+        };
+        events.push(Event::Html(checkbox.into()));
+
+        return Ok(None);
+    }
+}
+
+/// Turns `[[wikilinks]]` into links to the notes they name.
+pub struct WikilinkResolver;
+
+impl EventTransformer for WikilinkResolver {
+    fn transform<'e>(&mut self, event: Event<'e>, _: Range<usize>, _: &mut Vec<Event<'e>>, _: &mut RenderContext) -> Result<Option<Event<'e>>> {
+        return Ok(Some(event));
+    }
+
+    fn finish(&mut self, events: &mut Vec<Event>, context: &mut RenderContext) -> Result<()> {
+        *events = wikilink::resolve_wikilinks(std::mem::take(events), context.notes, &context.root);
+
+        return Ok(());
+    }
+}
+
+/// Turns paragraphs holding a single image into figures, and gives every
+/// other image its size up front.
+pub struct FigureWrapper;
+
+impl EventTransformer for FigureWrapper {
+    fn transform<'e>(&mut self, event: Event<'e>, _: Range<usize>, _: &mut Vec<Event<'e>>, _: &mut RenderContext) -> Result<Option<Event<'e>>> {
+        return Ok(Some(event));
+    }
+
+    fn finish(&mut self, events: &mut Vec<Event>, context: &mut RenderContext) -> Result<()> {
+        crate::wrap_figures(events, context.source, context.relative);
+        crate::render_images(events, context.source, context.relative);
+
+        return Ok(());
+    }
+}
+
+/// Puts the table of contents where a paragraph holds only `[TOC]`, or
+/// failing that at the very top if the frontmatter sets `toc: true`.
+pub struct TocInserter;
+
+impl EventTransformer for TocInserter {
+    fn transform<'e>(&mut self, event: Event<'e>, _: Range<usize>, _: &mut Vec<Event<'e>>, _: &mut RenderContext) -> Result<Option<Event<'e>>> {
+        return Ok(Some(event));
+    }
+
+    fn finish(&mut self, events: &mut Vec<Event>, context: &mut RenderContext) -> Result<()> {
+        match crate::find_toc_marker(events) {
+            Some(range) => {
+                events.splice(range, [Event::Html(crate::toc_html(&context.headings).into())]);
+            }
+            None if context.frontmatter.toc => events.insert(0, Event::Html(crate::toc_html(&context.headings).into())),
+            None => {}
+        }
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,269 @@
+use std::path::{Component, Path, PathBuf};
+
+use pulldown_cmark::escape::escape_href;
+use regex::{Captures, Regex};
+
+use crate::slug::slugify;
+
+lazy_static::lazy_static! {
+    static ref URL_ATTRIBUTE: Regex =
+        Regex::new(r#"(\s(?:href|src|question-attachments|answer-attachments)=")([^"]*)""#).unwrap();
+    static ref SRCSET: Regex = Regex::new(r#"(\ssrcset=")([^"]*)""#).unwrap();
+    static ref SCHEME: Regex = Regex::new(r"^[A-Za-z][A-Za-z0-9+.-]*:").unwrap();
+}
+
+/// How rendered pages are laid out in the destination directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UrlStyle {
+    /// Write `note.html` as `note/index.html`, linked to as `note/`.
+    pub clean: bool,
+    /// Slugify the directories and file name of every page, so
+    /// `My Notes/Tom's Essay.html` becomes `my-notes/tom-s-essay.html`.
+    pub slugify: bool,
+}
+
+impl UrlStyle {
+    /// Whether pages are written to their plain output path, neither clean
+    /// nor slugified.
+    pub fn is_plain(&self) -> bool {
+        *self == UrlStyle::default()
+    }
+
+    /// Where the page with plain output path `output` is written.
+    pub fn published(&self, output: &Path) -> PathBuf {
+        let is_page = output.extension().is_some_and(|extension| extension == "html");
+        if !is_page {
+            return output.to_path_buf();
+        }
+
+        let output = match self.slugify {
+            true => slugify_path(output),
+            false => output.to_path_buf(),
+        };
+        if !self.clean || output.file_name().is_some_and(|name| name == "index.html") {
+            return output;
+        }
+
+        return output.with_extension("").join("index.html");
+    }
+
+    /// The path, relative to the site root, that links to the page with
+    /// plain output path `output` use.
+    pub fn permalink(&self, output: &Path) -> String {
+        let published = self.published(output);
+        if self.clean && published.file_name().is_some_and(|name| name == "index.html") {
+            let directory = published.parent().unwrap_or_else(|| Path::new(""));
+            if directory.as_os_str().is_empty() {
+                return String::new();
+            }
+            return format!("{}/", directory.to_string_lossy());
+        }
+
+        return published.to_string_lossy().into_owned();
+    }
+
+    /// Rewrites every relative URL in `html`, which was rendered to sit at
+    /// plain output path `output`, to suit where it and the pages it links
+    /// to are actually published.
+    pub fn rewrite_html(&self, html: &str, output: &Path) -> String {
+        if self.is_plain() {
+            return html.to_string();
+        }
+
+        let base = self.published(output);
+        let rewritten = URL_ATTRIBUTE.replace_all(html, |captures: &Captures| {
+            match self.rewrite_url(&unescape(&captures[2]), output, &base) {
+                Some(url) => {
+                    let mut attribute = captures[1].to_string();
+                    let _ = escape_href(&mut attribute, &url);
+                    attribute.push('"');
+                    attribute
+                }
+                None => captures[0].to_string(),
+            }
+        });
+        // Each candidate in a srcset is a URL and a width or density.
+        let rewritten = SRCSET.replace_all(&rewritten, |captures: &Captures| {
+            let candidates: Vec<String> = captures[2].split(',')
+                .map(|candidate| {
+                    let candidate = candidate.trim();
+                    let (url, descriptor) = candidate.split_once(' ').unwrap_or((candidate, ""));
+                    match self.rewrite_url(&unescape(url), output, &base) {
+                        Some(rewritten) => {
+                            let mut candidate = String::new();
+                            let _ = escape_href(&mut candidate, &rewritten);
+                            format!("{} {}", candidate, descriptor).trim_end().to_string()
+                        }
+                        None => candidate.to_string(),
+                    }
+                })
+                .collect();
+            format!("{}{}\"", &captures[1], candidates.join(", "))
+        });
+
+        return rewritten.into_owned();
+    }
+
+    /// Rewrites `url`, written relative to plain output path `output`, to be
+    /// relative to `base` instead and to point at where its target is
+    /// published. URLs that aren't relative paths are left alone.
+    fn rewrite_url(&self, url: &str, output: &Path, base: &Path) -> Option<String> {
+        if url.is_empty() || url.starts_with(['/', '#', '?']) || SCHEME.is_match(url) {
+            return None;
+        }
+
+        let suffix_start = url.find(['?', '#']).unwrap_or(url.len());
+        let (path, suffix) = url.split_at(suffix_start);
+        let target = normalize(&output.parent().unwrap_or_else(|| Path::new("")).join(percent_decode(path)))?;
+        let target = match target.extension().is_some_and(|extension| extension == "html") {
+            true => self.permalink(&target),
+            false => target.to_string_lossy().into_owned(),
+        };
+
+        let mut relative = relative_to(&target, base.parent().unwrap_or_else(|| Path::new("")));
+        if relative.is_empty() {
+            relative.push_str("./");
+        }
+        relative.push_str(suffix);
+
+        return Some(relative);
+    }
+}
+
+/// The absolute URL of `path`, relative to the site root, under `base_url`.
+/// Characters that can't appear in a URL are percent-encoded.
+pub fn absolute_url(base_url: &str, path: &str) -> String {
+    let mut url = format!("{}/", base_url.trim_end_matches('/'));
+    for byte in path.trim_start_matches('/').bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => url.push(byte as char),
+            b'-' | b'.' | b'_' | b'~' | b'!' | b'$' | b'&' | b'\'' | b'(' | b')' | b'*' | b'+' | b',' | b';'
+                | b'=' | b':' | b'@' | b'/' | b'%' | b'?' | b'#' => url.push(byte as char),
+            _ => url.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    return url;
+}
+
+/// Rewrites every relative URL in `html`, written for the page with plain
+/// output path `from`, to work from the page at `to` instead.
+pub fn rebase_html(html: &str, from: &Path, to: &Path) -> String {
+    if from.parent() == to.parent() {
+        return html.to_string();
+    }
+
+    let plain = UrlStyle::default();
+    let rebased = URL_ATTRIBUTE.replace_all(html, |captures: &Captures| {
+        match plain.rewrite_url(&unescape(&captures[2]), from, to) {
+            Some(url) => {
+                let mut attribute = captures[1].to_string();
+                let _ = escape_href(&mut attribute, &url);
+                attribute.push('"');
+                attribute
+            }
+            None => captures[0].to_string(),
+        }
+    });
+
+    return rebased.into_owned();
+}
+
+/// Slugifies every component of `path` but its extension. Components with
+/// nothing to slugify are kept as they are.
+fn slugify_path(path: &Path) -> PathBuf {
+    let mut slugged = PathBuf::new();
+    let mut components = path.components().peekable();
+    while let Some(component) = components.next() {
+        let component = Path::new(component.as_os_str());
+        let is_file = components.peek().is_none();
+        let name = match is_file {
+            true => component.file_stem().unwrap_or_default(),
+            false => component.as_os_str(),
+        };
+        let slug = slugify(&name.to_string_lossy());
+        let slug = if slug.is_empty() { name.to_string_lossy().into_owned() } else { slug };
+
+        match component.extension() {
+            Some(extension) if is_file => slugged.push(format!("{}.{}", slug, extension.to_string_lossy())),
+            _ => slugged.push(slug),
+        }
+    }
+
+    return slugged;
+}
+
+/// Resolves `.` and `..` in `path`, or `None` if it climbs out of the root.
+pub(crate) fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            other => normalized.push(other),
+        }
+    }
+
+    return Some(normalized);
+}
+
+/// The relative URL from directory `base` to `target`, both relative to the
+/// site root. A trailing `/` on `target` is kept.
+fn relative_to(target: &str, base: &Path) -> String {
+    let target_parts: Vec<&str> = target.split('/').collect();
+    let base_parts: Vec<String> = base.components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect();
+
+    // The last part of the target is a file name, or empty for a directory.
+    let directories = &target_parts[..(target_parts.len() - 1)];
+    let common = directories.iter().zip(&base_parts).take_while(|(a, b)| **a == b.as_str()).count();
+
+    let mut relative = "../".repeat(base_parts.len() - common);
+    for part in &target_parts[common..] {
+        relative.push_str(part);
+        relative.push('/');
+    }
+    relative.pop();
+
+    return relative;
+}
+
+/// Undoes the HTML escaping of an attribute value.
+pub(crate) fn unescape(value: &str) -> String {
+    value.replace("&quot;", "\"")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Undoes the `%XX` escaping of a URL path or fragment.
+pub(crate) fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let escaped = match bytes[idx] {
+            b'%' => value.get((idx + 1)..(idx + 3)).and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                idx += 3;
+            }
+            None => {
+                decoded.push(bytes[idx]);
+                idx += 1;
+            }
+        }
+    }
+
+    return String::from_utf8_lossy(&decoded).into_owned();
+}
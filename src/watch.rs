@@ -0,0 +1,115 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::result;
+use std::sync::mpsc;
+
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use orbit_rs::{OrbitError, Page, Site};
+
+use crate::cli::MessageFormat;
+
+type Result<T> = result::Result<T, Box<dyn Error>>;
+
+/// Blocks forever, re-rendering pages as their sources change.
+///
+/// An edited markdown file is rendered on its own (along with the stylesheet);
+/// an edit to the configured template, layouts, partials, shortcodes, or
+/// stylesheet rebuilds the whole site.
+/// Problems are printed in `format`, and `on_rebuild` is called after every
+/// successful rebuild.
+pub fn watch_site<F: Fn()>(mut site: Site, format: MessageFormat, on_rebuild: F) -> Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(site.source(), RecursiveMode::Recursive)?;
+
+    let mut site_files = Vec::new();
+    let config = site.config();
+    let stylesheets = config.stylesheets.iter();
+    for path in [&config.template, &config.layouts, &config.partials, &config.shortcodes, &config.css].into_iter().flatten().chain(stylesheets) {
+        watcher.watch(path, RecursiveMode::Recursive)?;
+        site_files.push(path.canonicalize()?);
+    }
+
+    tracing::info!("watching {} for changes", site.source().display());
+    for event in rx {
+        let event = match event {
+            Ok(event) => event,
+            Err(err) => {
+                tracing::warn!("watch error: {}", err);
+                continue;
+            }
+        };
+
+        if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+            continue;
+        }
+
+        for path in &event.paths {
+            match rebuild_path(path, &mut site, &site_files, format) {
+                Ok(true) => on_rebuild(),
+                Ok(false) => {}
+                Err(err) => match err.downcast::<OrbitError>() {
+                    Ok(err) => tracing::error!("failed to rebuild {}: {}", path.display(), crate::report_error(*err, format, false)),
+                    Err(err) => tracing::error!("failed to rebuild {}: {}", path.display(), err),
+                },
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+/// Rebuilds whatever depends on `path`, returning whether anything was written.
+fn rebuild_path(path: &Path, site: &mut Site, site_files: &[PathBuf], format: MessageFormat) -> Result<bool> {
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    if site_files.iter().any(|site_file| canonical.starts_with(site_file)) {
+        // The template is compiled when the site is created, so pick up the
+        // edit by starting over.
+        *site = Site::new(site.config().clone())?;
+        for warning in site.build(false)?.warnings {
+            crate::report_warning(&warning, format);
+        }
+        tracing::info!("rebuilt site");
+        return Ok(true);
+    }
+
+    if !path.is_file() {
+        return Ok(false);
+    }
+
+    let relative = match relative_path(path, site.source()) {
+        Some(relative) => relative,
+        None => return Ok(false),
+    };
+    if is_hidden(&relative) || site.is_ignored(&relative, false) {
+        return Ok(false);
+    }
+
+    let is_markdown = path.file_name().map(orbit_rs::is_markdown).unwrap_or(false);
+    if is_markdown {
+        for warning in site.write_page(&Page { path: path.to_path_buf(), relative })? {
+            crate::report_warning(&warning, format);
+        }
+        site.write_css()?;
+    } else {
+        site.copy_asset(path, &relative)?;
+    }
+    tracing::info!("rebuilt {}", path.display());
+
+    return Ok(true);
+}
+
+/// `path` relative to `source`, or `None` if it lies outside of it.
+fn relative_path(path: &Path, source: &Path) -> Option<PathBuf> {
+    let source = source.canonicalize().ok()?;
+    let path = path.canonicalize().ok()?;
+    let relative = path.strip_prefix(&source).ok()?;
+
+    return Some(relative.to_path_buf());
+}
+
+fn is_hidden(relative: &Path) -> bool {
+    relative.components()
+        .any(|c| c.as_os_str().to_string_lossy().starts_with('.'))
+}
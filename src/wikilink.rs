@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use pulldown_cmark::escape::{escape_href, escape_html};
+use pulldown_cmark::{CowStr, Event, Tag};
+use regex::{Captures, Regex};
+
+use crate::site::Page;
+use crate::slug::slugify;
+
+lazy_static::lazy_static! {
+    static ref WIKILINK: Regex = Regex::new(r"\[\[([^\[\]|#]+)(#[^\[\]|]*)?(?:\|([^\[\]]+))?\]\]").unwrap();
+}
+
+/// Every note in a site, keyed by slug, so `[[Note Name]]` can be resolved
+/// to the page rendered from `note-name.md` wherever it lives in the tree.
+#[derive(Debug, Default, Clone)]
+pub struct NoteIndex {
+    notes: HashMap<String, PathBuf>,
+    /// The source of each note, relative to the source directory, keyed by
+    /// its output path.
+    sources: HashMap<PathBuf, PathBuf>,
+    /// Every other file, keyed by its lowercased path from the source
+    /// directory and by its lowercased file name, for `![[image.png]]`.
+    files: HashMap<String, PathBuf>,
+}
+
+impl NoteIndex {
+    /// Indexes `pages` both by file name and by their path from the source
+    /// directory, so `[[Note]]` and `[[folder/Note]]` both work. When two notes
+    /// share a file name, the one nearest the root wins.
+    pub fn new(pages: &[Page]) -> NoteIndex {
+        let mut pages: Vec<&Page> = pages.iter().collect();
+        pages.sort_by_key(|page| (page.relative.components().count(), page.relative.clone()));
+
+        let mut notes = HashMap::new();
+        let mut sources = HashMap::new();
+        for page in pages {
+            let output = page.output_path();
+            sources.insert(output.clone(), page.relative.clone());
+            let full = slugify(&page.relative.with_extension("").to_string_lossy());
+            notes.insert(full, output.clone());
+            if let Some(stem) = page.relative.file_stem() {
+                notes.entry(slugify(&stem.to_string_lossy())).or_insert(output);
+            }
+        }
+
+        return NoteIndex { notes, sources, files: HashMap::new() };
+    }
+
+    /// Indexes the site's other files, given relative to the source
+    /// directory, so they can be embedded. When two share a file name, the
+    /// one nearest the root wins.
+    pub fn index_files(&mut self, files: &[PathBuf]) {
+        let mut files: Vec<&PathBuf> = files.iter().collect();
+        files.sort_by_key(|file| (file.components().count(), file.to_path_buf()));
+        for file in files {
+            self.files.insert(file.to_string_lossy().to_lowercase(), file.clone());
+            if let Some(name) = file.file_name() {
+                self.files.entry(name.to_string_lossy().to_lowercase()).or_insert_with(|| file.clone());
+            }
+        }
+    }
+
+    /// The output path, relative to the site root, of the note `target` names.
+    pub fn resolve(&self, target: &str) -> Option<&Path> {
+        self.notes.get(&slugify(target)).map(PathBuf::as_path)
+    }
+
+    /// The file other than a note that `target` names, relative to the
+    /// source directory.
+    pub fn resolve_file(&self, target: &str) -> Option<&Path> {
+        self.files.get(&target.to_lowercase()).map(PathBuf::as_path)
+    }
+
+    /// The source, relative to the source directory, of the note rendered
+    /// to `output`.
+    pub fn source(&self, output: &Path) -> Option<&Path> {
+        self.sources.get(output).map(PathBuf::as_path)
+    }
+
+    /// Every indexed output path and file, for hashing into the build
+    /// manifest.
+    pub fn outputs(&self) -> Vec<&Path> {
+        let mut outputs: Vec<&Path> = self.notes.values().chain(self.files.values()).map(PathBuf::as_path).collect();
+        outputs.sort();
+        outputs.dedup();
+
+        return outputs;
+    }
+}
+
+/// Replaces `[[Target]]`, `[[Target|label]]` and `[[Target#Heading]]` in the
+/// text of `events` with links to the matching notes. `root` is the relative
+/// path from the page being rendered back to the site root. Targets that
+/// don't exist are rendered as `span.wikilink.missing` instead of a link.
+pub fn resolve_wikilinks<'a>(events: Vec<Event<'a>>, notes: &NoteIndex, root: &str) -> Vec<Event<'a>> {
+    // The parser splits text at every `[`, so adjacent text is joined back up
+    // before looking for links.
+    let mut merged: Vec<Event<'a>> = Vec::with_capacity(events.len());
+    let mut code_depth = 0;
+    for event in events {
+        match &event {
+            Event::Start(Tag::CodeBlock(_)) => code_depth += 1,
+            Event::End(Tag::CodeBlock(_)) => code_depth -= 1,
+            _ => {}
+        }
+
+        if let (Event::Text(text), Some(Event::Text(previous))) = (&event, merged.last_mut()) {
+            if code_depth == 0 {
+                *previous = CowStr::from(format!("{}{}", previous, text));
+                continue;
+            }
+        }
+        merged.push(event);
+    }
+
+    let mut output = Vec::with_capacity(merged.len());
+    for event in merged {
+        match &event {
+            Event::Start(Tag::CodeBlock(_)) => code_depth += 1,
+            Event::End(Tag::CodeBlock(_)) => code_depth -= 1,
+            _ => {}
+        }
+
+        let text = match &event {
+            Event::Text(text) if code_depth == 0 && text.contains("[[") => text.to_string(),
+            _ => {
+                output.push(event);
+                continue;
+            }
+        };
+
+        let mut last = 0;
+        for captures in WIKILINK.captures_iter(&text) {
+            let whole = captures.get(0).unwrap();
+            // A `!` right before the link makes it an embed.
+            let embed = whole.start() > last && text[..whole.start()].ends_with('!');
+            let text_end = if embed { whole.start() - 1 } else { whole.start() };
+            if text_end > last {
+                output.push(Event::Text(text[last..text_end].to_string().into()));
+            }
+
+            let target = captures[1].trim();
+            let heading = captures.get(2).map(|m| &m.as_str()[1..]).unwrap_or("");
+            let label = captures.get(3).map(|m| m.as_str().trim());
+            let html = match embed {
+                true => embed_html(notes, root, target, heading, label),
+                false => link_html(notes, root, target, heading, label.unwrap_or(target)),
+            };
+            output.push(Event::Html(html.into()));
+            last = whole.end();
+        }
+        if last < text.len() {
+            output.push(Event::Text(text[last..].to_string().into()));
+        }
+    }
+
+    return output;
+}
+
+/// The targets of every wikilink in `text`, without headings or labels.
+pub fn targets(text: &str) -> impl Iterator<Item = &str> {
+    WIKILINK.captures_iter(text).map(|captures| captures.get(1).unwrap().as_str().trim())
+}
+
+/// The targets of every `![[embed]]` in `text`, without headings or labels.
+pub fn embeds(text: &str) -> impl Iterator<Item = &str> {
+    WIKILINK.captures_iter(text)
+        .filter(|captures| text[..captures.get(0).unwrap().start()].ends_with('!'))
+        .map(|captures| captures.get(1).unwrap().as_str().trim())
+}
+
+/// `text` with every wikilink replaced by the label it displays.
+pub fn strip_wikilinks(text: &str) -> String {
+    let stripped = WIKILINK.replace_all(text, |captures: &Captures| {
+        captures.get(3).map_or(captures[1].trim(), |label| label.as_str().trim()).to_string()
+    });
+
+    return stripped.into_owned();
+}
+
+/// Opens the placeholder for a note to be embedded once rendered, which
+/// holds the note's output path and a link to it to fall back on.
+pub const EMBED_START: &str = "<div class=\"embed\" data-embed=\"";
+
+/// Image formats embedded as `<img>` rather than linked to.
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp", "svg", "avif", "bmp"];
+
+/// Embeds the note `target` names with a placeholder for the site to fill
+/// in, or an image. Other files are linked to. A numeric `label` on an image
+/// is its width, as in Obsidian.
+fn embed_html(notes: &NoteIndex, root: &str, target: &str, heading: &str, label: Option<&str>) -> String {
+    let mut html = String::new();
+    if let Some(output) = notes.resolve(target) {
+        html.push_str(EMBED_START);
+        let _ = escape_html(&mut html, &output.to_string_lossy());
+        if !heading.is_empty() {
+            html.push('#');
+            let _ = escape_html(&mut html, &slugify(heading));
+        }
+        html.push_str("\">");
+        html.push_str(&link_html(notes, root, target, heading, label.unwrap_or(target)));
+        html.push_str("</div>");
+        return html;
+    }
+
+    let file = match notes.resolve_file(target) {
+        Some(file) => file,
+        None => return link_html(notes, root, target, heading, label.unwrap_or(target)),
+    };
+    let is_image = file.extension()
+        .is_some_and(|extension| IMAGE_EXTENSIONS.contains(&extension.to_string_lossy().to_lowercase().as_str()));
+    if !is_image {
+        html.push_str("<a href=\"");
+        let _ = escape_href(&mut html, root);
+        let _ = escape_href(&mut html, &file.to_string_lossy());
+        html.push_str("\">");
+        let _ = escape_html(&mut html, label.unwrap_or(target));
+        html.push_str("</a>");
+        return html;
+    }
+
+    html.push_str("<img src=\"");
+    let _ = escape_href(&mut html, root);
+    let _ = escape_href(&mut html, &file.to_string_lossy());
+    html.push_str("\" alt=\"");
+    match label {
+        Some(width) if width.parse::<u32>().is_ok() => {
+            html.push_str(&format!("\" width=\"{}", width));
+        }
+        Some(alt) => {
+            let _ = escape_html(&mut html, alt);
+        }
+        None => {}
+    }
+    html.push_str("\" loading=\"lazy\" />");
+
+    return html;
+}
+
+fn link_html(notes: &NoteIndex, root: &str, target: &str, heading: &str, label: &str) -> String {
+    let mut html = String::new();
+    match notes.resolve(target) {
+        Some(output) => {
+            html.push_str("<a class=\"wikilink\" href=\"");
+            let _ = escape_href(&mut html, root);
+            let _ = escape_href(&mut html, &output.to_string_lossy());
+            if !heading.is_empty() {
+                html.push('#');
+                let _ = escape_href(&mut html, &slugify(heading));
+            }
+            html.push_str("\">");
+            let _ = escape_html(&mut html, label);
+            html.push_str("</a>");
+        }
+        None => {
+            html.push_str("<span class=\"wikilink missing\">");
+            let _ = escape_html(&mut html, label);
+            html.push_str("</span>");
+        }
+    }
+
+    return html;
+}
@@ -0,0 +1,84 @@
+use crate::error::{OrbitError, Result};
+
+/// A zip archive of uncompressed entries, which is all an EPUB or an Anki
+/// package needs. Without Zip64 it holds at most 65,535 entries and 4 GiB.
+#[derive(Default)]
+pub struct Archive {
+    bytes: Vec<u8>,
+    directory: Vec<u8>,
+    entries: u16,
+}
+
+/// Zip's version 2.0, the first to have directories.
+const ZIP_VERSION: u16 = 20;
+/// Marks entry names as UTF-8.
+const ZIP_UTF8: u16 = 1 << 11;
+/// 1980-01-01 in MS-DOS form, the earliest a zip can hold, so the same
+/// contents always make the same bytes.
+const ZIP_DATE: u16 = (1 << 5) | 1;
+
+impl Archive {
+    /// Appends a file called `name` holding `contents`.
+    pub fn add(&mut self, name: &str, contents: &[u8]) -> Result<()> {
+        let too_large = || OrbitError::ZipTooLarge { name: name.to_string() };
+        let crc = crc32fast::hash(contents);
+        let offset = u32::try_from(self.bytes.len()).map_err(|_| too_large())?;
+        let size = u32::try_from(contents.len()).map_err(|_| too_large())?;
+        let name_len = u16::try_from(name.len()).map_err(|_| too_large())?;
+        self.entries = self.entries.checked_add(1).ok_or_else(too_large)?;
+
+        push_u32(&mut self.bytes, 0x04034b50);
+        for field in [ZIP_VERSION, ZIP_UTF8, 0, 0, ZIP_DATE] {
+            push_u16(&mut self.bytes, field);
+        }
+        for field in [crc, size, size] {
+            push_u32(&mut self.bytes, field);
+        }
+        push_u16(&mut self.bytes, name_len);
+        push_u16(&mut self.bytes, 0);
+        self.bytes.extend_from_slice(name.as_bytes());
+        self.bytes.extend_from_slice(contents);
+
+        push_u32(&mut self.directory, 0x02014b50);
+        for field in [ZIP_VERSION, ZIP_VERSION, ZIP_UTF8, 0, 0, ZIP_DATE] {
+            push_u16(&mut self.directory, field);
+        }
+        for field in [crc, size, size] {
+            push_u32(&mut self.directory, field);
+        }
+        for field in [name_len, 0, 0, 0, 0] {
+            push_u16(&mut self.directory, field);
+        }
+        push_u32(&mut self.directory, 0);
+        push_u32(&mut self.directory, offset);
+        self.directory.extend_from_slice(name.as_bytes());
+
+        return Ok(());
+    }
+
+    /// The finished archive, with its central directory.
+    pub fn finish(mut self) -> Result<Vec<u8>> {
+        let too_large = || OrbitError::ZipTooLarge { name: String::from("the central directory") };
+        let offset = u32::try_from(self.bytes.len()).map_err(|_| too_large())?;
+        let size = u32::try_from(self.directory.len()).map_err(|_| too_large())?;
+        self.bytes.append(&mut self.directory);
+
+        push_u32(&mut self.bytes, 0x06054b50);
+        for field in [0, 0, self.entries, self.entries] {
+            push_u16(&mut self.bytes, field);
+        }
+        push_u32(&mut self.bytes, size);
+        push_u32(&mut self.bytes, offset);
+        push_u16(&mut self.bytes, 0);
+
+        return Ok(self.bytes);
+    }
+}
+
+fn push_u16(bytes: &mut Vec<u8>, value: u16) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}